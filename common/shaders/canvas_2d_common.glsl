@@ -0,0 +1,267 @@
+// Definitions shared by the canvas compute passes:
+// 1) canvas_2d_bbox.comp computes the bounding box of each path
+// 2) canvas_2d_coarse.comp bins the paths into the tiles they overlap
+// 3) canvas_2d.comp rasterizes each tile, only processing the paths binned into it
+//
+// Blurred layers are rasterized by canvas_2d.comp into layers of their own first, and blurred
+// by canvas_2d_blur.comp, before the window is rasterized
+//
+// Each command's params are pairs of 32 bit values. Points, sizes, radii, widths, angles and
+// offsets are stored as f32 bits, everything else as uints
+//
+// Command types:
+// 1) Start fill contour:
+//    opcode = 0
+//    param1 = starting point
+//    param2.x = color, if solid paint
+//    param2.y = blend mode
+//    param3.x = fill rule
+//    param3.y = paint type
+//
+// 2) Start stroke contour:
+//    opcode = 1
+//    param1 = starting point
+//    param2.x = color, if solid paint
+//    param2.y = blend mode
+//    param3.x = width
+//    param3.y = paint type
+//
+//    Always followed by stroke style data commands, before any paint data commands
+//
+// 3) Start clip contour:
+//    opcode = 2
+//    param1 = starting point
+//    param3.x = fill rule
+//
+// 4) Move to:
+//    opcode = 3
+//    param1 = starting point of the new subpath
+//
+// 5) Line to:
+//    opcode = 4
+//    param1 = point to draw line to
+//
+// 6) Quadratic bezier to:
+//    opcode = 5
+//    param1 = control point
+//    param2 = point to draw curve to
+//
+// 7) Cubic bezier to:
+//    opcode = 6
+//    param1 = first control point
+//    param2 = second control point
+//    param3 = point to draw curve to
+//
+// 8) Arc to:
+//    opcode = 7
+//    param1 = corner point
+//    param2 = point defining the second tangent line
+//    param3.x = radius
+//
+// 9) Circle:
+//    opcode = 8
+//    param1 = center
+//    param2.x = radius
+//
+// 10) Ellipse:
+//    opcode = 9
+//    param1 = center
+//    param2 = radii
+//
+// 11) Rounded rectangle:
+//    opcode = 10
+//    param1 = top left corner
+//    param2 = size
+//    param3.x = corner radius
+//
+// 12) Data:
+//    opcode = 11
+//    Follows contour starts with stroke styles or non solid paints, ignored while processing commands
+//
+//    Stroke style header:
+//    param1.x = line join
+//    param1.y = line cap
+//    param2.x = miter limit
+//    param2.y = dash offset, within the dash pattern
+//    param3.x = number of dash pairs, 0 for a solid stroke
+//    param3.y = total length of the dash pattern
+//
+//    Followed by one command per dash pair:
+//    param1 = (dash length, gap length)
+//
+//    Gradient header:
+//    param1 = start point (linear), center (radial, sweep)
+//    param2 = end point (linear), param2.x = radius (radial), start angle in radians (sweep)
+//    param3.x = extend mode
+//    param3.y = number of stops
+//
+//    Followed by one command per gradient stop:
+//    param1.x = offset
+//    param2.x = color
+//
+//    Image header:
+//    param1.x = texture index
+//    param1.y = sampler index
+//
+//    Followed by a command holding the canvas to texture pixel transform, with each
+//    param holding a column of the matrix
+//
+// 13) End contour:
+//    opcode = 12
+//
+// 14) Pop clip:
+//    opcode = 13
+//
+// 15) Set transform:
+//    opcode = 14
+//    param1, param2, param3 = columns of the window to canvas transform matrix
+//
+//    Applies to all following contours, an all zero transform means nothing is drawn
+//
+// 16) Push layer:
+//    opcode = 15
+//    param1.x = index of the blurred layer, NO_BLUR_LAYER if the layer isn't blurred
+//    param1.y = index of the matching pop layer path, if blurred
+//
+//    Following contours are composited into a transparent layer instead of the pixel color
+//
+// 17) Pop layer:
+//    opcode = 16
+//    param1.x = opacity
+//    param1.y = blend mode
+//    param2.x = index of the blurred layer, NO_BLUR_LAYER if the layer isn't blurred
+//
+//    Composites the innermost layer onto the pixel color from before it was pushed
+//
+// 18) Box shadow:
+//    opcode = 17
+//    param1 = top left corner of the shadow
+//    param2 = size
+//    param3.x = corner radius
+//    param3.y = standard deviation of the blur
+//
+//    Followed by a data command:
+//    param1.x = color
+//    param1.y = blend mode
+//
+// 19) Last command:
+//    opcode = 18
+#define OP_START_FILL 0
+#define OP_START_STROKE 1
+#define OP_START_CLIP 2
+#define OP_MOVE_TO 3
+#define OP_LINE_TO 4
+#define OP_QUAD_TO 5
+#define OP_CUBIC_TO 6
+#define OP_ARC_TO 7
+#define OP_CIRCLE 8
+#define OP_ELLIPSE 9
+#define OP_ROUNDED_RECT 10
+#define OP_DATA 11
+#define OP_END_CONT 12
+#define OP_POP_CLIP 13
+#define OP_SET_TRANSFORM 14
+#define OP_PUSH_LAYER 15
+#define OP_POP_LAYER 16
+#define OP_BOX_SHADOW 17
+#define OP_LAST_CMD 18
+
+#define JOIN_MITER 0
+#define JOIN_ROUND 1
+#define JOIN_BEVEL 2
+
+#define CAP_BUTT 0
+#define CAP_ROUND 1
+#define CAP_SQUARE 2
+
+struct CanvasCommand {
+    uint opcode;
+    uvec2 param1;
+    uvec2 param2;
+    uvec2 param3;
+};
+
+// A contour, clip contour, clip pop, layer push, layer pop or box shadow in the command list, in the order they were recorded
+struct PathInfo {
+    uint cmdIdx;
+    uint transformIdx;
+};
+
+#define NO_TRANSFORM 0xFFFFFFFFu
+
+// Marks layers that aren't blurred, and as the target layer, rasterizing the window
+#define NO_BLUR_LAYER 0xFFFFFFFFu
+
+// Size of the square tiles paths are binned into, must match TILE_SIZE in renderer.rs
+#define TILE_SIZE 16
+
+// List of canvas draw commands
+layout(set = 0, binding = 0, std430) readonly buffer CanvasCmdList {
+    CanvasCommand cmds[];
+} cmdList;
+
+// List of paths, pointing into the command list
+layout(set = 0, binding = 1, std430) readonly buffer CanvasPathList {
+    PathInfo paths[];
+} pathList;
+
+// Window space bounding box of each path, as (min, max)
+layout(set = 0, binding = 2, std430) buffer PathBBoxes {
+    vec4 bboxes[];
+} pathBBoxes;
+
+// For each tile, a bitmask of the paths binned into it, maskWords long
+layout(set = 0, binding = 3, std430) buffer TileMasks {
+    uint masks[];
+} tileMasks;
+
+// Blurred layers, with blurred layer i in array layer i + 1, and array layer 0 holding the
+// result of the horizontal blur pass. Sized to cover every tile
+layout(set = 0, binding = 4, rgba16f) uniform image2DArray layerImages;
+
+layout(push_constant) uniform PushConstants {
+    uvec2 numTiles;
+    
+    // First tile of the rectangle of tiles the raster and blur passes are dispatched over, only
+    // tiles with damage are redrawn
+    uvec2 tileOffset;
+    uint numPaths;
+    uint maskWords;
+    
+    // Range of paths rasterized, and the blurred layer they're rasterized into, or NO_BLUR_LAYER
+    // for the window. The blur passes blur the target layer
+    uint firstPath;
+    uint endPath;
+    uint targetLayer;
+    
+    // Standard deviation of the blur in pixels, and whether this is the vertical blur pass
+    float blurStdDev;
+    uint blurVertical;
+    
+    // Color the window or render target starts out as, packed like command colors
+    uint clearColor;
+    
+    // How the edges of fills are antialiased, one of the AA_ modes in canvas_2d.comp
+    uint antialiasing;
+} pc;
+
+// Unpacks an affine transform stored as the columns of a matrix in a command
+mat3x2 unpackTransform(CanvasCommand cmd) {
+    return mat3x2(uintBitsToFloat(cmd.param1), uintBitsToFloat(cmd.param2), uintBitsToFloat(cmd.param3));
+}
+
+// The window to canvas transform in effect for a path
+mat3x2 pathTransform(PathInfo path) {
+    if(path.transformIdx == NO_TRANSFORM) {
+        return mat3x2(1.0);
+    }
+    
+    return unpackTransform(cmdList.cmds[path.transformIdx]);
+}
+
+// Average scale from canvas space to pixels of a window to canvas transform, 0 if it's degenerate
+float transformScale(mat3x2 transform) {
+    float det = abs(determinant(mat2(transform)));
+    
+    return det == 0.0 ? 0.0 : inversesqrt(det);
+}
\ No newline at end of file
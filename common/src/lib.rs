@@ -2,6 +2,7 @@
 //! Also re-exports common dependencies
 
 pub use anyhow;
+pub use vek;
 
 pub mod window;
 pub mod renderer;
\ No newline at end of file
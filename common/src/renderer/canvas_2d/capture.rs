@@ -0,0 +1,475 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Result, Context};
+use vek::{Vec2, Rgba};
+
+use super::recorder::{
+    NO_TRANSFORM, MAX_BLUR_LAYERS, MAX_CLIP_DEPTH,
+    CanvasCommand, CanvasOp, PaintType, PathInfo, CanvasRecording, BlurLayer, pack_color
+};
+use super::renderer::{MAX_TEXTURES, PLACEHOLDER_TEXTURE};
+use super::paint::TextureId;
+use super::transform::Transform;
+
+/// Identifies canvas capture files
+const CAPTURE_MAGIC: [u8; 8] = *b"N3DCANVS";
+
+/// Version of the capture format, bumped whenever the layout of captures or the meaning of
+/// commands changes, captures of other versions aren't loaded
+const CAPTURE_VERSION: u32 = 1;
+
+/// A frame's canvas recording, along with the textures its image paints sample, that can be
+/// saved to disk and replayed later, e.g. to attach a broken frame to a bug report or keep it
+/// around as a regression fixture
+///
+/// Captures are taken with [`Renderer::capture_canvas_frame()`](crate::renderer::Renderer::capture_canvas_frame),
+/// and replayed with [`Renderer::replay_canvas_capture()`](crate::renderer::Renderer::replay_canvas_capture)
+/// or [`CpuRasterizer::replay()`](super::CpuRasterizer::replay)
+///
+/// Files are laid out as follows, with every value a little endian u32 unless stated otherwise:
+/// 1) The magic bytes `N3DCANVS` and the format version
+/// 2) Frame width, height and clear color, packed like command colors
+/// 3) Number of commands, then each command's opcode and params
+/// 4) Number of paths, then each path's command index and transform command index
+/// 5) Number of transforms, then each transform's `a` to `f` as f32s
+/// 6) Number of blurred layers, then each layer's first path, end path and standard deviation as an f32
+/// 7) Number of textures, then each texture's ID, width and height, followed by its sRGB
+///    encoded RGBA8 pixels with straight alpha
+///
+/// Loading only checks that a capture is well formed, not that every command's params make
+/// sense, so only captures from trusted sources should be replayed
+pub struct CanvasCapture {
+    pub(super) recording: CanvasRecording,
+    width: u32,
+    height: u32,
+    clear_color: Rgba<u8>,
+    textures: Vec<CapturedTexture>
+}
+
+/// A texture sampled by a captured frame
+pub struct CapturedTexture {
+    /// ID the texture had in the captured frame, which its image paints refer to
+    pub id: TextureId,
+    pub width: u32,
+    pub height: u32,
+
+    /// sRGB encoded RGBA8 pixels with straight alpha and tightly packed rows
+    pub pixels: Vec<u8>
+}
+
+/// Reads little endian values from a capture, failing if it ends early
+struct CaptureReader<'a> {
+    data: &'a [u8]
+}
+
+impl CanvasCapture {
+    /// Captures a finished recording of a `width` by `height` frame, textures are added with
+    /// [`add_texture()`](Self::add_texture)
+    pub(super) fn new(recording: CanvasRecording, width: u32, height: u32, clear_color: Rgba<u8>) -> Self {
+        Self {
+            recording,
+            width,
+            height,
+            clear_color,
+            textures: vec![]
+        }
+    }
+
+    /// Loads a capture saved with [`save()`](Self::save)
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path).with_context(|| format!("Failed to read canvas capture {}", path.display()))?;
+
+        Self::from_bytes(&data).with_context(|| format!("Failed to load canvas capture {}", path.display()))
+    }
+
+    /// Loads a capture from the bytes written by [`to_bytes()`](Self::to_bytes)
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = CaptureReader { data };
+
+        if reader.bytes(CAPTURE_MAGIC.len())? != CAPTURE_MAGIC {
+            bail!("Not a canvas capture");
+        }
+
+        let version = reader.u32()?;
+
+        if version != CAPTURE_VERSION {
+            bail!("Canvas capture is version {version}, only version {CAPTURE_VERSION} is supported");
+        }
+
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let clear_color = Rgba::from(reader.u32()?.to_le_bytes());
+
+        let mut recording = CanvasRecording::default();
+
+        for _ in 0..reader.count(7 * 4)? {
+            let opcode = reader.u32()?;
+            let opcode = CanvasOp::from_u32(opcode).with_context(|| format!("Invalid canvas opcode {opcode}"))?;
+            let mut param = || -> Result<Vec2<u32>> { Ok(Vec2::new(reader.u32()?, reader.u32()?)) };
+
+            recording.cmds.push(CanvasCommand::new(opcode, param()?, param()?, param()?));
+        }
+
+        for _ in 0..reader.count(2 * 4)? {
+            recording.paths.push(PathInfo {
+                cmd_idx: reader.u32()?,
+                transform_idx: reader.u32()?
+            });
+        }
+
+        for _ in 0..reader.count(6 * 4)? {
+            let mut coefficient = || reader.f32();
+
+            recording.transforms.push(Transform::new(
+                coefficient()?,
+                coefficient()?,
+                coefficient()?,
+                coefficient()?,
+                coefficient()?,
+                coefficient()?
+            ));
+        }
+
+        for _ in 0..reader.count(3 * 4)? {
+            recording.blur_layers.push(BlurLayer {
+                paths: reader.u32()?..reader.u32()?,
+                std_dev: reader.f32()?
+            });
+        }
+
+        let mut textures = vec![];
+
+        for _ in 0..reader.count(3 * 4)? {
+            let id = reader.u32()?;
+            let width = reader.u32()?;
+            let height = reader.u32()?;
+
+            if id >= MAX_TEXTURES {
+                bail!("Canvas capture texture ID {id} is past the texture limit of {MAX_TEXTURES}");
+            }
+
+            let len = width as usize * height as usize * 4;
+
+            if len == 0 {
+                bail!("Canvas capture texture {id} is empty");
+            }
+
+            textures.push(CapturedTexture {
+                id: TextureId(id as u16),
+                width,
+                height,
+                pixels: reader.bytes(len)?.to_vec()
+            });
+        }
+
+        if !reader.data.is_empty() {
+            bail!("Canvas capture has {} bytes of trailing data", reader.data.len());
+        }
+
+        validate_recording(&recording)?;
+
+        Ok(Self {
+            recording,
+            width,
+            height,
+            clear_color,
+            textures
+        })
+    }
+
+    /// Saves the capture to a file, overwriting it if it exists
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        fs::write(path, self.to_bytes()).with_context(|| format!("Failed to write canvas capture {}", path.display()))
+    }
+
+    /// Encodes the capture in the capture format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let recording = &self.recording;
+        let mut data = CAPTURE_MAGIC.to_vec();
+
+        for value in [CAPTURE_VERSION, self.width, self.height, pack_color(self.clear_color)] {
+            push_u32(&mut data, value);
+        }
+
+        push_u32(&mut data, recording.cmds.len() as u32);
+
+        for cmd in &recording.cmds {
+            push_u32(&mut data, cmd.opcode as u32);
+
+            for param in [cmd.param1, cmd.param2, cmd.param3] {
+                push_u32(&mut data, param.x);
+                push_u32(&mut data, param.y);
+            }
+        }
+
+        push_u32(&mut data, recording.paths.len() as u32);
+
+        for path in &recording.paths {
+            push_u32(&mut data, path.cmd_idx);
+            push_u32(&mut data, path.transform_idx);
+        }
+
+        push_u32(&mut data, recording.transforms.len() as u32);
+
+        for t in &recording.transforms {
+            for coefficient in [t.a, t.b, t.c, t.d, t.e, t.f] {
+                push_u32(&mut data, coefficient.to_bits());
+            }
+        }
+
+        push_u32(&mut data, recording.blur_layers.len() as u32);
+
+        for blur_layer in &recording.blur_layers {
+            push_u32(&mut data, blur_layer.paths.start);
+            push_u32(&mut data, blur_layer.paths.end);
+            push_u32(&mut data, blur_layer.std_dev.to_bits());
+        }
+
+        push_u32(&mut data, self.textures.len() as u32);
+
+        for texture in &self.textures {
+            push_u32(&mut data, texture.id.0 as u32);
+            push_u32(&mut data, texture.width);
+            push_u32(&mut data, texture.height);
+            data.extend_from_slice(&texture.pixels);
+        }
+
+        data
+    }
+
+    /// Width of the captured frame in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the captured frame in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// sRGB encoded color the captured frame started out as
+    pub fn clear_color(&self) -> Rgba<u8> {
+        self.clear_color
+    }
+
+    /// Textures sampled by the captured frame
+    pub fn textures(&self) -> &[CapturedTexture] {
+        &self.textures
+    }
+
+    /// IDs of the textures the recording's image paints sample, each listed once
+    pub(super) fn sampled_textures(&self) -> Vec<TextureId> {
+        let mut texture_ids = image_headers(&self.recording)
+            .into_iter()
+            .map(|header_idx| TextureId(self.recording.cmds[header_idx].param1.x as u16))
+            .collect::<Vec<_>>();
+
+        texture_ids.sort_by_key(|texture_id| texture_id.0);
+        texture_ids.dedup();
+        texture_ids
+    }
+
+    /// Adds a texture sampled by the captured frame
+    pub(super) fn add_texture(&mut self, id: TextureId, width: u32, height: u32, pixels: Vec<u8>) {
+        self.textures.push(CapturedTexture { id, width, height, pixels });
+    }
+
+    /// Copy of the recording for replaying with the capture's textures registered again, `remap`
+    /// pairing the ID each texture had in the capture with the ID it was registered under
+    ///
+    /// Image paints sampling textures missing from the capture sample [`PLACEHOLDER_TEXTURE`]
+    /// instead, which is never registered so they can't end up sampling an unrelated texture
+    pub(super) fn replay_recording(&self, remap: &[(TextureId, TextureId)]) -> CanvasRecording {
+        let mut recording = self.recording.clone();
+
+        for header_idx in image_headers(&self.recording) {
+            let texture_idx = &mut recording.cmds[header_idx].param1.x;
+
+            let texture_id = remap
+                .iter()
+                .find(|(captured_id, _)| captured_id.0 as u32 == *texture_idx)
+                .map_or(PLACEHOLDER_TEXTURE, |(_, texture_id)| *texture_id);
+
+            *texture_idx = texture_id.0 as u32;
+        }
+
+        recording
+    }
+}
+
+impl<'a> CaptureReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.data.len() {
+            bail!("Canvas capture ends early");
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    /// Reads the number of items in a list, checking there's enough data left for that many items
+    /// of `item_size` bytes before anything is allocated for them
+    fn count(&mut self, item_size: usize) -> Result<usize> {
+        let count = self.u32()? as usize;
+
+        if count.saturating_mul(item_size) > self.data.len() {
+            bail!("Canvas capture ends early");
+        }
+
+        Ok(count)
+    }
+}
+
+fn push_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Checks that every index in a loaded recording is in bounds, and that it's terminated
+fn validate_recording(recording: &CanvasRecording) -> Result<()> {
+    let num_cmds = recording.cmds.len() as u32;
+    let num_paths = recording.paths.len() as u32;
+
+    if !matches!(recording.cmds.last().map(|cmd| cmd.opcode), Some(CanvasOp::LastCommand)) {
+        bail!("Canvas capture's command list isn't terminated");
+    }
+
+    for path in &recording.paths {
+        if path.cmd_idx >= num_cmds || (path.transform_idx != NO_TRANSFORM && path.transform_idx >= num_cmds) {
+            bail!("Canvas capture has a path pointing past the end of the command list");
+        }
+    }
+
+    match recording.max_clip_depth() {
+        None => bail!("Canvas capture pops a clip that wasn't pushed"),
+        Some(depth) if depth > MAX_CLIP_DEPTH => bail!("Canvas capture has clips nested more than {MAX_CLIP_DEPTH} deep"),
+        _ => ()
+    }
+
+    if recording.blur_layers.len() > MAX_BLUR_LAYERS {
+        bail!("Canvas capture has more than {MAX_BLUR_LAYERS} blurred layers");
+    }
+
+    for blur_layer in &recording.blur_layers {
+        if blur_layer.paths.start > blur_layer.paths.end || blur_layer.paths.end > num_paths {
+            bail!("Canvas capture has a blurred layer with an invalid path range");
+        }
+    }
+
+    for header_idx in image_headers(recording) {
+        let texture_idx = recording.cmds[header_idx].param1.x;
+
+        if texture_idx >= MAX_TEXTURES {
+            bail!("Canvas capture samples texture {texture_idx}, past the texture limit of {MAX_TEXTURES}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Indices of the image paint header commands of every contour with an image paint
+///
+/// Fill contours are followed by their paint's data commands, and stroke contours by their
+/// stroke style header and dash pairs, then their paint's
+fn image_headers(recording: &CanvasRecording) -> Vec<usize> {
+    let cmds = &recording.cmds;
+
+    recording.paths
+        .iter()
+        .filter_map(|path| {
+            let cmd_idx = path.cmd_idx as usize;
+            let cmd = cmds.get(cmd_idx)?;
+
+            if cmd.param3.y != PaintType::Image as u32 {
+                return None;
+            }
+
+            let header_idx = match cmd.opcode {
+                CanvasOp::StartFill => cmd_idx + 1,
+                CanvasOp::StartStroke => cmd_idx + 2 + cmds.get(cmd_idx + 1)?.param3.x as usize,
+                _ => return None
+            };
+
+            (header_idx < cmds.len()).then_some(header_idx)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::recorder::{Canvas2DRecorder, FillRule};
+    use super::super::paint::{Paint, FilterMode, ExtendMode};
+    use super::super::cpu_raster::CpuRasterizer;
+
+    #[test]
+    fn missing_textures_never_alias_registered_ones() {
+        let image = Paint::Image {
+            texture: TextureId(0),
+            transform: Transform::IDENTITY,
+            filter: FilterMode::Nearest,
+            tiling: ExtendMode::Repeat
+        };
+
+        let recording = Canvas2DRecorder::new(CanvasRecording::default())
+            .start_fill(Vec2::zero(), image, FillRule::NonZero)
+            .rounded_rect(Vec2::zero(), Vec2::new(4.0, 4.0), 0.0)
+            .end()
+            .end()
+            .unwrap();
+
+        // The captured texture is missing, and every slot is then taken by a red texture
+        let capture = CanvasCapture::new(recording, 4, 4, Rgba::new(0, 0, 0, 255));
+        let mut replayed = CanvasCapture::new(capture.replay_recording(&[]), 4, 4, Rgba::new(0, 0, 0, 255));
+        let mut rasterizer = CpuRasterizer::new();
+
+        while let Ok(texture_id) = rasterizer.register_texture(1, 1, &[255, 0, 0, 255]) {
+            assert_ne!(texture_id, PLACEHOLDER_TEXTURE);
+            replayed.add_texture(texture_id, 1, 1, vec![255, 0, 0, 255]);
+        }
+
+        assert_eq!(replayed.sampled_textures(), [PLACEHOLDER_TEXTURE]);
+
+        // Drawn with the white placeholder rather than any of the red textures
+        let pixels = rasterizer.replay(&replayed);
+        assert!(pixels.chunks_exact(4).all(|pixel| pixel == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn replayed_textures_are_remapped() {
+        let image = |texture| Paint::Image {
+            texture,
+            transform: Transform::IDENTITY,
+            filter: FilterMode::Nearest,
+            tiling: ExtendMode::Repeat
+        };
+
+        let recording = Canvas2DRecorder::new(CanvasRecording::default())
+            .start_fill(Vec2::zero(), image(TextureId(2)), FillRule::NonZero)
+            .circle(Vec2::zero(), 1.0)
+            .end()
+            .start_fill(Vec2::zero(), image(TextureId(5)), FillRule::NonZero)
+            .circle(Vec2::zero(), 1.0)
+            .end()
+            .end()
+            .unwrap();
+
+        let capture = CanvasCapture::new(recording, 4, 4, Rgba::new(0, 0, 0, 255));
+        let replayed = CanvasCapture::new(capture.replay_recording(&[(TextureId(5), TextureId(0))]), 4, 4, Rgba::zero());
+
+        assert_eq!(replayed.sampled_textures(), [TextureId(0), PLACEHOLDER_TEXTURE]);
+    }
+}
\ No newline at end of file
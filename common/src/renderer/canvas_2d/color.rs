@@ -0,0 +1,302 @@
+//! CPU reference implementations of the canvas shader's color handling, so colors produced by
+//! the shader can be checked numerically. These must be kept in sync with canvas_2d.comp
+
+use vek::{Rgba, Rgb};
+
+/// Converts an sRGB encoded color component to linear light, like `srgbToLinear()` in the shader
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c > 0.04045 {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+    else {
+        c / 12.92
+    }
+}
+
+/// Converts a linear light color component to sRGB encoding, like `linearToSrgb()` in the shader
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c > 0.0031308 {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+    else {
+        c * 12.92
+    }
+}
+
+/// Converts an sRGB color with straight alpha to linear light with premultiplied alpha, the space
+/// the shader composites in
+pub fn decode_color(color: Rgba<u8>) -> Rgba<f32> {
+    let color = color.map(|c| c as f32 / 255.0);
+    let alpha = color.a;
+
+    Rgba::new(
+        srgb_to_linear(color.r) * alpha,
+        srgb_to_linear(color.g) * alpha,
+        srgb_to_linear(color.b) * alpha,
+        alpha
+    )
+}
+
+/// Converts a linear light color with premultiplied alpha back to sRGB with straight alpha,
+/// rounding to the nearest value the way the shader's output is stored
+pub fn encode_color(color: Rgba<f32>) -> Rgba<u8> {
+    let straight = if color.a > 0.0 { color / color.a } else { Rgba::zero() };
+    let to_unorm = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    Rgba::new(
+        to_unorm(linear_to_srgb(straight.r.clamp(0.0, 1.0))),
+        to_unorm(linear_to_srgb(straight.g.clamp(0.0, 1.0))),
+        to_unorm(linear_to_srgb(straight.b.clamp(0.0, 1.0))),
+        to_unorm(color.a)
+    )
+}
+
+/// How a contour's paint is combined with what's already been drawn
+///
+/// The Porter-Duff modes decide how much of the paint and what's below it is kept based on
+/// their alphas. The separable modes blend each color component, and composite the result
+/// with source over, as described in the W3C compositing and blending spec. Contours only
+/// affect the pixels they cover, so e.g. [`SrcIn`](BlendMode::SrcIn) doesn't clear anything
+/// outside of the contour
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Paint drawn over what's below it
+    #[default]
+    SrcOver = 0,
+
+    /// Clears what's below
+    Clear = 1,
+
+    /// Paint replaces what's below
+    Src = 2,
+
+    /// Paint is discarded
+    Dst = 3,
+
+    /// Paint drawn under what's already been drawn
+    DstOver = 4,
+
+    /// Paint only kept where something's been drawn, replacing it
+    SrcIn = 5,
+
+    /// What's below only kept where the paint is
+    DstIn = 6,
+
+    /// Paint only kept where nothing's been drawn, clearing everything else
+    SrcOut = 7,
+
+    /// What's below is erased where the paint is
+    DstOut = 8,
+
+    /// Paint only drawn where something's been drawn
+    SrcAtop = 9,
+
+    /// What's below only kept where the paint is, with the paint drawn under it
+    DstAtop = 10,
+
+    /// Paint and what's below are only kept where they don't overlap
+    Xor = 11,
+
+    /// Paint and what's below are added together
+    Plus = 12,
+
+    Multiply = 13,
+    Screen = 14,
+    Overlay = 15,
+    Darken = 16,
+    Lighten = 17,
+    ColorDodge = 18,
+    ColorBurn = 19,
+    HardLight = 20,
+    SoftLight = 21,
+    Difference = 22,
+    Exclusion = 23
+}
+
+impl BlendMode {
+    /// Every blend mode, indexed by the value stored in canvas commands
+    pub(super) const ALL: [BlendMode; 24] = [
+        BlendMode::SrcOver,
+        BlendMode::Clear,
+        BlendMode::Src,
+        BlendMode::Dst,
+        BlendMode::DstOver,
+        BlendMode::SrcIn,
+        BlendMode::DstIn,
+        BlendMode::SrcOut,
+        BlendMode::DstOut,
+        BlendMode::SrcAtop,
+        BlendMode::DstAtop,
+        BlendMode::Xor,
+        BlendMode::Plus,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Darken,
+        BlendMode::Lighten,
+        BlendMode::ColorDodge,
+        BlendMode::ColorBurn,
+        BlendMode::HardLight,
+        BlendMode::SoftLight,
+        BlendMode::Difference,
+        BlendMode::Exclusion
+    ];
+}
+
+/// Blends `src` onto `dst` with a blend mode, both in linear light with premultiplied alpha,
+/// like `blend()` in the shader
+pub fn blend(src: Rgba<f32>, dst: Rgba<f32>, mode: BlendMode) -> Rgba<f32> {
+    // Porter-Duff modes keep a fraction of each color
+    let (src_factor, dst_factor) = match mode {
+        BlendMode::SrcOver => (1.0, 1.0 - src.a),
+        BlendMode::Clear => (0.0, 0.0),
+        BlendMode::Src => (1.0, 0.0),
+        BlendMode::Dst => (0.0, 1.0),
+        BlendMode::DstOver => (1.0 - dst.a, 1.0),
+        BlendMode::SrcIn => (dst.a, 0.0),
+        BlendMode::DstIn => (0.0, src.a),
+        BlendMode::SrcOut => (1.0 - dst.a, 0.0),
+        BlendMode::DstOut => (0.0, 1.0 - src.a),
+        BlendMode::SrcAtop => (dst.a, 1.0 - src.a),
+        BlendMode::DstAtop => (1.0 - dst.a, src.a),
+        BlendMode::Xor => (1.0 - dst.a, 1.0 - src.a),
+        BlendMode::Plus => return (src + dst).map(|c| c.min(1.0)),
+
+        // Separable modes blend the straight colors where both overlap
+        _ => {
+            let cs = if src.a > 0.0 { src.rgb() / src.a } else { Rgb::zero() };
+            let cb = if dst.a > 0.0 { dst.rgb() / dst.a } else { Rgb::zero() };
+
+            let mixed = Rgb::new(
+                blend_component(cb.r, cs.r, mode),
+                blend_component(cb.g, cs.g, mode),
+                blend_component(cb.b, cs.b, mode)
+            );
+
+            let color = src.rgb() * (1.0 - dst.a) + dst.rgb() * (1.0 - src.a) + mixed * (src.a * dst.a);
+
+            return Rgba::from_translucent(color, src.a + dst.a - src.a * dst.a);
+        }
+    };
+
+    src * src_factor + dst * dst_factor
+}
+
+/// Separable blend function for a single straight color component, `cb` being the backdrop
+/// and `cs` the source, like `blendComponents()` in the shader
+fn blend_component(cb: f32, cs: f32, mode: BlendMode) -> f32 {
+    let multiply = |cb: f32, cs: f32| cb * cs;
+    let screen = |cb: f32, cs: f32| cb + cs - cb * cs;
+    let hard_light = |cb: f32, cs: f32| {
+        if cs <= 0.5 { multiply(cb, 2.0 * cs) } else { screen(cb, 2.0 * cs - 1.0) }
+    };
+
+    match mode {
+        BlendMode::Multiply => multiply(cb, cs),
+        BlendMode::Screen => screen(cb, cs),
+        BlendMode::Overlay => hard_light(cs, cb),
+        BlendMode::Darken => cb.min(cs),
+        BlendMode::Lighten => cb.max(cs),
+        BlendMode::ColorDodge => {
+            if cb == 0.0 { 0.0 } else if cs >= 1.0 { 1.0 } else { (cb / (1.0 - cs)).min(1.0) }
+        },
+        BlendMode::ColorBurn => {
+            if cb >= 1.0 { 1.0 } else if cs <= 0.0 { 0.0 } else { 1.0 - ((1.0 - cb) / cs).min(1.0) }
+        },
+        BlendMode::HardLight => hard_light(cb, cs),
+        BlendMode::SoftLight => {
+            if cs <= 0.5 {
+                cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+            }
+            else {
+                let d = if cb <= 0.25 { ((16.0 * cb - 12.0) * cb + 4.0) * cb } else { cb.sqrt() };
+                cb + (2.0 * cs - 1.0) * (d - cb)
+            }
+        },
+        BlendMode::Difference => (cb - cs).abs(),
+        BlendMode::Exclusion => cb + cs - 2.0 * cb * cs,
+        _ => cs
+    }
+}
+
+/// Composites `src` onto `dst` the way the shader does at the end of a contour, both in
+/// linear light with premultiplied alpha
+///
+/// `coverage` is the fraction of the pixel covered by the contour, and blends between `dst`
+/// and the fully blended color, so pixels outside the contour are left alone
+pub fn composite(src: Rgba<f32>, dst: Rgba<f32>, coverage: f32, mode: BlendMode) -> Rgba<f32> {
+    dst + (blend(src, dst, mode) - dst) * coverage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Rgba<f32>, expected: Rgba<f32>) {
+        let max_error = (actual - expected).map(f32::abs).reduce_partial_max();
+        assert!(max_error < 1e-5, "{actual:?} != {expected:?}");
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        for c in [0.0, 0.5, 1.0] {
+            assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-6, "{c} didn't round trip");
+        }
+
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(0.5) - 0.214_041_14).abs() < 1e-6);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn byte_round_trip() {
+        for c in 0..=255 {
+            let color = Rgba::new(c, 255 - c, c / 2, 255);
+            assert_eq!(encode_color(decode_color(color)), color);
+        }
+
+        assert_eq!(encode_color(decode_color(Rgba::new(255, 128, 0, 0))), Rgba::zero());
+    }
+
+    #[test]
+    fn half_white_over_black_is_linear() {
+        let white = decode_color(Rgba::new(255, 255, 255, 128));
+        let black = decode_color(Rgba::new(0, 0, 0, 255));
+
+        // Half of the light of white, rather than half of its sRGB value which would be 128
+        let blended = encode_color(blend(white, black, BlendMode::SrcOver));
+        assert_eq!(blended, Rgba::new(188, 188, 188, 255));
+    }
+
+    #[test]
+    fn porter_duff_src_in() {
+        let src = Rgba::new(1.0, 0.0, 0.0, 1.0);
+        let dst = Rgba::new(0.0, 0.25, 0.0, 0.5);
+
+        assert_close(blend(src, dst, BlendMode::SrcIn), Rgba::new(0.5, 0.0, 0.0, 0.5));
+        assert_close(blend(src, dst, BlendMode::DstOut), Rgba::zero());
+        assert_close(blend(src, dst, BlendMode::Xor), Rgba::new(0.5, 0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn separable_multiply() {
+        let src = Rgba::new(0.5, 0.5, 0.5, 1.0);
+        let dst = Rgba::new(1.0, 0.5, 0.0, 1.0);
+
+        assert_close(blend(src, dst, BlendMode::Multiply), Rgba::new(0.5, 0.25, 0.0, 1.0));
+
+        // Where the paint is translucent, the multiplied color is mixed with what's below
+        let src = Rgba::new(0.25, 0.25, 0.25, 0.5);
+        assert_close(blend(src, dst, BlendMode::Multiply), Rgba::new(0.75, 0.375, 0.0, 1.0));
+    }
+
+    #[test]
+    fn coverage_blends_towards_dst() {
+        let src = Rgba::new(1.0, 1.0, 1.0, 1.0);
+        let dst = Rgba::new(0.0, 0.0, 0.0, 1.0);
+
+        assert_close(composite(src, dst, 0.0, BlendMode::SrcOver), dst);
+        assert_close(composite(src, dst, 0.25, BlendMode::SrcOver), Rgba::new(0.25, 0.25, 0.25, 1.0));
+        assert_close(composite(src, dst, 1.0, BlendMode::Clear), Rgba::zero());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,2191 @@
+use std::mem;
+use std::ops::Range;
+use std::f32::consts::{TAU, SQRT_2, FRAC_1_SQRT_2};
+
+use vek::{Vec2, Rgba};
+use anyhow::{bail, Result, Context};
+
+use super::recorder::{
+    NO_TRANSFORM, NO_BLUR_LAYER, MAX_CLIP_DEPTH,
+    CanvasCommand, CanvasOp, PaintType, PathInfo, CanvasRecording, Canvas2DRecorder, InitState, FillRule
+};
+use super::renderer::{TILE_SIZE, MAX_REGISTERED_TEXTURES, Antialiasing};
+use super::paint::{TextureId, ExtendMode, FilterMode};
+use super::stroke::{LineJoin, LineCap};
+use super::color::{self, BlendMode};
+use super::transform::Transform;
+use super::capture::CanvasCapture;
+
+// These must match their counterparts in canvas_2d.comp
+const FLATTEN_TOLERANCE: f32 = 0.2;
+const MAX_CURVE_SEGMENTS: f32 = 64.0;
+const MAX_ARC_SEGMENTS: f32 = 256.0;
+const INIT_MIN_DIST: f32 = 999999.0;
+const SUPERSAMPLE_GRID: usize = 4;
+const NUM_SUPERSAMPLES: usize = 16;
+const MAX_LAYER_DEPTH: usize = 4;
+const BOX_SHADOW_SAMPLES: u32 = 4;
+const SQRT_TAU: f32 = 2.506_628_3;
+
+const MAX_BLUR_RADIUS: i32 = 128; // Must match MAX_BLUR_RADIUS in canvas_2d_blur.comp
+
+/// Rasterizes canvas recordings on the CPU, as a reference for the GPU renderer
+///
+/// The same command list recorded for [`Canvas2DRenderer`](super::Canvas2DRenderer) is processed
+/// by a port of the canvas shaders, with the same winding, antialiasing, stroking, blending and
+/// blurring, so recordings can be rendered and checked without a device. Every path is processed
+/// for every pixel, so this is far too slow for anything but small images.
+///
+/// The results match the GPU's within rounding, except that blurred layers are kept at full
+/// precision rather than in half floats, and that the GPU culls paths by tile, so blurred layers
+/// inside clips lose their contents in tiles the clip doesn't overlap
+pub struct CpuRasterizer {
+    recording: CanvasRecording,
+    textures: Vec<Option<CpuTexture>>,
+    placeholder_texture: CpuTexture,
+    antialiasing: Antialiasing
+}
+
+/// A registered texture, decoded to linear light with straight alpha like an sRGB texture is
+/// before it's filtered
+struct CpuTexture {
+    size: Vec2<usize>,
+    texels: Vec<Rgba<f32>>
+}
+
+/// Everything the pixels of a pass read
+struct Pass<'a> {
+    cmds: &'a [CanvasCommand],
+    paths: &'a [PathInfo],
+    textures: &'a [Option<CpuTexture>],
+    placeholder_texture: &'a CpuTexture,
+    antialiasing: Antialiasing,
+
+    /// Blurred layers rasterized so far, with the same layout as the GPU's layer images
+    layer_images: &'a [Vec<Rgba<f32>>]
+}
+
+/// Whether a contour is being filled or stroked
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Fill,
+    Stroke
+}
+
+/// A pixel's state while processing paths, like `PixelState` in the shader
+struct PixelState<'a> {
+    pass: &'a Pass<'a>,
+
+    /// Index of the pixel in the layer images
+    pixel_idx: usize,
+
+    cursor: Vec2<f32>,
+    subpath_start: Vec2<f32>,
+    color: Rgba<f32>,
+    draw_color: Rgba<f32>,
+    mode: Mode,
+    fill_rule: u32,
+    paint_type: u32,
+    paint_idx: usize,
+    blend_mode: u32,
+
+    /// Fill coverage, as the integral of the winding number over the pixel, or as the winding
+    /// number at each sample if supersampling
+    coverage_area: f32,
+    sample_windings: [i32; NUM_SUPERSAMPLES],
+
+    stroke_width: f32,
+    line_join: u32,
+    line_cap: u32,
+    miter_limit: f32,
+    dash_idx: usize,
+    num_dashes: usize,
+    dash_offset: f32,
+    dash_length: f32,
+    prev_dir: Vec2<f32>,
+    path_length: f32,
+    min_dist: f32,
+
+    /// This pixel's top left corner in window space, fills are covered in window space
+    pixel_coord: Vec2<f32>,
+
+    /// Derived from the window to canvas transform of the path being processed, see `setTransform()`
+    transform_scale: f32,
+    window_to_canvas: Transform,
+    canvas_to_window: Transform,
+    pixel_radius: f32,
+    shape_winding: f32,
+
+    clip_contour: bool,
+    clip_coverage: f32,
+    clip_depth: usize,
+    clip_stack: [f32; MAX_CLIP_DEPTH],
+    layer_depth: usize,
+    layer_backdrops: [Rgba<f32>; MAX_LAYER_DEPTH],
+    layer_clip_coverages: [f32; MAX_LAYER_DEPTH],
+    skip_paths_until: u32
+}
+
+impl CpuRasterizer {
+    pub fn new() -> Self {
+        Self {
+            recording: CanvasRecording::default(),
+            textures: vec![],
+            placeholder_texture: CpuTexture {
+                size: Vec2::one(),
+                texels: vec![Rgba::broadcast(1.0)]
+            },
+            antialiasing: Antialiasing::default()
+        }
+    }
+
+    /// Sets how fills are antialiased, like [`Canvas2DRenderer::set_antialiasing()`](super::Canvas2DRenderer::set_antialiasing)
+    pub fn set_antialiasing(&mut self, antialiasing: Antialiasing) {
+        self.antialiasing = antialiasing;
+    }
+
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.antialiasing
+    }
+
+    /// Registers an RGBA8 image for use in [`Paint::Image`](super::Paint::Image)
+    ///
+    /// Pixels are sRGB encoded with straight, not premultiplied, alpha. Slots are assigned the same
+    /// way as [`Canvas2DRenderer::register_texture()`](super::Canvas2DRenderer::register_texture), so
+    /// registering the same textures in the same order gives the same IDs
+    pub fn register_texture(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<TextureId> {
+        let expected_len = width as usize * height as usize * 4;
+
+        if pixels.len() != expected_len || expected_len == 0 {
+            bail!("Texture data is {} bytes, expected {expected_len} for a {width}x{height} texture", pixels.len());
+        }
+
+        // Find a free slot
+        let slot = match self.textures.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None if self.textures.len() < MAX_REGISTERED_TEXTURES as usize => {
+                self.textures.push(None);
+                self.textures.len() - 1
+            },
+            None => bail!("Canvas texture limit of {MAX_REGISTERED_TEXTURES} reached")
+        };
+
+        self.textures[slot] = Some(CpuTexture::decode(width, height, pixels));
+
+        Ok(TextureId(slot as u16))
+    }
+
+    /// Removes a registered texture, its slot may be reused by future registrations
+    pub fn unregister_texture(&mut self, texture_id: TextureId) -> Result<()> {
+        self.textures
+            .get_mut(texture_id.0 as usize)
+            .and_then(Option::take)
+            .context("Texture not registered")?;
+
+        Ok(())
+    }
+
+    /// Records canvas commands and rasterizes them into a `width` by `height` image
+    ///
+    /// Returns RGBA8 pixels, sRGB encoded with straight alpha, the same as the GPU renderer
+    /// writes to the window. Like the window, the image starts out opaque black. Fails if clips
+    /// are nested more than 8 deep
+    pub fn render(
+        &mut self,
+        width: u32,
+        height: u32,
+        record_fn: impl FnOnce(Canvas2DRecorder<InitState>) -> Canvas2DRecorder<InitState>
+    ) -> Result<Vec<u8>> {
+        self.render_cleared(width, height, Rgba::new(0, 0, 0, 255), record_fn)
+    }
+
+    /// Like [`render()`](Self::render), but the image starts out as an sRGB encoded clear color,
+    /// like a render target created with that color
+    pub fn render_cleared(
+        &mut self,
+        width: u32,
+        height: u32,
+        clear_color: Rgba<u8>,
+        record_fn: impl FnOnce(Canvas2DRecorder<InitState>) -> Canvas2DRecorder<InitState>
+    ) -> Result<Vec<u8>> {
+        let recording = record_fn(Canvas2DRecorder::new(mem::take(&mut self.recording))).end()?;
+        let pixels = rasterize(&recording, &self.textures, &self.placeholder_texture, self.antialiasing, width, height, clear_color);
+
+        // Keep the recording's allocations around for the next render
+        self.recording = recording;
+
+        Ok(pixels)
+    }
+
+    /// Rasterizes a captured frame at its size and clear color, see [`render()`](Self::render)
+    ///
+    /// The frame's image paints sample the textures stored in the capture rather than the
+    /// registered ones, and textures missing from the capture are drawn like unregistered ones
+    pub fn replay(&self, capture: &CanvasCapture) -> Vec<u8> {
+        let mut textures = vec![];
+
+        for texture in capture.textures() {
+            let slot = texture.id.0 as usize;
+
+            if textures.len() <= slot {
+                textures.resize_with(slot + 1, || None);
+            }
+
+            textures[slot] = Some(CpuTexture::decode(texture.width, texture.height, &texture.pixels));
+        }
+
+        rasterize(
+            &capture.recording,
+            &textures,
+            &self.placeholder_texture,
+            self.antialiasing,
+            capture.width(),
+            capture.height(),
+            capture.clear_color()
+        )
+    }
+}
+
+impl Default for CpuRasterizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CpuTexture {
+    /// Decodes sRGB encoded RGBA8 pixels with straight alpha
+    fn decode(width: u32, height: u32, pixels: &[u8]) -> Self {
+        let texels = pixels
+            .chunks_exact(4)
+            .map(|texel| {
+                let texel = Rgba::new(texel[0], texel[1], texel[2], texel[3]).map(|c| c as f32 / 255.0);
+
+                Rgba::new(color::srgb_to_linear(texel.r), color::srgb_to_linear(texel.g), color::srgb_to_linear(texel.b), texel.a)
+            })
+            .collect();
+
+        Self {
+            size: Vec2::new(width as usize, height as usize),
+            texels
+        }
+    }
+
+    fn texel(&self, texel: Vec2<i64>) -> Rgba<f32> {
+        self.texels[texel.y as usize * self.size.x + texel.x as usize]
+    }
+
+    /// Samples the texture at a position in texels, like `textureLod()` with the canvas samplers
+    fn sample(&self, pos: Vec2<f32>, filter: FilterMode, extend: ExtendMode) -> Rgba<f32> {
+        let size = self.size.map(|len| len as i64);
+
+        let wrap = |coord: i64, len: i64| match extend {
+            ExtendMode::Pad => coord.clamp(0, len - 1),
+            ExtendMode::Repeat => coord.rem_euclid(len),
+            ExtendMode::Reflect => {
+                let coord = coord.rem_euclid(2 * len);
+
+                if coord >= len { 2 * len - 1 - coord } else { coord }
+            }
+        };
+
+        let wrap_texel = |texel: Vec2<i64>| Vec2::new(wrap(texel.x, size.x), wrap(texel.y, size.y));
+
+        if filter == FilterMode::Nearest {
+            return self.texel(wrap_texel(pos.map(|c| c.floor() as i64)));
+        }
+
+        // Bilinear filtering between the 4 texels with centers around the position
+        let pos = pos - 0.5;
+        let base = pos.map(|c| c.floor() as i64);
+        let frac = pos.map(|c| c - c.floor());
+
+        let row = |y: i64| {
+            let left = self.texel(wrap_texel(Vec2::new(base.x, y)));
+            let right = self.texel(wrap_texel(Vec2::new(base.x + 1, y)));
+
+            left + (right - left) * frac.x
+        };
+
+        let top = row(base.y);
+        let bottom = row(base.y + 1);
+
+        top + (bottom - top) * frac.y
+    }
+}
+
+impl Pass<'_> {
+    /// Rasterizes a range of paths into an image covering `size` pixels, like `main()` in the shader
+    fn rasterize(&self, size: Vec2<usize>, paths: Range<u32>, clear_color: Rgba<f32>) -> Vec<Rgba<f32>> {
+        let mut colors = Vec::with_capacity(size.product());
+
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let pixel_coord = Vec2::new(x as f32, y as f32);
+                let mut state = PixelState::new(self, y * size.x + x, pixel_coord, clear_color);
+
+                // This pixel's corner and center, mapped to canvas space by the transform of the
+                // path being processed
+                let mut canvas_coord = pixel_coord;
+                let mut canvas_center = pixel_coord + 0.5;
+                let mut transform_idx = NO_TRANSFORM;
+
+                for path_idx in paths.clone() {
+                    // The contents of blurred layers were rasterized by their own pass
+                    if path_idx < state.skip_paths_until {
+                        continue;
+                    }
+
+                    let path = &self.paths[path_idx as usize];
+
+                    if path.transform_idx != transform_idx {
+                        let transform = path_transform(self.cmds, path);
+
+                        canvas_coord = transform.apply(pixel_coord);
+                        canvas_center = transform.apply(pixel_coord + 0.5);
+                        transform_idx = path.transform_idx;
+
+                        state.set_transform(&transform);
+                    }
+
+                    state.process_path(path.cmd_idx as usize, canvas_coord, canvas_center);
+                }
+
+                colors.push(state.color);
+            }
+        }
+
+        colors
+    }
+}
+
+/// Rasterizes a finished recording into a `width` by `height` image of sRGB encoded RGBA8 pixels
+fn rasterize(
+    recording: &CanvasRecording,
+    textures: &[Option<CpuTexture>],
+    placeholder_texture: &CpuTexture,
+    antialiasing: Antialiasing,
+    width: u32,
+    height: u32,
+    clear_color: Rgba<u8>
+) -> Vec<u8> {
+    // Like the GPU, whole tiles are rasterized, and blurs read the pixels overhanging the image
+    let size = Vec2::new(width, height).map(|len| (len.div_ceil(TILE_SIZE) * TILE_SIZE) as usize);
+
+    // Layer 0 holds horizontally blurred layers, and layer i + 1 holds blurred layer i
+    let mut layer_images = vec![vec![Rgba::zero(); size.product()]; recording.blur_layers.len() + 1];
+
+    // Rasterize and blur each blurred layer, nested layers come first so they're ready by the
+    // time the layers containing them are rasterized
+    for (layer_idx, blur_layer) in recording.blur_layers.iter().enumerate() {
+        let pass = Pass {
+            cmds: &recording.cmds,
+            paths: &recording.paths,
+            textures,
+            placeholder_texture,
+            antialiasing,
+            layer_images: &layer_images
+        };
+
+        let layer = pass.rasterize(size, blur_layer.paths.clone(), Rgba::zero());
+        let blurred = blur(&layer, size, blur_layer.std_dev, false);
+
+        layer_images[layer_idx + 1] = blur(&blurred, size, blur_layer.std_dev, true);
+    }
+
+    let pass = Pass {
+        cmds: &recording.cmds,
+        paths: &recording.paths,
+        textures,
+        placeholder_texture,
+        antialiasing,
+        layer_images: &layer_images
+    };
+
+    let colors = pass.rasterize(size, 0..recording.paths.len() as u32, color::decode_color(clear_color));
+
+    (0..height as usize)
+        .flat_map(|y| &colors[y * size.x..y * size.x + width as usize])
+        .flat_map(|&color| color::encode_color(color).into_array())
+        .collect()
+}
+
+/// Blurs an image in one direction by a gaussian, like canvas_2d_blur.comp
+fn blur(image: &[Rgba<f32>], size: Vec2<usize>, std_dev: f32, vertical: bool) -> Vec<Rgba<f32>> {
+    // Gaussian weights are normalized by their sum, so the truncated kernel doesn't darken the image
+    let radius = ((3.0 * std_dev).ceil() as i32).min(MAX_BLUR_RADIUS);
+    let falloff = -0.5 / (std_dev * std_dev);
+
+    let weights: Vec<f32> = (-radius..=radius).map(|offset| ((offset * offset) as f32 * falloff).exp()).collect();
+    let weight_sum: f32 = weights.iter().sum();
+
+    let dir = if vertical { Vec2::new(0, 1) } else { Vec2::new(1, 0) };
+    let size_i32 = size.map(|len| len as i32);
+    let mut blurred = Vec::with_capacity(image.len());
+
+    for y in 0..size_i32.y {
+        for x in 0..size_i32.x {
+            let mut sum = Rgba::zero();
+
+            for (offset, weight) in (-radius..=radius).zip(&weights) {
+                let tap = Vec2::new(x, y) + dir * offset;
+
+                // Everything past the edges of the image is transparent
+                if tap.x >= 0 && tap.y >= 0 && tap.x < size_i32.x && tap.y < size_i32.y {
+                    sum += image[tap.y as usize * size.x + tap.x as usize] * *weight;
+                }
+            }
+
+            blurred.push(sum / weight_sum);
+        }
+    }
+
+    blurred
+}
+
+/// Unpacks a point or pair of f32s stored in a command param
+fn unpack_point(param: Vec2<u32>) -> Vec2<f32> {
+    param.map(f32::from_bits)
+}
+
+/// Unpacks an sRGB color packed into a u32 to linear light with premultiplied alpha, like `unpackColor()`
+fn unpack_color(param: u32) -> Rgba<f32> {
+    let [r, g, b, a] = param.to_le_bytes();
+
+    color::decode_color(Rgba::new(r, g, b, a))
+}
+
+fn unpack_blend_mode(param: u32) -> BlendMode {
+    BlendMode::ALL.get(param as usize).copied().unwrap_or_default()
+}
+
+/// Unpacks an affine transform stored as the columns of a matrix in a command
+fn unpack_transform(cmd: &CanvasCommand) -> Transform {
+    let (col1, col2, col3) = (unpack_point(cmd.param1), unpack_point(cmd.param2), unpack_point(cmd.param3));
+
+    Transform::new(col1.x, col1.y, col2.x, col2.y, col3.x, col3.y)
+}
+
+/// The window to canvas transform in effect for a path
+fn path_transform(cmds: &[CanvasCommand], path: &PathInfo) -> Transform {
+    if path.transform_idx == NO_TRANSFORM {
+        return Transform::IDENTITY;
+    }
+
+    unpack_transform(&cmds[path.transform_idx as usize])
+}
+
+/// Average scale from canvas space to pixels of a window to canvas transform, 0 if it's degenerate
+fn transform_scale(transform: &Transform) -> f32 {
+    let det = (transform.a * transform.d - transform.c * transform.b).abs();
+
+    if det == 0.0 { 0.0 } else { 1.0 / det.sqrt() }
+}
+
+/// Clamps like GLSL's `clamp()` on the GPU, with NaN clamped to `min`
+fn clamp(x: f32, min: f32, max: f32) -> f32 {
+    x.max(min).min(max)
+}
+
+/// GLSL's `sign()`, which is 0 for 0 unlike [`f32::signum()`]
+fn sign(x: f32) -> f32 {
+    if x > 0.0 { 1.0 } else if x < 0.0 { -1.0 } else { 0.0 }
+}
+
+/// GLSL's `mod()`, which takes the sign of `y`
+fn modulo(x: f32, y: f32) -> f32 {
+    x - y * (x / y).floor()
+}
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: Vec2<f32>, b: Vec2<f32>, t: f32) -> Vec2<f32> {
+    a * (1.0 - t) + b * t
+}
+
+fn min2(a: Vec2<f32>, b: Vec2<f32>) -> Vec2<f32> {
+    a.map2(b, f32::min)
+}
+
+fn max2(a: Vec2<f32>, b: Vec2<f32>) -> Vec2<f32> {
+    a.map2(b, f32::max)
+}
+
+fn cross2(a: Vec2<f32>, b: Vec2<f32>) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+fn in_range(p: f32, a: f32, b: f32) -> bool {
+    p >= a.min(b) && p < a.max(b)
+}
+
+/// Integral of `clamp(x, 0.0, 1.0)` from 0 to x
+fn clamped_integral(x: f32) -> f32 {
+    let c = clamp(x, 0.0, 1.0);
+
+    0.5 * c * c + (x - 1.0).max(0.0)
+}
+
+/// Signed area of the part of the pixel's row between a window space line's ends that's left of
+/// the line, see `lineArea()`
+fn line_area(pixel: Vec2<f32>, a: Vec2<f32>, b: Vec2<f32>) -> f32 {
+    let a = a - pixel;
+    let b = b - pixel;
+
+    // Clip the line to the pixel's row
+    let y0 = clamp(a.y, 0.0, 1.0);
+    let y1 = clamp(b.y, 0.0, 1.0);
+
+    if y0 == y1 {
+        return 0.0;
+    }
+
+    let dxdy = (b.x - a.x) / (b.y - a.y);
+    let x0 = a.x + (y0 - a.y) * dxdy;
+    let x1 = a.x + (y1 - a.y) * dxdy;
+
+    let width = if (x1 - x0).abs() < 1e-4 {
+        clamp(0.5 * (x0 + x1), 0.0, 1.0)
+    }
+    else {
+        (clamped_integral(x1) - clamped_integral(x0)) / (x1 - x0)
+    };
+
+    (y1 - y0) * width
+}
+
+/// Window space position of a supersample, on a regular grid within the pixel
+fn sample_pos(pixel: Vec2<f32>, sample_idx: usize) -> Vec2<f32> {
+    let grid_pos = Vec2::new((sample_idx % SUPERSAMPLE_GRID) as f32, (sample_idx / SUPERSAMPLE_GRID) as f32);
+
+    pixel + (grid_pos + 0.5) / SUPERSAMPLE_GRID as f32
+}
+
+fn line_winding_direction(p: Vec2<f32>, a: Vec2<f32>, b: Vec2<f32>) -> bool {
+    let ba = b - a;
+    let pa = p - a;
+
+    ba.x * pa.y > ba.y * pa.x
+}
+
+/// Distance from a point to an axis aligned box, 0 if the point is inside
+fn box_dist(p: Vec2<f32>, box_min: Vec2<f32>, box_max: Vec2<f32>) -> f32 {
+    max2(max2(box_min - p, p - box_max), Vec2::zero()).magnitude()
+}
+
+/// Signed distance to the box covering `span` along a line from `origin`, and `half_width` to
+/// either side of it
+fn line_box_dist(p: Vec2<f32>, origin: Vec2<f32>, dir: Vec2<f32>, span: Vec2<f32>, half_width: f32) -> f32 {
+    let local = Vec2::new((p - origin).dot(dir), cross2(dir, p - origin));
+    let d = Vec2::new((span.x - local.x).max(local.x - span.y), local.y.abs() - half_width);
+
+    max2(d, Vec2::zero()).magnitude() + d.x.max(d.y).min(0.0)
+}
+
+/// Signed distance to a convex polygon, negative inside, whichever way it's wound
+fn convex_polygon_dist(p: Vec2<f32>, vertices: &[Vec2<f32>]) -> f32 {
+    let edge = |i: usize| (vertices[i], vertices[(i + 1) % vertices.len()] - vertices[i]);
+    let winding = sign((0..vertices.len()).map(|i| cross2(edge(i).0, edge(i).1)).sum());
+
+    let mut dist_sq = f32::MAX;
+    let mut inside = true;
+
+    for i in 0..vertices.len() {
+        let (a, e) = edge(i);
+        let v = p - a;
+
+        // Closest point on the edge, repeated vertices make zero length edges which never exclude the point
+        let pq = v - e * clamp(v.dot(e) / e.dot(e).max(1e-12), 0.0, 1.0);
+
+        dist_sq = dist_sq.min(pq.dot(pq));
+        inside &= winding * cross2(e, v) >= 0.0;
+    }
+
+    if inside { -dist_sq.sqrt() } else { dist_sq.sqrt() }
+}
+
+/// Signed distance to an ellipse centered at the origin, negative inside, see `ellipseDist()`
+fn ellipse_dist(p: Vec2<f32>, radii: Vec2<f32>) -> f32 {
+    let radii = max2(radii, Vec2::broadcast(1e-3));
+
+    let ap = p.map(f32::abs);
+    let mut t = Vec2::broadcast(FRAC_1_SQRT_2);
+
+    for _ in 0..3 {
+        let xy = radii * t;
+        let e = Vec2::new(
+            (radii.x * radii.x - radii.y * radii.y) / radii.x,
+            (radii.y * radii.y - radii.x * radii.x) / radii.y
+        ) * t * t * t;
+
+        let r = (xy - e).magnitude();
+        let q = (ap - e).magnitude().max(1e-6);
+
+        t = ((ap - e) * r / q + e) / radii;
+        t = t.map(|c| clamp(c, 0.0, 1.0)).normalized();
+    }
+
+    let dist = (ap - radii * t).magnitude();
+    let normalized = p / radii;
+
+    if normalized.dot(normalized) < 1.0 { -dist } else { dist }
+}
+
+/// Signed distance to a rounded rectangle, negative inside
+fn rounded_rect_dist(p: Vec2<f32>, top_left: Vec2<f32>, size: Vec2<f32>, radius: f32) -> f32 {
+    let half_size = size * 0.5;
+    let radius = radius.min(half_size.x.min(half_size.y));
+
+    let q = (p - top_left - half_size).map(f32::abs) - half_size + radius;
+
+    q.x.max(q.y).min(0.0) + max2(q, Vec2::zero()).magnitude() - radius
+}
+
+/// Signed distance to a circle, ellipse or rounded rectangle command's shape, negative inside
+fn shape_dist(cmd: &CanvasCommand, p: Vec2<f32>) -> f32 {
+    match cmd.opcode {
+        CanvasOp::Circle => (p - unpack_point(cmd.param1)).magnitude() - f32::from_bits(cmd.param2.x).abs(),
+        CanvasOp::Ellipse => ellipse_dist(p - unpack_point(cmd.param1), unpack_point(cmd.param2).map(f32::abs)),
+        _ => {
+            let top_left = unpack_point(cmd.param1);
+            let size = unpack_point(cmd.param2);
+
+            rounded_rect_dist(p, min2(top_left, top_left + size), size.map(f32::abs), f32::from_bits(cmd.param3.x).max(0.0))
+        }
+    }
+}
+
+/// Outline of a circle or rounded rectangle, the outline of a rectangular core pushed out by the
+/// radius, see `RoundedOutline` in the shader
+#[derive(Clone, Copy)]
+struct RoundedOutline {
+    core_min: Vec2<f32>,
+    core_max: Vec2<f32>,
+    radius: f32,
+    start_normal: Vec2<f32>
+}
+
+impl RoundedOutline {
+    /// Corner of the core, clockwise from the top left one
+    fn corner(&self, corner: usize) -> Vec2<f32> {
+        let corner = corner % 4;
+
+        Vec2::new(
+            if corner == 1 || corner == 2 { self.core_max.x } else { self.core_min.x },
+            if corner >= 2 { self.core_max.y } else { self.core_min.y }
+        )
+    }
+
+    /// Length of a straight edge, clockwise from the top one
+    fn edge(&self, edge: usize) -> f32 {
+        let core_size = self.core_max - self.core_min;
+
+        if edge.is_multiple_of(2) { core_size.x } else { core_size.y }
+    }
+
+    fn len(&self) -> f32 {
+        2.0 * (self.edge(0) + self.edge(1)) + TAU * self.radius
+    }
+
+    /// Signed distance to the outline, the distance along it of its closest point and how much
+    /// distances along it are stretched at `p`, see `outlineDist()`
+    fn dist(&self, p: Vec2<f32>) -> (f32, f32, f32) {
+        let mut core = Vec2::new(clamp(p.x, self.core_min.x, self.core_max.x), clamp(p.y, self.core_min.y, self.core_max.y));
+        let normal;
+        let dist;
+
+        // Points inside the core are closest to its nearest edge, otherwise to the nearest point on it
+        if p == core {
+            let edge_dists = [p.x - self.core_min.x, p.y - self.core_min.y, self.core_max.x - p.x, self.core_max.y - p.y];
+            let edge_dist = edge_dists.into_iter().fold(f32::INFINITY, f32::min);
+
+            if edge_dist == edge_dists[0] {
+                normal = Vec2::new(-1.0, 0.0);
+                core.x = self.core_min.x;
+            }
+            else if edge_dist == edge_dists[1] {
+                normal = Vec2::new(0.0, -1.0);
+                core.y = self.core_min.y;
+            }
+            else if edge_dist == edge_dists[2] {
+                normal = Vec2::new(1.0, 0.0);
+                core.x = self.core_max.x;
+            }
+            else {
+                normal = Vec2::new(0.0, 1.0);
+                core.y = self.core_max.y;
+            }
+
+            dist = -edge_dist - self.radius;
+        }
+        else {
+            normal = (p - core).normalized();
+            dist = (p - core).magnitude() - self.radius;
+        }
+
+        // The normal relative to the start of the outline picks the edge and the corner after it, and
+        // the angle turned around that corner
+        let mut turned = Vec2::new(normal.dot(self.start_normal), cross2(self.start_normal, normal));
+
+        let edge = if turned.y >= 0.0 && turned.x > 0.0 { 0 }
+            else if turned.x <= 0.0 && turned.y > 0.0 { 1 }
+            else if turned.y <= 0.0 && turned.x < 0.0 { 2 }
+            else { 3 };
+
+        let mut along = 0.0;
+
+        for i in 0..edge {
+            turned = Vec2::new(turned.y, -turned.x);
+            along += self.edge(i) + 0.25 * TAU * self.radius;
+        }
+
+        let angle = turned.y.atan2(turned.x);
+
+        along += (core - self.corner(edge)).magnitude() + angle * self.radius;
+
+        let stretch = if angle > 0.0 || self.edge(edge) == 0.0 { (dist + self.radius).max(0.0) / self.radius } else { 1.0 };
+
+        (dist, along, stretch)
+    }
+
+    /// Point at a distance along the outline, and the outline's direction there
+    fn point(&self, mut along: f32) -> (Vec2<f32>, Vec2<f32>) {
+        let mut normal = self.start_normal;
+        let quarter_arc = 0.25 * TAU * self.radius;
+        let mut edge = 0;
+
+        // Find the edge the point lies on, or the corner after it
+        while edge < 3 {
+            let edge_arc = self.edge(edge) + quarter_arc;
+
+            if along <= edge_arc {
+                break;
+            }
+
+            along -= edge_arc;
+            normal = Vec2::new(-normal.y, normal.x);
+            edge += 1;
+        }
+
+        let next_normal = Vec2::new(-normal.y, normal.x);
+        let edge_length = self.edge(edge);
+
+        if along <= edge_length {
+            return (self.corner(edge) + normal * self.radius + next_normal * along, next_normal);
+        }
+
+        let (sin, cos) = ((along - edge_length) / self.radius).sin_cos();
+
+        (self.corner(edge + 1) + (normal * cos + next_normal * sin) * self.radius, next_normal * cos - normal * sin)
+    }
+}
+
+/// Approximates the error function, with a maximum error of 5e-4
+fn erf(x: f32) -> f32 {
+    let s = sign(x);
+    let a = x.abs();
+
+    let mut x = 1.0 + (0.278393 + (0.230389 + 0.078108 * (a * a)) * a) * a;
+    x *= x;
+
+    s - s / (x * x)
+}
+
+fn gaussian(x: f32, std_dev: f32) -> f32 {
+    (-(x * x) / (2.0 * std_dev * std_dev)).exp() / (SQRT_TAU * std_dev)
+}
+
+/// Horizontally blurred coverage of a single row of a rounded rectangle centered on the origin,
+/// y being the row's offset from the center
+fn box_shadow_row(x: f32, y: f32, half_size: Vec2<f32>, radius: f32, std_dev: f32) -> f32 {
+    // Width of the row, narrower within the rounded corners
+    let corner_y = (half_size.y - radius - y.abs()).min(0.0);
+    let half_width = half_size.x - radius + (radius * radius - corner_y * corner_y).max(0.0).sqrt();
+
+    let scale = 0.5f32.sqrt() / std_dev;
+    let integral = |x: f32| 0.5 + 0.5 * erf(x * scale);
+
+    integral(x + half_width) - integral(x - half_width)
+}
+
+/// Coverage of a rounded rectangle blurred by a gaussian, see `boxShadow()`
+fn box_shadow(p: Vec2<f32>, top_left: Vec2<f32>, size: Vec2<f32>, radius: f32, std_dev: f32) -> f32 {
+    let box_min = min2(top_left, top_left + size);
+    let box_max = max2(top_left, top_left + size);
+    let half_size = (box_max - box_min) * 0.5;
+
+    let p = p - (box_min + box_max) * 0.5;
+    let radius = clamp(radius, 0.0, half_size.x.min(half_size.y));
+
+    // Rows are sampled at offsets from the pixel, within the rectangle
+    let first_row = clamp(-3.0 * std_dev, p.y - half_size.y, p.y + half_size.y);
+    let last_row = clamp(3.0 * std_dev, p.y - half_size.y, p.y + half_size.y);
+    let row_step = (last_row - first_row) / BOX_SHADOW_SAMPLES as f32;
+
+    (0..BOX_SHADOW_SAMPLES)
+        .map(|i| {
+            let y = first_row + (i as f32 + 0.5) * row_step;
+
+            box_shadow_row(p.x, p.y - y, half_size, radius, std_dev) * gaussian(y, std_dev) * row_step
+        })
+        .sum()
+}
+
+/// Applies a gradient's extend mode to the gradient parameter
+fn apply_extend(t: f32, extend: u32) -> f32 {
+    if extend == ExtendMode::Repeat as u32 {
+        fract(t)
+    }
+    else if extend == ExtendMode::Reflect as u32 {
+        1.0 - (modulo(t, 2.0) - 1.0).abs()
+    }
+    else {
+        clamp(t, 0.0, 1.0)
+    }
+}
+
+impl<'a> PixelState<'a> {
+    fn new(pass: &'a Pass<'a>, pixel_idx: usize, pixel_coord: Vec2<f32>, color: Rgba<f32>) -> Self {
+        let mut state = Self {
+            pass,
+            pixel_idx,
+            cursor: Vec2::zero(),
+            subpath_start: Vec2::zero(),
+            color,
+            draw_color: Rgba::zero(),
+            mode: Mode::Fill,
+            fill_rule: FillRule::NonZero as u32,
+            paint_type: PaintType::Solid as u32,
+            paint_idx: 0,
+            blend_mode: BlendMode::SrcOver as u32,
+            coverage_area: 0.0,
+            sample_windings: [0; NUM_SUPERSAMPLES],
+            stroke_width: 0.0,
+            line_join: LineJoin::Miter as u32,
+            line_cap: LineCap::Butt as u32,
+            miter_limit: 0.0,
+            dash_idx: 0,
+            num_dashes: 0,
+            dash_offset: 0.0,
+            dash_length: 0.0,
+            prev_dir: Vec2::zero(),
+            path_length: 0.0,
+            min_dist: INIT_MIN_DIST,
+            pixel_coord,
+            transform_scale: 1.0,
+            window_to_canvas: Transform::IDENTITY,
+            canvas_to_window: Transform::IDENTITY,
+            pixel_radius: 0.0,
+            shape_winding: 0.0,
+            clip_contour: false,
+            clip_coverage: 1.0,
+            clip_depth: 0,
+            clip_stack: [0.0; MAX_CLIP_DEPTH],
+            layer_depth: 0,
+            layer_backdrops: [Rgba::zero(); MAX_LAYER_DEPTH],
+            layer_clip_coverages: [0.0; MAX_LAYER_DEPTH],
+            skip_paths_until: 0
+        };
+
+        state.set_transform(&Transform::IDENTITY);
+        state
+    }
+
+    /// Sets the values derived from the window to canvas transform of the paths being processed
+    fn set_transform(&mut self, transform: &Transform) {
+        let det = transform.a * transform.d - transform.c * transform.b;
+        let corner_dist = |x: f32, y: f32| Vec2::new(transform.a * x + transform.c * y, transform.b * x + transform.d * y).magnitude();
+
+        self.transform_scale = transform_scale(transform);
+        self.window_to_canvas = *transform;
+        self.pixel_radius = 0.5 * corner_dist(1.0, 1.0).max(corner_dist(1.0, -1.0));
+        self.shape_winding = sign(det);
+
+        // Degenerate transforms don't cover anything, so their inverse doesn't matter
+        if det == 0.0 {
+            self.canvas_to_window = Transform::new(0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+            return;
+        }
+
+        let inv_det = 1.0 / det;
+
+        self.canvas_to_window = Transform::new(
+            transform.d * inv_det,
+            -transform.b * inv_det,
+            -transform.c * inv_det,
+            transform.a * inv_det,
+            (transform.c * transform.f - transform.d * transform.e) * inv_det,
+            (transform.b * transform.e - transform.a * transform.f) * inv_det
+        );
+    }
+
+    fn cmd(&self, idx: usize) -> &'a CanvasCommand {
+        &self.pass.cmds[idx]
+    }
+
+    /// Lengths of a dash pair of the current stroke
+    fn dash_pair(&self, pair: usize) -> Vec2<f32> {
+        unpack_point(self.cmd(self.dash_idx + pair).param1)
+    }
+
+    /// Checks if a distance along the current subpath falls within a dash, always true for solid strokes
+    fn in_dash(&self, dist: f32) -> bool {
+        if self.num_dashes == 0 {
+            return true;
+        }
+
+        let phase = modulo(dist + self.dash_offset, self.dash_length);
+        let mut pos = 0.0;
+
+        for i in 0..self.num_dashes {
+            let pair = self.dash_pair(i);
+
+            if phase < pos + pair.x + pair.y {
+                return phase <= pos + pair.x;
+            }
+
+            pos += pair.x + pair.y;
+        }
+
+        false
+    }
+
+    /// Finds the last dash starting at or before a distance along the current subpath, and the dash
+    /// after it, as (start, end) distances along the subpath
+    fn find_dashes(&self, dist: f32) -> (Vec2<f32>, Vec2<f32>) {
+        let phase = modulo(dist + self.dash_offset, self.dash_length);
+        let pattern_start = dist - phase;
+        let mut pos = 0.0;
+
+        for i in 0..self.num_dashes {
+            let pair = self.dash_pair(i);
+
+            if phase < pos + pair.x + pair.y || i == self.num_dashes - 1 {
+                let next_pos = pos + pair.x + pair.y;
+                let next_length = self.dash_pair((i + 1) % self.num_dashes).x;
+
+                return (
+                    Vec2::new(pos, pos + pair.x) + pattern_start,
+                    Vec2::new(next_pos, next_pos + next_length) + pattern_start
+                );
+            }
+
+            pos += pair.x + pair.y;
+        }
+
+        (Vec2::zero(), Vec2::zero())
+    }
+
+    /// How far the pieces of a stroke overlap in canvas space, two pixels so anywhere within a
+    /// pixel of the edge between two pieces is at least a pixel inside one of them, and covered
+    /// rather than antialiased
+    fn stroke_overlap(&self) -> f32 {
+        2.0 / self.transform_scale
+    }
+
+    /// Adds a cap at the open end of a subpath or dash, with `dir` pointing away from the stroke
+    fn stroke_cap(&mut self, p: Vec2<f32>, point: Vec2<f32>, dir: Vec2<f32>) {
+        let half_width = 0.5 * self.stroke_width;
+
+        if self.line_cap == LineCap::Round as u32 {
+            self.min_dist = self.min_dist.min((p - point).magnitude() - half_width);
+        }
+        else if self.line_cap == LineCap::Square as u32 {
+            // Reaches back into the body so the edge between them is covered
+            self.min_dist = self.min_dist.min(line_box_dist(p, point, dir, Vec2::new(-self.stroke_overlap(), half_width), half_width));
+        }
+    }
+
+    /// Adds the part of a dash lying on a line, with `dash` relative to the start of the line
+    fn stroke_dash(&mut self, p: Vec2<f32>, a: Vec2<f32>, dir: Vec2<f32>, len: f32, dash: Vec2<f32>) {
+        if dash.x > len || dash.y < 0.0 {
+            return;
+        }
+
+        let span = dash.map(|c| clamp(c, 0.0, len));
+
+        if span.y > span.x {
+            self.min_dist = self.min_dist.min(line_box_dist(p, a, dir, span, 0.5 * self.stroke_width));
+        }
+
+        if dash.x > 0.0 {
+            self.stroke_cap(p, a + dir * dash.x, -dir);
+        }
+
+        if dash.y < len {
+            self.stroke_cap(p, a + dir * dash.y, dir);
+        }
+    }
+
+    /// Adds the body of a line to the stroke, only along its dashes if the stroke is dashed
+    fn stroke_body(&mut self, p: Vec2<f32>, a: Vec2<f32>, dir: Vec2<f32>, len: f32) {
+        if self.num_dashes == 0 {
+            self.min_dist = self.min_dist.min(line_box_dist(p, a, dir, Vec2::new(0.0, len), 0.5 * self.stroke_width));
+            return;
+        }
+
+        // Only the dashes around the point on the line closest to the pixel can cover it
+        let along = clamp((p - a).dot(dir), 0.0, len);
+        let (dash, next_dash) = self.find_dashes(self.path_length + along);
+
+        self.stroke_dash(p, a, dir, len, dash - self.path_length);
+        self.stroke_dash(p, a, dir, len, next_dash - self.path_length);
+    }
+
+    /// Adds the join between the previous line and a line leaving `point` in direction `dir`
+    fn stroke_join(&mut self, p: Vec2<f32>, point: Vec2<f32>, dir: Vec2<f32>, join: u32) {
+        if !self.in_dash(self.path_length) {
+            return;
+        }
+
+        let half_width = 0.5 * self.stroke_width;
+
+        if join == LineJoin::Round as u32 {
+            self.min_dist = self.min_dist.min((p - point).magnitude() - half_width);
+            return;
+        }
+
+        let turn = cross2(self.prev_dir, dir);
+        let straightness = 1.0 + self.prev_dir.dot(dir);
+
+        // Lines doubling back have no corner to fill
+        if turn == 0.0 && straightness < 1.0 {
+            return;
+        }
+
+        // Outer corners of the two lines' ends, on the opposite side to the turn
+        let side = if turn > 0.0 { -1.0 } else { 1.0 };
+        let normal1 = Vec2::new(-self.prev_dir.y, self.prev_dir.x) * side;
+        let normal2 = Vec2::new(-dir.y, dir.x) * side;
+        let corner1 = point + normal1 * half_width;
+        let corner2 = point + normal2 * half_width;
+
+        // The join covers the ends of both lines as well as the corner between them, so the edges
+        // where they meet are covered. On the inner side the ends are cut short where they cross,
+        // which keeps the join convex
+        let overlap = self.stroke_overlap();
+        let inner = if overlap * straightness < half_width * turn.abs() { overlap * straightness / turn.abs() } else { half_width };
+
+        // Joins past the miter limit are left beveled, with the tip in the middle of the bevel
+        let mut tip = mix(corner1, corner2, 0.5);
+
+        if join == LineJoin::Miter as u32 {
+            let cos_half_turn = (0.5 * straightness).sqrt();
+
+            if cos_half_turn * self.miter_limit >= 1.0 {
+                tip = point + (normal1 + normal2).normalized() * half_width / cos_half_turn;
+            }
+        }
+
+        let vertices = [
+            point - self.prev_dir * overlap - normal1 * inner,
+            corner1 - self.prev_dir * overlap,
+            corner1,
+            tip,
+            corner2,
+            corner2 + dir * overlap,
+            point + dir * overlap - normal2 * inner
+        ];
+
+        self.min_dist = self.min_dist.min(convex_polygon_dist(p, &vertices));
+    }
+
+    /// Adds a line to the stroke, joined to the previous line of the subpath or capped if it's the first
+    fn stroke_line(&mut self, p: Vec2<f32>, a: Vec2<f32>, b: Vec2<f32>, smooth_join: bool) {
+        let len = (b - a).magnitude();
+
+        // Zero length lines have no direction to join or cap with
+        if len == 0.0 {
+            return;
+        }
+
+        let dir = (b - a) / len;
+
+        if self.prev_dir == Vec2::zero() {
+            if self.in_dash(self.path_length) {
+                self.stroke_cap(p, a, -dir);
+            }
+        }
+        else {
+            self.stroke_join(p, a, dir, if smooth_join { LineJoin::Bevel as u32 } else { self.line_join });
+        }
+
+        self.stroke_body(p, a, dir, len);
+
+        self.prev_dir = dir;
+        self.path_length += len;
+    }
+
+    /// Furthest the stroke's outline can be from its path, with miter joins and square caps
+    /// sticking out past half the stroke width
+    fn stroke_extent(&self) -> f32 {
+        let mut extent: f32 = 1.0;
+
+        if self.line_join == LineJoin::Miter as u32 {
+            extent = extent.max(self.miter_limit);
+        }
+
+        if self.line_cap == LineCap::Square as u32 {
+            extent = extent.max(SQRT_2);
+        }
+
+        extent * 0.5 * self.stroke_width
+    }
+
+    /// Maps a canvas space point to window space
+    fn to_window(&self, point: Vec2<f32>) -> Vec2<f32> {
+        self.canvas_to_window.apply(point)
+    }
+
+    /// Accumulates a canvas space line's contribution to the pixel's fill coverage, see `fillLine()`
+    fn fill_line(&mut self, a: Vec2<f32>, b: Vec2<f32>) {
+        let a = self.to_window(a);
+        let b = self.to_window(b);
+
+        if self.pass.antialiasing == Antialiasing::Supersampled {
+            let down = b.y > a.y;
+
+            for (i, winding) in self.sample_windings.iter_mut().enumerate() {
+                let s = sample_pos(self.pixel_coord, i);
+
+                if in_range(s.y, a.y, b.y) && line_winding_direction(s, a, b) == down {
+                    *winding += if down { 1 } else { -1 };
+                }
+            }
+
+            return;
+        }
+
+        self.coverage_area += line_area(self.pixel_coord, a, b);
+    }
+
+    /// Adds a winding number to the whole pixel's fill coverage
+    fn fill_pixel(&mut self, winding_num: f32) {
+        if self.pass.antialiasing == Antialiasing::Supersampled {
+            for winding in &mut self.sample_windings {
+                *winding += winding_num as i32;
+            }
+
+            return;
+        }
+
+        self.coverage_area += winding_num;
+    }
+
+    /// Clears the fill coverage accumulated by the previous contour
+    fn reset_fill(&mut self) {
+        self.coverage_area = 0.0;
+        self.sample_windings = [0; NUM_SUPERSAMPLES];
+    }
+
+    /// Accumulates a line's contribution to the pixel's fill coverage, or to the pixel's distance
+    /// to the stroke outline
+    fn process_line(&mut self, p: Vec2<f32>, a: Vec2<f32>, b: Vec2<f32>, smooth_join: bool) {
+        if self.mode == Mode::Fill {
+            self.fill_line(a, b);
+        }
+        else {
+            self.stroke_line(p, a, b, smooth_join);
+        }
+    }
+
+    /// Checks if a curve with the given control points can be skipped entirely for this pixel, see `canSkipCurve()`
+    fn can_skip_curve(&self, p: Vec2<f32>, box_min: Vec2<f32>, box_max: Vec2<f32>) -> bool {
+        if self.mode == Mode::Fill {
+            let corners = [
+                self.to_window(box_min),
+                self.to_window(Vec2::new(box_max.x, box_min.y)),
+                self.to_window(Vec2::new(box_min.x, box_max.y)),
+                self.to_window(box_max)
+            ];
+
+            let window_min = corners.iter().copied().reduce(min2).unwrap();
+            let window_max = corners.iter().copied().reduce(max2).unwrap();
+            let pixel_max = self.pixel_coord + 1.0;
+
+            return window_min.x >= pixel_max.x || window_min.y >= pixel_max.y
+                || window_max.x <= self.pixel_coord.x || window_max.y <= self.pixel_coord.y;
+        }
+
+        self.num_dashes == 0 && box_dist(p, box_min, box_max) - self.stroke_extent() > self.min_dist
+    }
+
+    /// Accounts for a skipped curve, fills are covered by the line between its ends instead, and
+    /// strokes keep track of the direction it ends in, so the line after it is joined correctly
+    fn skip_curve(&mut self, start: Vec2<f32>, end: Vec2<f32>, end_tangent: Vec2<f32>) {
+        if self.mode == Mode::Fill {
+            self.fill_line(start, end);
+        }
+        else if end_tangent != Vec2::zero() {
+            self.prev_dir = end_tangent.normalized();
+        }
+    }
+
+    /// Flattening tolerance in canvas space, so curves are equally smooth at any scale
+    fn flatten_tolerance(&self) -> f32 {
+        FLATTEN_TOLERANCE / self.transform_scale
+    }
+
+    /// Flattens a quadratic bezier into line segments and processes them
+    fn process_quad(&mut self, p: Vec2<f32>, p0: Vec2<f32>, p1: Vec2<f32>, p2: Vec2<f32>) {
+        if self.can_skip_curve(p, min2(min2(p0, p1), p2), max2(max2(p0, p1), p2)) {
+            self.skip_curve(p0, p2, if p2 != p1 { p2 - p1 } else { p2 - p0 });
+            return;
+        }
+
+        // Flattening error with n segments is |p0 - 2p1 + p2| / (8n^2)
+        let dd = (p0 - p1 * 2.0 + p2).magnitude();
+        let segments = clamp((dd / (8.0 * self.flatten_tolerance())).sqrt().ceil(), 1.0, MAX_CURVE_SEGMENTS);
+
+        let mut prev = p0;
+
+        for i in 1..=segments as u32 {
+            let t = i as f32 / segments;
+            let point = mix(mix(p0, p1, t), mix(p1, p2, t), t);
+
+            self.process_line(p, prev, point, i > 1);
+            prev = point;
+        }
+    }
+
+    /// Flattens a cubic bezier into line segments and processes them
+    fn process_cubic(&mut self, p: Vec2<f32>, p0: Vec2<f32>, p1: Vec2<f32>, p2: Vec2<f32>, p3: Vec2<f32>) {
+        if self.can_skip_curve(p, min2(min2(p0, p1), min2(p2, p3)), max2(max2(p0, p1), max2(p2, p3))) {
+            self.skip_curve(p0, p3, if p3 != p2 { p3 - p2 } else if p3 != p1 { p3 - p1 } else { p3 - p0 });
+            return;
+        }
+
+        // Wang's formula for the number of segments needed
+        let dd = (p0 - p1 * 2.0 + p2).magnitude().max((p1 - p2 * 2.0 + p3).magnitude());
+        let segments = clamp((0.75 * dd / self.flatten_tolerance()).sqrt().ceil(), 1.0, MAX_CURVE_SEGMENTS);
+
+        let mut prev = p0;
+
+        for i in 1..=segments as u32 {
+            let t = i as f32 / segments;
+            let s = 1.0 - t;
+            let point = p0 * (s * s * s) + p1 * (3.0 * s * s * t) + p2 * (3.0 * s * t * t) + p3 * (t * t * t);
+
+            self.process_line(p, prev, point, i > 1);
+            prev = point;
+        }
+    }
+
+    /// Flattens an elliptical arc into lines and processes them, continuing smoothly from the previous line
+    fn process_elliptical_arc(&mut self, p: Vec2<f32>, center: Vec2<f32>, radii: Vec2<f32>, start_angle: f32, sweep: f32) {
+        // Flattening error with n segments is r(1 - cos(sweep / 2n))
+        let max_radius = radii.x.max(radii.y).max(1e-6);
+        let max_step = 2.0 * clamp(1.0 - self.flatten_tolerance() / max_radius, -1.0, 1.0).acos();
+        let segments = clamp((sweep.abs() / max_step.max(1e-6)).ceil(), 1.0, MAX_ARC_SEGMENTS);
+
+        let point_at = |angle: f32| center + radii * Vec2::new(angle.cos(), angle.sin());
+        let mut prev = point_at(start_angle);
+
+        for i in 1..=segments as u32 {
+            let point = point_at(start_angle + sweep * i as f32 / segments);
+
+            self.process_line(p, prev, point, true);
+            prev = point;
+        }
+    }
+
+    /// Tangent arc from the cursor, in the same manner as the HTML canvas `arcTo()`, see `processArc()`
+    fn process_arc(&mut self, p: Vec2<f32>, corner: Vec2<f32>, end: Vec2<f32>, radius: f32) {
+        let d1 = (self.cursor - corner).normalized();
+        let d2 = (end - corner).normalized();
+        let cos_theta = d1.dot(d2);
+
+        // Degenerate arcs are just a line to the corner
+        if radius == 0.0 || self.cursor == corner || end == corner || cos_theta.abs() > 0.9999 {
+            self.process_line(p, self.cursor, corner, false);
+            self.cursor = corner;
+            return;
+        }
+
+        // Tangent points and arc center
+        let half_theta = cos_theta.acos() * 0.5;
+        let t1 = corner + d1 * radius / half_theta.tan();
+        let t2 = corner + d2 * radius / half_theta.tan();
+        let center = corner + (d1 + d2).normalized() * radius / half_theta.sin();
+
+        self.process_line(p, self.cursor, t1, false);
+
+        // The arc is flattened, so strokes can join, cap and dash it like any other line
+        let from = t1 - center;
+        let to = t2 - center;
+
+        self.process_elliptical_arc(p, center, Vec2::broadcast(radius), from.y.atan2(from.x), cross2(from, to).atan2(from.dot(to)));
+        self.cursor = t2;
+    }
+
+    /// Accumulates a closed shape's contribution to the fill from the signed distance to it, see `fillShape()`
+    fn fill_shape(&mut self, cmd: &CanvasCommand, canvas_center: Vec2<f32>) {
+        let dist = shape_dist(cmd, canvas_center);
+
+        if dist.abs() > self.pixel_radius {
+            if dist < 0.0 {
+                self.fill_pixel(self.shape_winding);
+            }
+
+            return;
+        }
+
+        if self.pass.antialiasing == Antialiasing::Supersampled {
+            for (i, winding) in self.sample_windings.iter_mut().enumerate() {
+                let s = self.window_to_canvas.apply(sample_pos(self.pixel_coord, i));
+
+                if shape_dist(cmd, s) < 0.0 {
+                    *winding += self.shape_winding as i32;
+                }
+            }
+
+            return;
+        }
+
+        self.coverage_area += self.shape_winding * clamp(0.5 - dist * self.transform_scale, 0.0, 1.0);
+    }
+
+    /// Checks if a closed shape's stroke, lying within the given bounding box, can be skipped entirely
+    fn can_skip_stroke_shape(&self, p: Vec2<f32>, box_min: Vec2<f32>, box_max: Vec2<f32>) -> bool {
+        box_dist(p, box_min, box_max) - self.stroke_extent() > self.min_dist
+    }
+
+    /// Adds the part of a dash lying on a rounded outline, see `strokeOutlineDash()`
+    #[allow(clippy::too_many_arguments)]
+    fn stroke_outline_dash(&mut self, p: Vec2<f32>, outline: &RoundedOutline, dist: f32, along: f32, stretch: f32, len: f32, dash: Vec2<f32>) {
+        if dash.x > len || dash.y < 0.0 {
+            return;
+        }
+
+        let mut span = dash.map(|c| clamp(c, 0.0, len));
+
+        // Dashes running across the outline's start reach back past it if its end lies within a
+        // dash, so the edge where the outline meets itself is covered
+        if dash.x <= 0.0 && dash.y > 0.0 && self.in_dash(len) {
+            span.x = -self.stroke_overlap();
+        }
+
+        if span.y > span.x {
+            let d = Vec2::new((span.x - along).max(along - span.y) * stretch, dist.abs() - 0.5 * self.stroke_width);
+
+            self.min_dist = self.min_dist.min(max2(d, Vec2::zero()).magnitude() + d.x.max(d.y).min(0.0));
+        }
+
+        if dash.x > 0.0 {
+            let (point, tangent) = outline.point(dash.x);
+
+            self.stroke_cap(p, point, -tangent);
+        }
+
+        if dash.y < len {
+            let (point, tangent) = outline.point(dash.y);
+
+            self.stroke_cap(p, point, tangent);
+        }
+    }
+
+    /// Adds a rounded outline to the stroke from the exact distance to it, see `strokeOutline()`
+    fn stroke_outline(&mut self, p: Vec2<f32>, outline: &RoundedOutline) {
+        if self.can_skip_stroke_shape(p, outline.core_min - outline.radius, outline.core_max + outline.radius) {
+            return;
+        }
+
+        let (dist, along, stretch) = outline.dist(p);
+
+        if self.num_dashes == 0 {
+            self.min_dist = self.min_dist.min(dist.abs() - 0.5 * self.stroke_width);
+            return;
+        }
+
+        let len = outline.len();
+
+        // Only the dashes around the closest point can cover the pixel, including those across the
+        // point where the outline meets itself
+        let wrapped = if along < 0.5 * len { along + len } else { along - len };
+
+        for along in [along, wrapped] {
+            let (dash, next_dash) = self.find_dashes(along);
+
+            self.stroke_outline_dash(p, outline, dist, along, stretch, len, dash);
+            self.stroke_outline_dash(p, outline, dist, along, stretch, len, next_dash);
+        }
+    }
+
+    /// Adds a circle's outline to the stroke, starting from its rightmost point and going clockwise
+    fn stroke_circle(&mut self, p: Vec2<f32>, center: Vec2<f32>, radius: f32) {
+        let radius = radius.abs();
+
+        // Like any other zero length path, circles without a radius aren't stroked
+        if radius > 0.0 {
+            self.stroke_outline(p, &RoundedOutline {
+                core_min: center,
+                core_max: center,
+                radius,
+                start_normal: Vec2::new(1.0, 0.0)
+            });
+        }
+    }
+
+    /// Adds an ellipse's flattened outline to the stroke, starting from its rightmost point and
+    /// going clockwise, see `strokeEllipse()`
+    fn stroke_ellipse(&mut self, p: Vec2<f32>, center: Vec2<f32>, radii: Vec2<f32>) {
+        let radii = radii.map(f32::abs);
+
+        if self.can_skip_stroke_shape(p, center - radii, center + radii) {
+            return;
+        }
+
+        let prev_dir = self.prev_dir;
+        let path_length = self.path_length;
+
+        // The outline is closed, so its start is joined to its end rather than capped
+        self.prev_dir = Vec2::new(0.0, 1.0);
+        self.path_length = 0.0;
+
+        self.process_elliptical_arc(p, center, radii, 0.0, TAU);
+
+        self.prev_dir = prev_dir;
+        self.path_length = path_length;
+    }
+
+    /// Adds a rounded rectangle's outline to the stroke, starting from the left end of its top edge
+    /// and going clockwise, unrounded rectangles are stroked as four lines, see `strokeRoundedRect()`
+    fn stroke_rounded_rect(&mut self, p: Vec2<f32>, top_left: Vec2<f32>, size: Vec2<f32>, radius: f32) {
+        let box_min = min2(top_left, top_left + size);
+        let box_max = max2(top_left, top_left + size);
+        let radius = clamp(radius, 0.0, 0.5 * (box_max.x - box_min.x).min(box_max.y - box_min.y));
+
+        if radius > 0.0 {
+            self.stroke_outline(p, &RoundedOutline {
+                core_min: box_min + radius,
+                core_max: box_max - radius,
+                radius,
+                start_normal: Vec2::new(0.0, -1.0)
+            });
+
+            return;
+        }
+
+        if self.can_skip_stroke_shape(p, box_min, box_max) {
+            return;
+        }
+
+        let prev_dir = self.prev_dir;
+        let path_length = self.path_length;
+
+        self.prev_dir = Vec2::new(0.0, -1.0);
+        self.path_length = 0.0;
+
+        self.process_line(p, box_min, Vec2::new(box_max.x, box_min.y), false);
+        self.process_line(p, Vec2::new(box_max.x, box_min.y), box_max, false);
+        self.process_line(p, box_max, Vec2::new(box_min.x, box_max.y), false);
+        self.process_line(p, Vec2::new(box_min.x, box_max.y), box_min, false);
+
+        self.prev_dir = prev_dir;
+        self.path_length = path_length;
+    }
+
+    /// Ends the current subpath, closing it if it's filled or capping it if it's stroked
+    fn end_subpath(&mut self, p: Vec2<f32>) {
+        if self.mode == Mode::Fill {
+            if self.cursor != self.subpath_start {
+                self.process_line(p, self.cursor, self.subpath_start, false);
+            }
+        }
+        else if self.prev_dir != Vec2::zero() && self.in_dash(self.path_length) {
+            self.stroke_cap(p, self.cursor, self.prev_dir);
+        }
+
+        self.prev_dir = Vec2::zero();
+        self.path_length = 0.0;
+    }
+
+    /// Checks if a winding number is inside the fill according to the contour's fill rule
+    fn inside_fill(&self, winding_num: i32) -> bool {
+        if self.fill_rule == FillRule::EvenOdd as u32 {
+            return winding_num.abs() & 1 != 0;
+        }
+
+        winding_num != 0
+    }
+
+    /// Fraction of the pixel covered by the current contour, see `contourCoverage()`
+    fn contour_coverage(&self) -> f32 {
+        if self.transform_scale == 0.0 {
+            return 0.0;
+        }
+
+        // Strokes track the signed distance to their outline, which is scaled to pixels for antialiasing
+        if self.mode == Mode::Stroke {
+            return 1.0 - smoothstep(-1.0, 0.0, self.min_dist * self.transform_scale);
+        }
+
+        if self.pass.antialiasing == Antialiasing::Supersampled {
+            let covered = self.sample_windings.iter().filter(|&&winding| self.inside_fill(winding)).count();
+
+            return covered as f32 / NUM_SUPERSAMPLES as f32;
+        }
+
+        let area = self.coverage_area.abs();
+
+        // Alternates between covered and uncovered with each winding
+        if self.fill_rule == FillRule::EvenOdd as u32 {
+            return 1.0 - (modulo(area, 2.0) - 1.0).abs();
+        }
+
+        area.min(1.0)
+    }
+
+    /// Samples an image paint, with its data stored in data commands starting at `paint_idx`
+    fn eval_image(&self, p: Vec2<f32>) -> Rgba<f32> {
+        let header = self.cmd(self.paint_idx);
+        let tex_coord = unpack_transform(self.cmd(self.paint_idx + 1)).apply(p);
+
+        let texture = self.pass.textures
+            .get(header.param1.x as usize)
+            .and_then(Option::as_ref)
+            .unwrap_or(self.pass.placeholder_texture);
+
+        // Samplers are indexed by filter * 3 + extend
+        let filter = if header.param1.y / 3 == FilterMode::Linear as u32 { FilterMode::Linear } else { FilterMode::Nearest };
+        let extend = match header.param1.y % 3 {
+            1 => ExtendMode::Repeat,
+            2 => ExtendMode::Reflect,
+            _ => ExtendMode::Pad
+        };
+
+        let texel = texture.sample(tex_coord, filter, extend);
+
+        Rgba::new(texel.r * texel.a, texel.g * texel.a, texel.b * texel.a, texel.a)
+    }
+
+    /// Evaluates the color of the contour's paint at a pixel, in linear light with premultiplied alpha
+    fn eval_paint(&self, p: Vec2<f32>) -> Rgba<f32> {
+        if self.paint_type == PaintType::Solid as u32 {
+            return self.draw_color;
+        }
+
+        if self.paint_type == PaintType::Image as u32 {
+            return self.eval_image(p);
+        }
+
+        let header = self.cmd(self.paint_idx);
+        let geometry1 = unpack_point(header.param1);
+        let geometry2 = unpack_point(header.param2);
+        let num_stops = header.param3.y as usize;
+
+        // Gradient parameter
+        let t = if self.paint_type == PaintType::LinearGradient as u32 {
+            let dir = geometry2 - geometry1;
+
+            (p - geometry1).dot(dir) / dir.dot(dir).max(1e-6)
+        }
+        else if self.paint_type == PaintType::RadialGradient as u32 {
+            (p - geometry1).magnitude() / geometry2.x.max(1e-6)
+        }
+        else {
+            let pc = p - geometry1;
+
+            fract((pc.y.atan2(pc.x) - geometry2.x) / TAU)
+        };
+
+        let t = apply_extend(t, header.param3.x);
+
+        // Find the pair of stops around t and interpolate between them
+        let mut color = Rgba::zero();
+        let mut prev_offset = 0.0;
+
+        for i in 0..num_stops {
+            let stop = self.cmd(self.paint_idx + 1 + i);
+            let offset = f32::from_bits(stop.param1.x);
+            let stop_color = unpack_color(stop.param2.x);
+
+            if i == 0 || t <= prev_offset {
+                color = stop_color;
+            }
+            else {
+                color = color + (stop_color - color) * clamp((t - prev_offset) / (offset - prev_offset).max(1e-6), 0.0, 1.0);
+            }
+
+            if t <= offset {
+                break;
+            }
+
+            prev_offset = offset;
+        }
+
+        color
+    }
+
+    /// Processes the commands of a single path, starting at its contour start, pop clip, push layer,
+    /// pop layer or box shadow command
+    fn process_path(&mut self, start_idx: usize, canvas_coord: Vec2<f32>, canvas_center: Vec2<f32>) {
+        for i in start_idx.. {
+            let cmd = self.cmd(i);
+
+            match cmd.opcode {
+                CanvasOp::StartFill => {
+                    self.mode = Mode::Fill;
+                    self.cursor = unpack_point(cmd.param1);
+                    self.subpath_start = self.cursor;
+                    self.draw_color = unpack_color(cmd.param2.x);
+                    self.blend_mode = cmd.param2.y;
+                    self.fill_rule = cmd.param3.x;
+                    self.paint_type = cmd.param3.y;
+                    self.paint_idx = i + 1;
+                    self.min_dist = INIT_MIN_DIST;
+                    self.reset_fill();
+                    self.clip_contour = false;
+                },
+
+                CanvasOp::StartStroke => {
+                    self.mode = Mode::Stroke;
+                    self.cursor = unpack_point(cmd.param1);
+                    self.subpath_start = self.cursor;
+                    self.draw_color = unpack_color(cmd.param2.x);
+                    self.blend_mode = cmd.param2.y;
+                    self.stroke_width = f32::from_bits(cmd.param3.x);
+                    self.paint_type = cmd.param3.y;
+                    self.min_dist = INIT_MIN_DIST;
+                    self.prev_dir = Vec2::zero();
+                    self.path_length = 0.0;
+
+                    // Stroke style data comes before the paint data
+                    let style = self.cmd(i + 1);
+
+                    self.line_join = style.param1.x;
+                    self.line_cap = style.param1.y;
+                    self.miter_limit = f32::from_bits(style.param2.x);
+                    self.dash_offset = f32::from_bits(style.param2.y);
+                    self.num_dashes = style.param3.x as usize;
+                    self.dash_length = f32::from_bits(style.param3.y);
+                    self.dash_idx = i + 2;
+                    self.paint_idx = i + 2 + self.num_dashes;
+                    self.clip_contour = false;
+                },
+
+                CanvasOp::StartClip => {
+                    self.mode = Mode::Fill;
+                    self.cursor = unpack_point(cmd.param1);
+                    self.subpath_start = self.cursor;
+                    self.fill_rule = cmd.param3.x;
+                    self.min_dist = INIT_MIN_DIST;
+                    self.reset_fill();
+                    self.clip_contour = true;
+                },
+
+                CanvasOp::MoveTo => {
+                    self.end_subpath(canvas_coord);
+
+                    self.cursor = unpack_point(cmd.param1);
+                    self.subpath_start = self.cursor;
+                },
+
+                CanvasOp::LineTo => {
+                    let line_end = unpack_point(cmd.param1);
+
+                    self.process_line(canvas_coord, self.cursor, line_end, false);
+                    self.cursor = line_end;
+                },
+
+                CanvasOp::QuadTo => {
+                    let curve_end = unpack_point(cmd.param2);
+
+                    self.process_quad(canvas_coord, self.cursor, unpack_point(cmd.param1), curve_end);
+                    self.cursor = curve_end;
+                },
+
+                CanvasOp::CubicTo => {
+                    let curve_end = unpack_point(cmd.param3);
+
+                    self.process_cubic(canvas_coord, self.cursor, unpack_point(cmd.param1), unpack_point(cmd.param2), curve_end);
+                    self.cursor = curve_end;
+                },
+
+                CanvasOp::ArcTo => {
+                    self.process_arc(canvas_coord, unpack_point(cmd.param1), unpack_point(cmd.param2), f32::from_bits(cmd.param3.x));
+                },
+
+                // Circles, ellipses and rounded rectangles are filled from the distance to them
+                CanvasOp::Circle | CanvasOp::Ellipse | CanvasOp::RoundedRect if self.mode == Mode::Fill => {
+                    self.fill_shape(cmd, canvas_center);
+                },
+
+                CanvasOp::Circle => self.stroke_circle(canvas_coord, unpack_point(cmd.param1), f32::from_bits(cmd.param2.x)),
+
+                CanvasOp::Ellipse => self.stroke_ellipse(canvas_coord, unpack_point(cmd.param1), unpack_point(cmd.param2)),
+
+                CanvasOp::RoundedRect => {
+                    self.stroke_rounded_rect(canvas_coord, unpack_point(cmd.param1), unpack_point(cmd.param2), f32::from_bits(cmd.param3.x));
+                },
+
+                // End of contour, composite the paint over the pixel color or push the clip
+                CanvasOp::EndContour => {
+                    self.end_subpath(canvas_coord);
+
+                    let coverage = self.contour_coverage();
+
+                    if self.clip_contour {
+                        self.clip_stack[self.clip_depth] = self.clip_coverage;
+                        self.clip_coverage *= coverage;
+                        self.clip_depth += 1;
+                    }
+                    else {
+                        let coverage = coverage * self.clip_coverage;
+
+                        if coverage > 0.0 {
+                            let paint = self.eval_paint(canvas_center);
+
+                            self.color = color::composite(paint, self.color, coverage, unpack_blend_mode(self.blend_mode));
+                        }
+                    }
+
+                    return;
+                },
+
+                CanvasOp::PopClip => {
+                    self.clip_depth -= 1;
+                    self.clip_coverage = self.clip_stack[self.clip_depth];
+                    return;
+                },
+
+                // Push a layer, saving the pixel color as its backdrop and starting out transparent
+                CanvasOp::PushLayer => {
+                    if self.layer_depth < MAX_LAYER_DEPTH {
+                        self.layer_backdrops[self.layer_depth] = self.color;
+                        self.layer_clip_coverages[self.layer_depth] = self.clip_coverage;
+
+                        self.color = Rgba::zero();
+                        self.clip_coverage = 1.0;
+
+                        // A blurred layer's contents were rasterized by their own pass, so they're skipped
+                        if cmd.param1.x != NO_BLUR_LAYER {
+                            self.skip_paths_until = cmd.param1.y;
+                        }
+                    }
+
+                    self.layer_depth += 1;
+                    return;
+                },
+
+                // Pop the innermost layer, compositing it onto its backdrop
+                CanvasOp::PopLayer => {
+                    self.layer_depth -= 1;
+
+                    if self.layer_depth < MAX_LAYER_DEPTH {
+                        let backdrop = self.layer_backdrops[self.layer_depth];
+                        let opacity = f32::from_bits(cmd.param1.x);
+
+                        if cmd.param2.x != NO_BLUR_LAYER {
+                            self.color = self.pass.layer_images[cmd.param2.x as usize + 1][self.pixel_idx];
+                        }
+
+                        self.clip_coverage = self.layer_clip_coverages[self.layer_depth];
+                        self.color = color::composite(self.color * opacity, backdrop, self.clip_coverage, unpack_blend_mode(cmd.param1.y));
+                    }
+
+                    return;
+                },
+
+                CanvasOp::BoxShadow => {
+                    let data = self.cmd(i + 1);
+
+                    if self.transform_scale > 0.0 {
+                        // Blurs narrower than half a pixel are widened, antialiasing the edges of sharp shadows
+                        let std_dev = f32::from_bits(cmd.param3.y).max(0.5 / self.transform_scale);
+                        let coverage = self.clip_coverage * box_shadow(
+                            canvas_center,
+                            unpack_point(cmd.param1),
+                            unpack_point(cmd.param2),
+                            f32::from_bits(cmd.param3.x),
+                            std_dev
+                        );
+
+                        if coverage > 0.0 {
+                            self.color = color::composite(unpack_color(data.param1.x), self.color, coverage, unpack_blend_mode(data.param1.y));
+                        }
+                    }
+
+                    return;
+                },
+
+                CanvasOp::Data | CanvasOp::SetTransform | CanvasOp::LastCommand => ()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::env;
+    use std::path::PathBuf;
+
+    use super::*;
+    use super::super::paint::{Paint, ColorStop};
+    use super::super::stroke::StrokeStyle;
+    use super::super::scene::CanvasPicture;
+
+    /// Compares rendered pixels with a golden image in the `golden` directory next to this file,
+    /// allowing each component to be off by one for differences in float rounding
+    ///
+    /// Golden images are raw RGBA8 pixels, running the tests with `UPDATE_GOLDEN=1` rewrites them
+    /// with what's rendered
+    fn check_golden(name: &str, width: u32, pixels: &[u8]) {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "src", "renderer", "canvas_2d", "golden", &format!("{name}.rgba")]
+            .iter()
+            .collect();
+
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, pixels).unwrap();
+            return;
+        }
+
+        let golden = fs::read(&path).unwrap_or_else(|err| panic!("Failed to read {}: {err}", path.display()));
+        assert_eq!(pixels.len(), golden.len(), "{name} is a different size to its golden image");
+
+        let mismatch = pixels
+            .chunks_exact(4)
+            .zip(golden.chunks_exact(4))
+            .position(|(pixel, expected)| pixel.iter().zip(expected).any(|(&a, &b)| a.abs_diff(b) > 1));
+
+        if let Some(idx) = mismatch {
+            let (x, y) = (idx as u32 % width, idx as u32 / width);
+            let (pixel, expected) = (&pixels[idx * 4..idx * 4 + 4], &golden[idx * 4..idx * 4 + 4]);
+
+            panic!("{name} differs from its golden image at ({x}, {y}): {pixel:?} != {expected:?}");
+        }
+    }
+
+    /// The sRGB encoded pixel at a position
+    fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let idx = (y * width + x) as usize * 4;
+
+        pixels[idx..idx + 4].try_into().unwrap()
+    }
+
+    /// Two nested squares wound the same way, so the inner one is a hole with the even-odd rule only
+    fn nested_squares(
+        recorder: Canvas2DRecorder<InitState>,
+        offset: Vec2<f32>,
+        fill_rule: FillRule
+    ) -> Canvas2DRecorder<InitState> {
+        let square = |recorder: Canvas2DRecorder<_>, min: f32, max: f32| {
+            recorder
+                .move_to(offset + Vec2::new(min, min))
+                .line_to(offset + Vec2::new(max, min))
+                .line_to(offset + Vec2::new(max, max))
+                .line_to(offset + Vec2::new(min, max))
+        };
+
+        let recorder = recorder.start_fill(offset, Rgba::new(40, 200, 120, 255), fill_rule);
+        let recorder = square(recorder, 2.5, 21.5);
+
+        square(recorder, 7.5, 16.5).end()
+    }
+
+    #[test]
+    fn fill_rules() {
+        let mut rasterizer = CpuRasterizer::new();
+
+        let pixels = rasterizer.render(48, 24, |canvas_2d| {
+            let canvas_2d = nested_squares(canvas_2d, Vec2::zero(), FillRule::NonZero);
+            nested_squares(canvas_2d, Vec2::new(24.0, 0.0), FillRule::EvenOdd)
+        }).unwrap();
+
+        assert_eq!(pixel(&pixels, 48, 12, 12), [40, 200, 120, 255]);
+        assert_eq!(pixel(&pixels, 48, 36, 12), [0, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 48, 28, 12), [40, 200, 120, 255]);
+
+        check_golden("fill_rules", 48, &pixels);
+    }
+
+    #[test]
+    fn stroke_styles() {
+        let mut rasterizer = CpuRasterizer::new();
+
+        let joined = StrokeStyle {
+            join: LineJoin::Round,
+            cap: LineCap::Square,
+            ..StrokeStyle::new(4.0)
+        };
+
+        let dashed = StrokeStyle {
+            join: LineJoin::Miter,
+            cap: LineCap::Round,
+            dashes: &[6.0, 4.0],
+            dash_offset: 2.0,
+            ..StrokeStyle::new(2.0)
+        };
+
+        let pixels = rasterizer.render(32, 32, |canvas_2d| {
+            canvas_2d
+                .start_stroke(Vec2::new(4.0, 14.0), Rgba::new(255, 200, 60, 255), joined)
+                .line_to(Vec2::new(16.0, 4.0))
+                .line_to(Vec2::new(28.0, 14.0))
+                .end()
+                .start_stroke(Vec2::new(4.0, 20.0), Rgba::new(100, 180, 255, 255), dashed)
+                .line_to(Vec2::new(28.0, 20.0))
+                .line_to(Vec2::new(28.0, 28.0))
+                .line_to(Vec2::new(8.0, 28.0))
+                .end()
+        }).unwrap();
+
+        check_golden("stroke_styles", 32, &pixels);
+    }
+
+    #[test]
+    fn translucent_overlap() {
+        let mut rasterizer = CpuRasterizer::new();
+
+        let pixels = rasterizer.render(32, 24, |canvas_2d| {
+            canvas_2d
+                .start_fill(Vec2::zero(), Rgba::new(255, 0, 0, 128), FillRule::NonZero)
+                .circle(Vec2::new(12.0, 12.0), 9.0)
+                .end()
+                .start_fill(Vec2::zero(), Rgba::new(0, 0, 255, 128), FillRule::NonZero)
+                .circle(Vec2::new(20.0, 12.0), 9.0)
+                .end()
+        }).unwrap();
+
+        // Blended in linear light, then encoded back to sRGB
+        let expected = color::encode_color(color::blend(
+            color::decode_color(Rgba::new(0, 0, 255, 128)),
+            color::blend(color::decode_color(Rgba::new(255, 0, 0, 128)), Rgba::new(0.0, 0.0, 0.0, 1.0), BlendMode::SrcOver),
+            BlendMode::SrcOver
+        ));
+
+        assert_eq!(pixel(&pixels, 32, 16, 12), expected.into_array());
+
+        check_golden("translucent_overlap", 32, &pixels);
+    }
+
+    #[test]
+    fn blurred_layer() {
+        let mut rasterizer = CpuRasterizer::new();
+
+        let pixels = rasterizer.render(32, 32, |canvas_2d| {
+            canvas_2d
+                .push_blur_layer(1.0, BlendMode::SrcOver, 6.0)
+                .start_fill(Vec2::zero(), Rgba::new(255, 255, 255, 255), FillRule::NonZero)
+                .rounded_rect(Vec2::new(10.0, 10.0), Vec2::new(12.0, 12.0), 0.0)
+                .end()
+                .pop_layer()
+        }).unwrap();
+
+        // The blur spreads the square past its edges, and softens its center
+        assert_ne!(pixel(&pixels, 32, 7, 16), [0, 0, 0, 255]);
+        assert_ne!(pixel(&pixels, 32, 16, 16), [255, 255, 255, 255]);
+
+        check_golden("blurred_layer", 32, &pixels);
+    }
+
+    #[test]
+    fn nested_clips() {
+        let mut rasterizer = CpuRasterizer::new();
+
+        let pixels = rasterizer.render(32, 32, |canvas_2d| {
+            canvas_2d
+                .push_clip_path(Vec2::zero(), FillRule::NonZero)
+                .circle(Vec2::new(16.0, 16.0), 13.0)
+                .end()
+                .push_clip_rect(Vec2::new(8.0, 4.0), Vec2::new(20.0, 16.0))
+                .start_fill(Vec2::new(0.0, 0.0), Rgba::new(200, 60, 160, 255), FillRule::NonZero)
+                .line_to(Vec2::new(32.0, 0.0))
+                .line_to(Vec2::new(32.0, 32.0))
+                .line_to(Vec2::new(0.0, 32.0))
+                .end()
+                .pop_clip()
+                .pop_clip()
+        }).unwrap();
+
+        // Only the part inside both the circle and the rectangle is drawn
+        assert_eq!(pixel(&pixels, 32, 16, 12), [200, 60, 160, 255]);
+        assert_eq!(pixel(&pixels, 32, 16, 26), [0, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 32, 5, 12), [0, 0, 0, 255]);
+
+        check_golden("nested_clips", 32, &pixels);
+    }
+
+    #[test]
+    fn clip_depth_limit() {
+        let mut rasterizer = CpuRasterizer::new();
+        let clip = |size: f32| (Vec2::new(8.0 - size, 0.0), Vec2::new(size * 2.0, 16.0));
+
+        // Each clip is narrower than the one it's nested in, so the innermost one decides what's drawn
+        let pixels = rasterizer.render(16, 16, |canvas_2d| {
+            let (p0, s0) = clip(8.0);
+            let (p1, s1) = clip(7.0);
+            let (p2, s2) = clip(6.0);
+            let (p3, s3) = clip(5.0);
+            let (p4, s4) = clip(4.0);
+            let (p5, s5) = clip(3.0);
+            let (p6, s6) = clip(2.0);
+            let (p7, s7) = clip(1.0);
+
+            canvas_2d
+                .push_clip_rect(p0, s0).push_clip_rect(p1, s1).push_clip_rect(p2, s2).push_clip_rect(p3, s3)
+                .push_clip_rect(p4, s4).push_clip_rect(p5, s5).push_clip_rect(p6, s6).push_clip_rect(p7, s7)
+                .start_fill(Vec2::zero(), Rgba::new(255, 255, 255, 255), FillRule::NonZero)
+                .rounded_rect(Vec2::zero(), Vec2::new(16.0, 16.0), 0.0)
+                .end()
+                .pop_clip().pop_clip().pop_clip().pop_clip().pop_clip().pop_clip().pop_clip().pop_clip()
+        }).unwrap();
+
+        assert_eq!(pixel(&pixels, 16, 7, 8), [255, 255, 255, 255]);
+        assert_eq!(pixel(&pixels, 16, 6, 8), [0, 0, 0, 255]);
+
+        // A ninth clip doesn't fit, whether it's pushed directly or by a picture
+        let result = rasterizer.render(16, 16, |canvas_2d| {
+            canvas_2d
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0)).push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0)).push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0)).push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0)).push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .pop_clip().pop_clip().pop_clip().pop_clip().pop_clip().pop_clip().pop_clip().pop_clip().pop_clip()
+        });
+
+        assert!(result.is_err());
+
+        let picture = CanvasPicture::record(|canvas_2d| {
+            canvas_2d
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .pop_clip()
+                .pop_clip()
+        }).unwrap();
+
+        let result = rasterizer.render(16, 16, |canvas_2d| {
+            canvas_2d
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0)).push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0)).push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0)).push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .push_clip_rect(Vec2::zero(), Vec2::new(16.0, 16.0))
+                .draw_picture(&picture)
+                .pop_clip().pop_clip().pop_clip().pop_clip().pop_clip().pop_clip().pop_clip()
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn gradients() {
+        let mut rasterizer = CpuRasterizer::new();
+
+        let stops = [
+            ColorStop::new(0.0, Rgba::new(255, 0, 100, 255)),
+            ColorStop::new(0.5, Rgba::new(255, 255, 0, 255)),
+            ColorStop::new(1.0, Rgba::new(0, 150, 255, 255))
+        ];
+
+        let linear = Paint::LinearGradient {
+            start: Vec2::new(4.0, 0.0),
+            end: Vec2::new(28.0, 0.0),
+            stops: &stops,
+            extend: ExtendMode::Pad
+        };
+
+        let radial = Paint::RadialGradient {
+            center: Vec2::new(16.0, 24.0),
+            radius: 6.0,
+            stops: &stops,
+            extend: ExtendMode::Reflect
+        };
+
+        let pixels = rasterizer.render(32, 32, |canvas_2d| {
+            canvas_2d
+                .start_fill(Vec2::zero(), linear, FillRule::NonZero)
+                .rounded_rect(Vec2::new(0.0, 2.0), Vec2::new(32.0, 12.0), 0.0)
+                .end()
+                .start_fill(Vec2::zero(), radial, FillRule::NonZero)
+                .rounded_rect(Vec2::new(0.0, 16.0), Vec2::new(32.0, 16.0), 0.0)
+                .end()
+        }).unwrap();
+
+        // The linear gradient is padded with its end colors
+        assert_eq!(pixel(&pixels, 32, 1, 8), [255, 0, 100, 255]);
+        assert_eq!(pixel(&pixels, 32, 30, 8), [0, 150, 255, 255]);
+
+        check_golden("gradients", 32, &pixels);
+    }
+
+    #[test]
+    fn image_paint() {
+        let mut rasterizer = CpuRasterizer::new();
+
+        // Red and green on the top row, blue and translucent white on the bottom
+        let texels = [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 128];
+
+        let texture = rasterizer.register_texture(2, 2, &texels).unwrap();
+
+        let nearest = Paint::Image {
+            texture,
+            transform: Transform::scaling(Vec2::broadcast(4.0)),
+            filter: FilterMode::Nearest,
+            tiling: ExtendMode::Repeat
+        };
+
+        let linear = Paint::Image {
+            texture,
+            transform: Transform::scaling(Vec2::broadcast(8.0)).then(&Transform::translation(Vec2::new(16.0, 16.0))),
+            filter: FilterMode::Linear,
+            tiling: ExtendMode::Pad
+        };
+
+        let pixels = rasterizer.render(32, 32, |canvas_2d| {
+            canvas_2d
+                .start_fill(Vec2::zero(), nearest, FillRule::NonZero)
+                .rounded_rect(Vec2::zero(), Vec2::new(32.0, 16.0), 0.0)
+                .end()
+                .start_fill(Vec2::zero(), linear, FillRule::NonZero)
+                .rounded_rect(Vec2::new(0.0, 16.0), Vec2::new(32.0, 16.0), 0.0)
+                .end()
+        }).unwrap();
+
+        // Nearest filtering repeats the texels exactly
+        assert_eq!(pixel(&pixels, 32, 1, 1), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 32, 13, 1), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, 32, 9, 5), [0, 0, 255, 255]);
+
+        check_golden("image_paint", 32, &pixels);
+    }
+
+    #[test]
+    fn blend_modes() {
+        let mut rasterizer = CpuRasterizer::new();
+
+        let pixels = rasterizer.render(32, 32, |canvas_2d| {
+            canvas_2d
+                .start_fill(Vec2::zero(), Rgba::new(60, 120, 240, 255), FillRule::NonZero)
+                .rounded_rect(Vec2::new(2.0, 2.0), Vec2::new(28.0, 28.0), 0.0)
+                .end()
+                .set_blend_mode(BlendMode::Multiply)
+                .start_fill(Vec2::zero(), Rgba::new(255, 220, 80, 255), FillRule::NonZero)
+                .circle(Vec2::new(12.0, 12.0), 8.0)
+                .end()
+                .set_blend_mode(BlendMode::Screen)
+                .start_fill(Vec2::zero(), Rgba::new(200, 40, 40, 255), FillRule::NonZero)
+                .circle(Vec2::new(20.0, 20.0), 8.0)
+                .end()
+                .set_blend_mode(BlendMode::DstOut)
+                .start_fill(Vec2::zero(), Rgba::new(0, 0, 0, 255), FillRule::NonZero)
+                .circle(Vec2::new(24.0, 8.0), 4.0)
+                .end()
+        }).unwrap();
+
+        let base = color::decode_color(Rgba::new(60, 120, 240, 255));
+        let multiplied = color::blend(color::decode_color(Rgba::new(255, 220, 80, 255)), base, BlendMode::Multiply);
+
+        assert_eq!(pixel(&pixels, 32, 10, 10), color::encode_color(multiplied).into_array());
+
+        check_golden("blend_modes", 32, &pixels);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,278 @@
+use std::collections::VecDeque;
+
+use vek::{Aabr, Rect, Vec2};
+
+use super::renderer::TILE_SIZE;
+
+/// Damaged tile rectangles dispatched separately, any more are merged into their bounding box
+const MAX_DAMAGE_RECTS: usize = 16;
+
+/// Frames of damage kept, swapchain images last drawn to longer ago than this are fully redrawn
+const DAMAGE_HISTORY_LEN: usize = 8;
+
+/// Rectangles of tiles that need to be redrawn, with exclusive maximums, or `None` if every tile does
+///
+/// The rectangles never overlap, so no pixel is drawn twice by the same pass
+pub(super) type TileDamage = Option<Vec<Aabr<u32>>>;
+
+/// Tracks the tiles changed by recent frames, to work out which tiles of a swapchain image are
+/// out of date when it's drawn to again
+///
+/// Swapchain images keep what was last drawn to them, so only the tiles changed since then, by
+/// the frames drawn to the other images in the meantime and by the current frame, are redrawn
+pub(super) struct DamageTracker {
+    /// Tiles changed by each of the most recent frames, oldest first
+    history: VecDeque<TileDamage>,
+
+    /// Number of frames drawn
+    frame_count: u64,
+
+    /// Frame each swapchain image was last drawn by, if it's been drawn to
+    swap_image_frames: Vec<Option<u64>>,
+
+    /// Size in tiles of the images drawn by the most recent frame, once it changes none of them
+    /// hold anything worth keeping
+    num_tiles: [u32; 2]
+}
+
+impl DamageTracker {
+    pub fn new(num_swap_images: usize) -> Self {
+        Self {
+            history: VecDeque::with_capacity(DAMAGE_HISTORY_LEN),
+            frame_count: 0,
+            swap_image_frames: vec![None; num_swap_images],
+            num_tiles: [0, 0]
+        }
+    }
+
+    /// Forgets what every swapchain image holds, so each is fully redrawn the next time it's drawn to
+    pub fn invalidate(&mut self) {
+        self.swap_image_frames.fill(None);
+    }
+
+    /// Records a frame drawn to a swapchain image of `num_tiles` tiles, changing the given tiles,
+    /// and returns the tiles of the image that need to be redrawn
+    ///
+    /// If the size changed, such as when the window is resized, every image is fully redrawn
+    pub fn next_frame(&mut self, swap_image_idx: usize, num_tiles: [u32; 2], damage: TileDamage) -> TileDamage {
+        if num_tiles != self.num_tiles {
+            self.num_tiles = num_tiles;
+            self.history.clear();
+            self.invalidate();
+        }
+
+        if self.history.len() == DAMAGE_HISTORY_LEN {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(damage);
+
+        let frame = self.frame_count;
+        self.frame_count += 1;
+
+        // Frames since the image was last drawn to, including this one
+        let age = match self.swap_image_frames[swap_image_idx].replace(frame) {
+            Some(last_frame) => (frame - last_frame) as usize,
+            None => return None
+        };
+
+        if age > self.history.len() {
+            return None;
+        }
+
+        let mut redraw = Vec::new();
+
+        for damage in self.history.iter().skip(self.history.len() - age) {
+            redraw.extend_from_slice(damage.as_ref()?);
+        }
+
+        merge_rects(&mut redraw);
+
+        Some(redraw)
+    }
+}
+
+/// Converts window space rectangles to the tiles they touch, out of a grid of `num_tiles` tiles
+pub(super) fn tile_rects(rects: &[Rect<f32, f32>], num_tiles: [u32; 2]) -> Vec<Aabr<u32>> {
+    let to_tile = |p: f32, round: fn(f32) -> f32, axis: usize| {
+        (round(p / TILE_SIZE as f32).max(0.0) as u32).min(num_tiles[axis])
+    };
+
+    let mut tiles = rects
+        .iter()
+        .map(|rect| Aabr {
+            min: Vec2::new(to_tile(rect.x, f32::floor, 0), to_tile(rect.y, f32::floor, 1)),
+            max: Vec2::new(to_tile(rect.x + rect.w, f32::ceil, 0), to_tile(rect.y + rect.h, f32::ceil, 1))
+        })
+        .filter(|tiles| tiles.min.x < tiles.max.x && tiles.min.y < tiles.max.y)
+        .collect();
+
+    merge_rects(&mut tiles);
+    tiles
+}
+
+/// Grows each rectangle by `margin` tiles in every direction, within a grid of `num_tiles` tiles
+pub(super) fn expand_rects(rects: &[Aabr<u32>], margin: u32, num_tiles: [u32; 2]) -> Vec<Aabr<u32>> {
+    let max = Vec2::from(num_tiles);
+
+    let mut expanded = rects
+        .iter()
+        .map(|rect| Aabr {
+            min: rect.min.map(|t| t.saturating_sub(margin)),
+            max: (rect.max + margin).map2(max, u32::min)
+        })
+        .collect();
+
+    merge_rects(&mut expanded);
+    expanded
+}
+
+/// Merges overlapping rectangles into their bounding boxes till none overlap, and merges them
+/// all into one if there's too many
+fn merge_rects(rects: &mut Vec<Aabr<u32>>) {
+    let overlaps = |a: &Aabr<u32>, b: &Aabr<u32>| {
+        a.min.x < b.max.x && b.min.x < a.max.x && a.min.y < b.max.y && b.min.y < a.max.y
+    };
+
+    let mut i = 0;
+
+    while i < rects.len() {
+        match (i + 1..rects.len()).find(|&j| overlaps(&rects[i], &rects[j])) {
+            Some(j) => {
+                // The merged rectangle may overlap ones already checked, so start over
+                let other = rects.swap_remove(j);
+                rects[i] = rects[i].union(other);
+                i = 0;
+            },
+
+            None => i += 1
+        }
+    }
+
+    if rects.len() > MAX_DAMAGE_RECTS {
+        let bounds = rects.iter().copied().reduce(Aabr::union).unwrap();
+
+        rects.clear();
+        rects.push(bounds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aabr(min: [u32; 2], max: [u32; 2]) -> Aabr<u32> {
+        Aabr { min: Vec2::from(min), max: Vec2::from(max) }
+    }
+
+    #[test]
+    fn overlapping_rects_merge() {
+        // The third only overlaps the first two once they're merged
+        let mut rects = vec![aabr([0, 0], [4, 4]), aabr([3, 3], [6, 6]), aabr([5, 0], [8, 2])];
+        merge_rects(&mut rects);
+
+        assert_eq!(rects, [aabr([0, 0], [8, 6])]);
+    }
+
+    #[test]
+    fn adjacent_rects_stay_separate() {
+        // Rects sharing an edge don't overlap, so no tile is drawn twice without merging them
+        let mut rects = vec![aabr([0, 0], [2, 2]), aabr([2, 0], [4, 2]), aabr([0, 2], [2, 4])];
+        merge_rects(&mut rects);
+
+        assert_eq!(rects, [aabr([0, 0], [2, 2]), aabr([2, 0], [4, 2]), aabr([0, 2], [2, 4])]);
+    }
+
+    #[test]
+    fn too_many_rects_merge_into_bounds() {
+        let mut rects: Vec<_> = (0..MAX_DAMAGE_RECTS as u32 + 1).map(|i| aabr([i * 2, 1], [i * 2 + 1, 2])).collect();
+        merge_rects(&mut rects);
+
+        assert_eq!(rects, [aabr([0, 1], [MAX_DAMAGE_RECTS as u32 * 2 + 1, 2])]);
+    }
+
+    #[test]
+    fn tile_rects_are_clamped_to_the_grid() {
+        let tile = TILE_SIZE as f32;
+
+        let rects = [
+            // Partly off the top left and bottom right
+            Rect::new(-tile * 2.0, -0.5, tile * 3.0, tile),
+            Rect::new(tile * 3.5, tile * 2.5, tile * 10.0, tile * 10.0),
+
+            // Entirely off the grid, and empty
+            Rect::new(tile * 20.0, 0.0, tile, tile),
+            Rect::new(tile, tile, 0.0, 0.0)
+        ];
+
+        assert_eq!(tile_rects(&rects, [5, 4]), [aabr([0, 0], [1, 1]), aabr([3, 2], [5, 4])]);
+    }
+
+    #[test]
+    fn expanded_rects_are_clamped_and_merged() {
+        let rects = [aabr([0, 0], [1, 1]), aabr([3, 3], [4, 4])];
+
+        assert_eq!(expand_rects(&rects, 1, [5, 5]), [aabr([0, 0], [2, 2]), aabr([2, 2], [5, 5])]);
+        assert_eq!(expand_rects(&rects, 2, [5, 5]), [aabr([0, 0], [5, 5])]);
+    }
+
+    #[test]
+    fn images_redraw_damage_since_they_were_drawn() {
+        let mut tracker = DamageTracker::new(3);
+        let damage = |i: u32| Some(vec![aabr([i, 0], [i + 1, 1])]);
+
+        // Images are fully redrawn the first time they're drawn to
+        assert_eq!(tracker.next_frame(0, [8, 8], damage(0)), None);
+        assert_eq!(tracker.next_frame(1, [8, 8], damage(1)), None);
+
+        // Image 0 is drawn to again before image 2, so it only missed frame 1
+        assert_eq!(tracker.next_frame(0, [8, 8], damage(2)), Some(vec![aabr([1, 0], [2, 1]), aabr([2, 0], [3, 1])]));
+        assert_eq!(tracker.next_frame(2, [8, 8], damage(3)), None);
+
+        // Image 1 missed frames 2 and 3, image 0 missed frames 3 and 4
+        assert_eq!(
+            tracker.next_frame(1, [8, 8], damage(4)),
+            Some(vec![aabr([2, 0], [3, 1]), aabr([3, 0], [4, 1]), aabr([4, 0], [5, 1])])
+        );
+
+        assert_eq!(
+            tracker.next_frame(0, [8, 8], Some(vec![])),
+            Some(vec![aabr([3, 0], [4, 1]), aabr([4, 0], [5, 1])])
+        );
+
+        // A fully damaged frame fully redraws every image that missed it
+        assert_eq!(tracker.next_frame(2, [8, 8], None), None);
+        assert_eq!(tracker.next_frame(1, [8, 8], Some(vec![])), None);
+        assert_eq!(tracker.next_frame(2, [8, 8], Some(vec![])), Some(vec![]));
+    }
+
+    #[test]
+    fn images_drawn_too_long_ago_are_fully_redrawn() {
+        let mut tracker = DamageTracker::new(2);
+
+        tracker.next_frame(0, [8, 8], Some(vec![]));
+
+        for _ in 0..DAMAGE_HISTORY_LEN {
+            tracker.next_frame(1, [8, 8], Some(vec![]));
+        }
+
+        assert_eq!(tracker.next_frame(0, [8, 8], Some(vec![])), None);
+    }
+
+    #[test]
+    fn resizing_fully_redraws_every_image() {
+        let mut tracker = DamageTracker::new(2);
+
+        tracker.next_frame(0, [8, 8], Some(vec![]));
+        tracker.next_frame(1, [8, 8], Some(vec![]));
+        assert_eq!(tracker.next_frame(0, [8, 8], Some(vec![])), Some(vec![]));
+
+        assert_eq!(tracker.next_frame(1, [9, 8], Some(vec![])), None);
+        assert_eq!(tracker.next_frame(0, [9, 8], Some(vec![])), None);
+        assert_eq!(tracker.next_frame(1, [9, 8], Some(vec![])), Some(vec![]));
+
+        // Invalidating has the same effect without a resize
+        tracker.invalidate();
+        assert_eq!(tracker.next_frame(0, [9, 8], Some(vec![])), None);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,228 @@
+use std::fs;
+use std::rc::Rc;
+use std::path::Path;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use vek::Vec2;
+use ttf_parser::{GlyphId, OutlineBuilder};
+use rustybuzz::{Face, UnicodeBuffer, Direction};
+use anyhow::{Result, Context};
+use self_cell::self_cell;
+
+use super::recorder::{CanvasCommand, glyph_commands};
+
+/// A segment of a glyph outline, in font units with y pointing up
+#[derive(Clone, Copy, Debug)]
+pub(super) enum GlyphSegment {
+    Move(Vec2<f32>),
+    Line(Vec2<f32>),
+    Quad(Vec2<f32>, Vec2<f32>),
+    Cubic(Vec2<f32>, Vec2<f32>, Vec2<f32>)
+}
+
+/// A glyph positioned along a line of text
+pub(super) struct PositionedGlyph {
+    pub(super) id: GlyphId,
+
+    /// Offset from the start of the line on the baseline, in pixels with y pointing up
+    pub(super) offset: Vec2<f32>
+}
+
+/// A glyph picked and positioned by shaping, in font units with y pointing up
+pub(super) struct ShapedGlyph {
+    pub(super) id: GlyphId,
+
+    /// Byte offset of the first character of the cluster the glyph belongs to, the characters
+    /// shaped into a group of glyphs as a whole, like a ligature or a character and its marks
+    pub(super) cluster: usize,
+
+    pub(super) advance: f32,
+
+    /// Offset from the pen position, which doesn't affect the advance
+    pub(super) offset: Vec2<f32>
+}
+
+self_cell!(
+    /// Font data along with the face parsed from it
+    struct FontFace {
+        owner: Vec<u8>,
+
+        #[covariant]
+        dependent: Face,
+    }
+);
+
+/// A TrueType or OpenType font
+///
+/// The font is parsed once when it's loaded. Each glyph is recorded as canvas commands the first
+/// time it's drawn at a size and cached, so drawing text only copies the cached commands with a
+/// transform for each glyph rather than extracting and converting the outlines again
+pub struct Font {
+    face: FontFace,
+    units_per_em: f32,
+    glyph_cache: RefCell<GlyphCache>
+}
+
+/// Path commands of each glyph drawn so far, keyed by glyph and the bits of the size it was drawn at
+type GlyphCache = HashMap<(GlyphId, u32), Rc<[CanvasCommand]>>;
+
+impl Font {
+    /// Loads the first font face in a TTF/OTF file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path).with_context(|| format!("Failed to read font file {}", path.display()))?;
+
+        Self::from_bytes(data, 0)
+    }
+
+    /// Loads a font face from TTF/OTF data, `index` selects the face in font collections
+    pub fn from_bytes(data: Vec<u8>, index: u32) -> Result<Self> {
+        let face = FontFace::try_new(data, |data| Face::from_slice(data, index).context("Failed to parse font"))?;
+        let units_per_em = face.borrow_dependent().units_per_em() as f32;
+
+        Ok(Self {
+            face,
+            units_per_em,
+            glyph_cache: RefCell::new(HashMap::new())
+        })
+    }
+
+    /// Distance from the baseline to the top of the tallest glyphs, in pixels
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.face().ascender() as f32 * self.scale(size)
+    }
+
+    /// Distance from the baseline to the bottom of the lowest glyphs, in pixels
+    ///
+    /// This is usually negative
+    pub fn descent(&self, size: f32) -> f32 {
+        self.face().descender() as f32 * self.scale(size)
+    }
+
+    /// Recommended distance between consecutive baselines, in pixels
+    pub fn line_height(&self, size: f32) -> f32 {
+        let face = self.face();
+        (face.ascender() as f32 - face.descender() as f32 + face.line_gap() as f32) * self.scale(size)
+    }
+
+    /// Width of a line of text, in pixels
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        let advance: f32 = self.shape(text, None)
+            .iter()
+            .filter(|shaped| !is_control_cluster(text, shaped))
+            .map(|shaped| shaped.advance)
+            .sum();
+
+        advance * self.scale(size)
+    }
+
+    /// Scale from font units to pixels
+    pub(super) fn scale(&self, size: f32) -> f32 {
+        size / self.units_per_em
+    }
+
+    /// Shapes and positions the glyphs of a single line of text
+    ///
+    /// The line is shaped as a single run in the direction of its script, use a
+    /// [`TextLayout`](super::TextLayout) for mixed direction text. Control characters are skipped,
+    /// characters missing in the font use the `.notdef` glyph
+    pub(super) fn layout_line(&self, text: &str, size: f32) -> Vec<PositionedGlyph> {
+        let scale = self.scale(size);
+
+        let mut glyphs = vec![];
+        let mut pen = 0.0;
+
+        for shaped in self.shape(text, None).iter().filter(|shaped| !is_control_cluster(text, shaped)) {
+            glyphs.push(PositionedGlyph {
+                id: shaped.id,
+                offset: Vec2::new(pen, 0.0) + shaped.offset * scale
+            });
+
+            pen += shaped.advance * scale;
+        }
+
+        glyphs
+    }
+
+    /// Shapes a run of text into glyphs in visual order, applying ligatures, mark positioning and
+    /// kerning
+    ///
+    /// `rtl` sets the run's direction, or it's guessed from the script of the text if `None`
+    pub(super) fn shape(&self, text: &str, rtl: Option<bool>) -> Vec<ShapedGlyph> {
+        let mut buffer = UnicodeBuffer::new();
+
+        buffer.push_str(text);
+
+        if let Some(rtl) = rtl {
+            buffer.set_direction(if rtl { Direction::RightToLeft } else { Direction::LeftToRight });
+        }
+
+        buffer.guess_segment_properties();
+
+        let output = rustybuzz::shape(self.face(), &[], buffer);
+
+        output.glyph_infos()
+            .iter()
+            .zip(output.glyph_positions())
+            .map(|(info, position)| ShapedGlyph {
+                id: GlyphId(info.glyph_id as u16),
+                cluster: info.cluster as usize,
+                advance: position.x_advance as f32,
+                offset: Vec2::new(position.x_offset as f32, position.y_offset as f32)
+            })
+            .collect()
+    }
+
+    /// Gets a glyph's path commands at `size` from the cache, recording them if needed
+    ///
+    /// The commands are in pixels with the glyph's origin at (0, 0)
+    pub(super) fn glyph(&self, glyph_id: GlyphId, size: f32) -> Rc<[CanvasCommand]> {
+        self.glyph_cache
+            .borrow_mut()
+            .entry((glyph_id, size.to_bits()))
+            .or_insert_with(|| {
+                let mut builder = GlyphBuilder { segments: vec![] };
+                self.face().outline_glyph(glyph_id, &mut builder);
+
+                glyph_commands(&builder.segments, self.scale(size)).into()
+            })
+            .clone()
+    }
+
+    pub(super) fn face(&self) -> &Face<'_> {
+        self.face.borrow_dependent()
+    }
+}
+
+/// Checks if a shaped glyph belongs to a control character, which isn't drawn
+fn is_control_cluster(text: &str, shaped: &ShapedGlyph) -> bool {
+    text[shaped.cluster..].starts_with(char::is_control)
+}
+
+/// Collects glyph outlines into [`GlyphSegment`]s
+///
+/// Closing segments aren't recorded since fill subpaths are closed implicitly
+struct GlyphBuilder {
+    segments: Vec<GlyphSegment>
+}
+
+impl OutlineBuilder for GlyphBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.segments.push(GlyphSegment::Move(Vec2::new(x, y)));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.segments.push(GlyphSegment::Line(Vec2::new(x, y)));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.segments.push(GlyphSegment::Quad(Vec2::new(x1, y1), Vec2::new(x, y)));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.segments.push(GlyphSegment::Cubic(Vec2::new(x1, y1), Vec2::new(x2, y2), Vec2::new(x, y)));
+    }
+
+    fn close(&mut self) {}
+}
\ No newline at end of file
@@ -0,0 +1,253 @@
+use vek::{Aabr, Vec2};
+
+use super::path::Path;
+use super::recorder::{FillRule, average_scale};
+use super::transform::Transform;
+
+/// Curves are flattened into lines that deviate from them by atmost this many pixels
+const HIT_TOLERANCE: f32 = 0.05;
+
+/// The part of a shape that can be hit
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HitArea {
+    Fill(FillRule),
+
+    /// Stroke width
+    Stroke(f32)
+}
+
+struct HitShape<Id> {
+    id: Id,
+    path: Path,
+    area: HitArea,
+
+    /// Maps window space to path space
+    inverse: Transform,
+
+    /// Flattening tolerance in path space, so curves are equally accurate at any scale
+    tolerance: f32,
+
+    /// Window space bounding box
+    bounds: Aabr<f32>
+}
+
+/// Shapes drawn on the canvas, in the order they're drawn, that window positions can be
+/// resolved to, e.g. to find the UI element under the mouse
+///
+/// Shapes are added with the paths and transforms they're drawn with, such as
+/// [`current_transform()`](super::Canvas2DRecorder::current_transform), and are tested in
+/// window space, so positions from [`WindowEvent::MouseMoved`](crate::window::WindowEvent::MouseMoved)
+/// can be looked up directly with [`Position::pixel_center()`](crate::window::Position::pixel_center).
+/// Clips and layers aren't taken into account
+pub struct HitMap<Id> {
+    shapes: Vec<HitShape<Id>>
+}
+
+impl<Id> HitMap<Id> {
+    pub fn new() -> Self {
+        Self { shapes: vec![] }
+    }
+
+    /// Adds a filled path, on top of every shape added so far
+    ///
+    /// Paths drawn with a degenerate transform can't be hit, so they're ignored
+    pub fn add_fill(&mut self, id: Id, path: &Path, transform: Transform, fill_rule: FillRule) {
+        self.add(id, path, transform, HitArea::Fill(fill_rule));
+    }
+
+    /// Adds a stroked path, on top of every shape added so far
+    ///
+    /// Paths drawn with a degenerate transform can't be hit, so they're ignored
+    pub fn add_stroke(&mut self, id: Id, path: &Path, transform: Transform, width: f32) {
+        self.add(id, path, transform, HitArea::Stroke(width));
+    }
+
+    /// The top-most shape at a window position
+    pub fn hit_test(&self, point: Vec2<f32>) -> Option<&Id> {
+        self.hits(point).next()
+    }
+
+    /// Every shape at a window position, top-most first
+    pub fn hits(&self, point: Vec2<f32>) -> impl Iterator<Item = &Id> {
+        self.shapes
+            .iter()
+            .rev()
+            .filter(move |shape| shape.bounds.contains_point(point) && shape.contains(point))
+            .map(|shape| &shape.id)
+    }
+
+    /// Every shape whose bounding box overlaps a window space box, top-most first, e.g. for
+    /// selecting shapes by dragging out a rectangle
+    pub fn query_bounds(&self, bounds: Aabr<f32>) -> impl Iterator<Item = &Id> {
+        self.shapes
+            .iter()
+            .rev()
+            .filter(move |shape| shape.bounds.collides_with_aabr(bounds))
+            .map(|shape| &shape.id)
+    }
+
+    /// Window space bounding box of every shape with an ID, `None` if there aren't any
+    pub fn bounds_of(&self, id: &Id) -> Option<Aabr<f32>> where Id: PartialEq {
+        self.shapes
+            .iter()
+            .filter(|shape| shape.id == *id)
+            .map(|shape| shape.bounds)
+            .reduce(Aabr::union)
+    }
+
+    /// Removes every shape, e.g. before the frame they were drawn in is recorded again
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.shapes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    fn add(&mut self, id: Id, path: &Path, transform: Transform, area: HitArea) {
+        let Some(inverse) = transform.inverse() else {
+            return;
+        };
+
+        let tolerance = HIT_TOLERANCE / average_scale(&transform);
+        let Some(path_bounds) = path.flattened_bounds(tolerance) else {
+            return;
+        };
+
+        // Strokes reach half their width past the path
+        let margin = match area {
+            HitArea::Fill(_) => 0.0,
+            HitArea::Stroke(width) => width * 0.5
+        };
+
+        let corners = [
+            path_bounds.min - margin,
+            Vec2::new(path_bounds.max.x + margin, path_bounds.min.y - margin),
+            Vec2::new(path_bounds.min.x - margin, path_bounds.max.y + margin),
+            path_bounds.max + margin
+        ].map(|corner| transform.apply(corner));
+
+        let bounds = corners[1..]
+            .iter()
+            .fold(Aabr::new_empty(corners[0]), |bounds, &corner| bounds.expanded_to_contain_point(corner));
+
+        self.shapes.push(HitShape {
+            id,
+            path: path.clone(),
+            area,
+            inverse,
+            tolerance,
+            bounds
+        });
+    }
+}
+
+impl<Id> HitShape<Id> {
+    fn contains(&self, point: Vec2<f32>) -> bool {
+        let point = self.inverse.apply(point);
+
+        match self.area {
+            HitArea::Fill(fill_rule) => self.path.fill_contains(point, fill_rule, self.tolerance),
+            HitArea::Stroke(width) => self.path.outline_distance(point, self.tolerance) <= width * 0.5
+        }
+    }
+}
+
+impl<Id> Default for HitMap<Id> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_4;
+
+    use super::*;
+
+    fn square(min: f32, max: f32) -> Path {
+        Path::new(Vec2::new(min, min))
+            .line_to(Vec2::new(max, min))
+            .line_to(Vec2::new(max, max))
+            .line_to(Vec2::new(min, max))
+    }
+
+    #[test]
+    fn top_most_first() {
+        let mut hit_map = HitMap::new();
+
+        hit_map.add_fill(1, &square(0.0, 30.0), Transform::IDENTITY, FillRule::NonZero);
+        hit_map.add_fill(2, &square(10.0, 20.0), Transform::IDENTITY, FillRule::NonZero);
+        hit_map.add_fill(3, &square(5.0, 25.0), Transform::IDENTITY, FillRule::NonZero);
+
+        assert_eq!(hit_map.hits(Vec2::new(15.0, 15.0)).copied().collect::<Vec<_>>(), [3, 2, 1]);
+        assert_eq!(hit_map.hits(Vec2::new(7.0, 7.0)).copied().collect::<Vec<_>>(), [3, 1]);
+        assert_eq!(hit_map.hit_test(Vec2::new(15.0, 15.0)), Some(&3));
+        assert_eq!(hit_map.hit_test(Vec2::new(2.0, 2.0)), Some(&1));
+        assert_eq!(hit_map.hit_test(Vec2::new(40.0, 40.0)), None);
+    }
+
+    #[test]
+    fn ring_fill_rules() {
+        let ring = Path::new(Vec2::zero())
+            .circle(Vec2::new(50.0, 50.0), 40.0)
+            .circle(Vec2::new(50.0, 50.0), 20.0);
+
+        let mut hit_map = HitMap::new();
+
+        hit_map.add_fill("non-zero", &ring, Transform::IDENTITY, FillRule::NonZero);
+        hit_map.add_fill("even-odd", &ring, Transform::IDENTITY, FillRule::EvenOdd);
+
+        // The hole is within both shapes' bounds, but only the non-zero fill covers it
+        assert_eq!(hit_map.hits(Vec2::new(50.0, 50.0)).copied().collect::<Vec<_>>(), ["non-zero"]);
+        assert_eq!(hit_map.hits(Vec2::new(20.0, 50.0)).copied().collect::<Vec<_>>(), ["even-odd", "non-zero"]);
+    }
+
+    #[test]
+    fn stroke_half_width() {
+        let line = Path::new(Vec2::new(0.0, 50.0)).line_to(Vec2::new(100.0, 50.0));
+        let mut hit_map = HitMap::new();
+
+        hit_map.add_stroke((), &line, Transform::IDENTITY, 8.0);
+
+        assert!(hit_map.hit_test(Vec2::new(50.0, 53.9)).is_some());
+        assert!(hit_map.hit_test(Vec2::new(50.0, 46.1)).is_some());
+        assert!(hit_map.hit_test(Vec2::new(50.0, 54.1)).is_none());
+        assert!(hit_map.hit_test(Vec2::new(50.0, 45.9)).is_none());
+    }
+
+    #[test]
+    fn transformed_shapes() {
+        let mut hit_map = HitMap::new();
+
+        // Turned into a diamond centered on (100, 100), reaching 10 * sqrt(2) along each axis
+        let transform = Transform::rotation(FRAC_PI_4).then(&Transform::translation(Vec2::new(100.0, 100.0)));
+        hit_map.add_fill("diamond", &square(-10.0, 10.0), transform, FillRule::NonZero);
+
+        assert_eq!(hit_map.hit_test(Vec2::new(113.0, 100.0)), Some(&"diamond"));
+        assert_eq!(hit_map.hit_test(Vec2::new(100.0, 87.0)), Some(&"diamond"));
+        assert_eq!(hit_map.hit_test(Vec2::new(109.0, 109.0)), None);
+
+        // Stroke widths are scaled with the path, to 4 pixels either side here
+        let transform = Transform::scaling(Vec2::broadcast(2.0)).then(&Transform::translation(Vec2::new(0.0, 200.0)));
+        hit_map.add_stroke("line", &Path::new(Vec2::zero()).line_to(Vec2::new(50.0, 0.0)), transform, 4.0);
+
+        assert_eq!(hit_map.hit_test(Vec2::new(60.0, 203.9)), Some(&"line"));
+        assert_eq!(hit_map.hit_test(Vec2::new(60.0, 204.1)), None);
+        assert_eq!(hit_map.bounds_of(&"line"), Some(Aabr { min: Vec2::new(-4.0, 196.0), max: Vec2::new(104.0, 204.0) }));
+    }
+
+    #[test]
+    fn degenerate_transforms_are_ignored() {
+        let mut hit_map = HitMap::new();
+
+        hit_map.add_fill((), &square(0.0, 10.0), Transform::scaling(Vec2::new(1.0, 0.0)), FillRule::NonZero);
+
+        assert!(hit_map.is_empty());
+        assert_eq!(hit_map.hit_test(Vec2::new(5.0, 0.0)), None);
+    }
+}
\ No newline at end of file
@@ -2,6 +2,33 @@
 
 mod renderer;
 mod recorder;
+mod paint;
+mod stroke;
+pub mod color;
+mod transform;
+mod font;
+mod text;
+mod svg_path;
+mod svg;
+mod cpu_raster;
+mod scene;
+mod damage;
+mod path;
+mod hit_test;
+mod capture;
 
-pub use renderer::Canvas2DRenderer;
-pub use recorder::{Canvas2DRecorder, InitState, ContourState};
\ No newline at end of file
+pub use renderer::{Canvas2DRenderer, TargetId, Antialiasing};
+pub use recorder::{Canvas2DRecorder, InitState, ContourState, ClipState, LayerState, DrawState, FillRule};
+pub use paint::{Paint, ColorStop, ExtendMode, FilterMode, TextureId};
+pub use stroke::{StrokeStyle, LineJoin, LineCap};
+pub use color::BlendMode;
+pub use transform::Transform;
+pub use font::Font;
+pub use text::{TextLayout, TextSpan, TextAlign, Caret};
+pub use svg_path::{SvgPath, PathSegment};
+pub use svg::SvgImage;
+pub use cpu_raster::CpuRasterizer;
+pub use scene::{CanvasPicture, CanvasScene};
+pub use path::Path;
+pub use hit_test::HitMap;
+pub use capture::{CanvasCapture, CapturedTexture};
\ No newline at end of file
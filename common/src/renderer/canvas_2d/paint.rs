@@ -0,0 +1,112 @@
+use vek::{Vec2, Rgba};
+
+use super::transform::Transform;
+
+/// How a gradient is extended outside of its `[0, 1]` range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtendMode {
+    /// The end colors are extended
+    Pad = 0,
+
+    /// The gradient is repeated
+    Repeat = 1,
+
+    /// The gradient is repeated, with every other repetition mirrored
+    Reflect = 2
+}
+
+/// How textures are filtered when sampled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterMode {
+    Nearest = 0,
+    Linear = 1
+}
+
+/// Handle to a texture registered with the canvas renderer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(pub(super) u16);
+
+/// A color at a position along a gradient
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorStop {
+    /// Position along the gradient, in the range `[0, 1]`
+    pub offset: f32,
+    pub color: Rgba<u8>
+}
+
+impl ColorStop {
+    pub fn new(offset: f32, color: Rgba<u8>) -> Self {
+        Self { offset, color }
+    }
+}
+
+/// Describes how the area covered by a contour is colored
+///
+/// Gradient stops must be sorted by offset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Paint<'a> {
+    /// A single color
+    Solid(Rgba<u8>),
+
+    /// Gradient along the line from `start` to `end`
+    LinearGradient {
+        start: Vec2<f32>,
+        end: Vec2<f32>,
+        stops: &'a [ColorStop],
+        extend: ExtendMode
+    },
+
+    /// Gradient going outwards from `center`, reaching the last stop at `radius`
+    RadialGradient {
+        center: Vec2<f32>,
+        radius: f32,
+        stops: &'a [ColorStop],
+        extend: ExtendMode
+    },
+
+    /// Gradient going clockwise around `center`, starting at `start_angle` radians
+    SweepGradient {
+        center: Vec2<f32>,
+        start_angle: f32,
+        stops: &'a [ColorStop],
+        extend: ExtendMode
+    },
+
+    /// A registered texture
+    ///
+    /// `transform` maps texture pixel coordinates to canvas coordinates, and `tiling`
+    /// decides how the texture is extended beyond its bounds
+    Image {
+        texture: TextureId,
+        transform: Transform,
+        filter: FilterMode,
+        tiling: ExtendMode
+    }
+}
+
+impl From<Rgba<u8>> for Paint<'_> {
+    fn from(color: Rgba<u8>) -> Self {
+        Paint::Solid(color)
+    }
+}
+
+impl Paint<'_> {
+    /// The paint with its geometry moved by `offset`
+    pub(super) fn translated(self, offset: Vec2<f32>) -> Self {
+        match self {
+            Paint::Solid(_) => self,
+            Paint::LinearGradient { start, end, stops, extend } => {
+                Paint::LinearGradient { start: start + offset, end: end + offset, stops, extend }
+            },
+            Paint::RadialGradient { center, radius, stops, extend } => {
+                Paint::RadialGradient { center: center + offset, radius, stops, extend }
+            },
+            Paint::SweepGradient { center, start_angle, stops, extend } => {
+                Paint::SweepGradient { center: center + offset, start_angle, stops, extend }
+            },
+            Paint::Image { texture, transform, filter, tiling } => {
+                Paint::Image { texture, transform: transform.then(&Transform::translation(offset)), filter, tiling }
+            }
+        }
+    }
+}
\ No newline at end of file
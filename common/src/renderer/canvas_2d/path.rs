@@ -0,0 +1,471 @@
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use vek::{Aabr, Vec2};
+
+use super::recorder::FillRule;
+use super::svg_path::{SvgPath, PathSegment};
+
+/// Curves are flattened into lines that deviate from them by atmost this much, in path units
+const FLATTEN_TOLERANCE: f32 = 0.05;
+const MAX_CURVE_SEGMENTS: f32 = 256.0;
+
+/// An operation of a [`Path`], one for each contour command it's drawn with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum PathOp {
+    MoveTo(Vec2<f32>),
+    LineTo(Vec2<f32>),
+    QuadTo(Vec2<f32>, Vec2<f32>),
+    CubicTo(Vec2<f32>, Vec2<f32>, Vec2<f32>),
+    ArcTo(Vec2<f32>, Vec2<f32>, f32),
+    Circle(Vec2<f32>, f32),
+    Ellipse(Vec2<f32>, Vec2<f32>),
+    RoundedRect(Vec2<f32>, Vec2<f32>, f32)
+}
+
+/// A path kept on the CPU, that can be drawn and also queried, e.g. to find out which shape
+/// is under the mouse
+///
+/// Paths are built with the same calls as contours, and drawn with
+/// [`fill_path()`](super::Canvas2DRecorder::fill_path) or [`stroke_path()`](super::Canvas2DRecorder::stroke_path),
+/// so the shape that's queried is the shape that's drawn. Queries flatten curves into lines
+/// and count windings the same way the shader does
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub(super) start_point: Vec2<f32>,
+    pub(super) ops: Vec<PathOp>
+}
+
+impl Path {
+    /// Starts a path, like starting a contour
+    pub fn new(start_point: Vec2<f32>) -> Self {
+        Self { start_point, ops: vec![] }
+    }
+
+    /// Starts a new subpath, filled subpaths are implicitly closed
+    pub fn move_to(mut self, point: Vec2<f32>) -> Self {
+        self.ops.push(PathOp::MoveTo(point));
+        self
+    }
+
+    pub fn line_to(mut self, point: Vec2<f32>) -> Self {
+        self.ops.push(PathOp::LineTo(point));
+        self
+    }
+
+    pub fn quad_to(mut self, control: Vec2<f32>, point: Vec2<f32>) -> Self {
+        self.ops.push(PathOp::QuadTo(control, point));
+        self
+    }
+
+    pub fn cubic_to(mut self, control1: Vec2<f32>, control2: Vec2<f32>, point: Vec2<f32>) -> Self {
+        self.ops.push(PathOp::CubicTo(control1, control2, point));
+        self
+    }
+
+    /// Tangent arc from the current point, in the same manner as the HTML canvas `arcTo()`
+    pub fn arc_to(mut self, corner: Vec2<f32>, point: Vec2<f32>, radius: f32) -> Self {
+        self.ops.push(PathOp::ArcTo(corner, point, radius));
+        self
+    }
+
+    /// Adds a closed circle, which doesn't move the current point
+    pub fn circle(mut self, center: Vec2<f32>, radius: f32) -> Self {
+        self.ops.push(PathOp::Circle(center, radius));
+        self
+    }
+
+    /// Adds a closed axis aligned ellipse, which doesn't move the current point
+    pub fn ellipse(mut self, center: Vec2<f32>, radii: Vec2<f32>) -> Self {
+        self.ops.push(PathOp::Ellipse(center, radii));
+        self
+    }
+
+    /// Adds a closed rectangle with rounded corners, which doesn't move the current point
+    pub fn rounded_rect(mut self, top_left: Vec2<f32>, size: Vec2<f32>, radius: f32) -> Self {
+        self.ops.push(PathOp::RoundedRect(top_left, size, radius));
+        self
+    }
+
+    /// Adds every subpath of a parsed SVG path, with closed subpaths ending in a line back to their start
+    pub fn svg_path(mut self, path: &SvgPath) -> Self {
+        let mut cursor = Vec2::zero();
+        let mut subpath_start = Vec2::zero();
+
+        for segment in path.segments() {
+            self = match *segment {
+                PathSegment::MoveTo(point) => {
+                    subpath_start = point;
+                    self.move_to(point)
+                },
+                PathSegment::LineTo(point) => self.line_to(point),
+                PathSegment::QuadTo(control, point) => self.quad_to(control, point),
+                PathSegment::CubicTo(control1, control2, point) => self.cubic_to(control1, control2, point),
+                PathSegment::Close if cursor != subpath_start => self.line_to(subpath_start),
+                PathSegment::Close => self
+            };
+
+            cursor = match *segment {
+                PathSegment::MoveTo(point) | PathSegment::LineTo(point) => point,
+                PathSegment::QuadTo(_, point) | PathSegment::CubicTo(_, _, point) => point,
+                PathSegment::Close => subpath_start
+            };
+        }
+
+        self
+    }
+
+    /// Checks if a point is inside the path when it's filled with the given fill rule
+    pub fn contains(&self, point: Vec2<f32>, fill_rule: FillRule) -> bool {
+        self.fill_contains(point, fill_rule, FLATTEN_TOLERANCE)
+    }
+
+    /// Distance from a point to the path's outline, as it's stroked, so subpaths aren't closed
+    pub fn distance(&self, point: Vec2<f32>) -> f32 {
+        self.outline_distance(point, FLATTEN_TOLERANCE)
+    }
+
+    /// Checks if a point is on the path when it's stroked with the given width
+    ///
+    /// Joins and caps are treated as round, which makes the stroke a little easier to hit
+    pub fn stroke_contains(&self, point: Vec2<f32>, width: f32) -> bool {
+        self.distance(point) <= width * 0.5
+    }
+
+    /// Smallest box containing the path, `None` if it's a single point
+    pub fn bounds(&self) -> Option<Aabr<f32>> {
+        self.flattened_bounds(FLATTEN_TOLERANCE)
+    }
+
+    pub(super) fn fill_contains(&self, point: Vec2<f32>, fill_rule: FillRule, tolerance: f32) -> bool {
+        // Each edge is counted on both sides of the point, so this is twice the winding number
+        let mut winding_num: i32 = 0;
+
+        self.flatten(tolerance, true, |a, b| {
+            if point.y >= a.y.min(b.y) && point.y < a.y.max(b.y) {
+                winding_num += if cross2(b - a, point - a) > 0.0 { 1 } else { -1 };
+            }
+        });
+
+        match fill_rule {
+            FillRule::NonZero => winding_num != 0,
+            FillRule::EvenOdd => (winding_num.abs() >> 1) & 1 != 0
+        }
+    }
+
+    pub(super) fn outline_distance(&self, point: Vec2<f32>, tolerance: f32) -> f32 {
+        let mut dist = f32::INFINITY;
+
+        self.flatten(tolerance, false, |a, b| dist = dist.min(line_dist(point, a, b)));
+
+        dist
+    }
+
+    pub(super) fn flattened_bounds(&self, tolerance: f32) -> Option<Aabr<f32>> {
+        let mut bounds: Option<Aabr<f32>> = None;
+
+        self.flatten(tolerance, false, |a, b| {
+            let line_bounds = Aabr { min: Vec2::partial_min(a, b), max: Vec2::partial_max(a, b) };
+            bounds = Some(bounds.map_or(line_bounds, |bounds| bounds.union(line_bounds)));
+        });
+
+        bounds
+    }
+
+    /// Flattens the path into lines, closing every subpath if it's filled
+    fn flatten(&self, tolerance: f32, fill: bool, line: impl FnMut(Vec2<f32>, Vec2<f32>)) {
+        let mut flattener = Flattener {
+            line,
+            tolerance,
+            fill,
+            cursor: self.start_point,
+            subpath_start: self.start_point
+        };
+
+        for op in &self.ops {
+            match *op {
+                PathOp::MoveTo(point) => {
+                    flattener.end_subpath();
+                    flattener.cursor = point;
+                    flattener.subpath_start = point;
+                },
+                PathOp::LineTo(point) => flattener.line_to(point),
+                PathOp::QuadTo(control, point) => flattener.quad_to(control, point),
+                PathOp::CubicTo(control1, control2, point) => flattener.cubic_to(control1, control2, point),
+                PathOp::ArcTo(corner, point, radius) => flattener.arc_to(corner, point, radius),
+                PathOp::Circle(center, radius) => {
+                    flattener.arc(center, Vec2::broadcast(radius), 0.0, TAU);
+                },
+                PathOp::Ellipse(center, radii) => {
+                    flattener.arc(center, radii, 0.0, TAU);
+                },
+                PathOp::RoundedRect(top_left, size, radius) => flattener.rounded_rect(top_left, size, radius)
+            }
+        }
+
+        flattener.end_subpath();
+    }
+}
+
+/// Splits a path into lines, passing each one to a callback
+struct Flattener<F> {
+    line: F,
+    tolerance: f32,
+    fill: bool,
+    cursor: Vec2<f32>,
+    subpath_start: Vec2<f32>
+}
+
+impl<F: FnMut(Vec2<f32>, Vec2<f32>)> Flattener<F> {
+    fn line_to(&mut self, point: Vec2<f32>) {
+        (self.line)(self.cursor, point);
+        self.cursor = point;
+    }
+
+    fn quad_to(&mut self, control: Vec2<f32>, point: Vec2<f32>) {
+        let p0 = self.cursor;
+
+        // Flattening error with n segments is |p0 - 2p1 + p2| / (8n^2)
+        let dd = (p0 - control * 2.0 + point).magnitude();
+        let segments = (dd / (8.0 * self.tolerance)).sqrt().ceil().clamp(1.0, MAX_CURVE_SEGMENTS);
+
+        for i in 1..=segments as u32 {
+            let t = i as f32 / segments;
+            self.line_to(mix(mix(p0, control, t), mix(control, point, t), t));
+        }
+    }
+
+    fn cubic_to(&mut self, control1: Vec2<f32>, control2: Vec2<f32>, point: Vec2<f32>) {
+        let p0 = self.cursor;
+
+        // Wang's formula for the number of segments needed
+        let dd = (p0 - control1 * 2.0 + control2).magnitude().max((control1 - control2 * 2.0 + point).magnitude());
+        let segments = (0.75 * dd / self.tolerance).sqrt().ceil().clamp(1.0, MAX_CURVE_SEGMENTS);
+
+        for i in 1..=segments as u32 {
+            let t = i as f32 / segments;
+            let s = 1.0 - t;
+
+            self.line_to(p0 * (s * s * s) + control1 * (3.0 * s * s * t) + control2 * (3.0 * s * t * t) + point * (t * t * t));
+        }
+    }
+
+    /// Tangent arc from the cursor, with the same tangent points as the shader's `processArc()`
+    fn arc_to(&mut self, corner: Vec2<f32>, point: Vec2<f32>, radius: f32) {
+        let d1 = (self.cursor - corner).normalized();
+        let d2 = (point - corner).normalized();
+        let cos_theta = d1.dot(d2);
+
+        // Degenerate arcs are just a line to the corner
+        if radius == 0.0 || self.cursor == corner || point == corner || cos_theta.abs() > 0.9999 {
+            self.line_to(corner);
+            return;
+        }
+
+        let half_theta = cos_theta.acos() * 0.5;
+        let t1 = corner + d1 * radius / half_theta.tan();
+        let t2 = corner + d2 * radius / half_theta.tan();
+        let center = corner + (d1 + d2).normalized() * radius / half_theta.sin();
+
+        self.line_to(t1);
+
+        let from = t1 - center;
+        let to = t2 - center;
+        let sweep = cross2(from, to).atan2(from.dot(to));
+
+        self.cursor = self.arc(center, Vec2::broadcast(radius), from.y.atan2(from.x), sweep);
+    }
+
+    /// Flattens an elliptical arc, without moving the cursor, and returns the point it ends at
+    fn arc(&mut self, center: Vec2<f32>, radii: Vec2<f32>, start_angle: f32, sweep: f32) -> Vec2<f32> {
+        // Flattening error with n segments is r(1 - cos(sweep / 2n))
+        let max_radius = radii.x.abs().max(radii.y.abs()).max(1e-6);
+        let max_step = 2.0 * (1.0 - self.tolerance / max_radius).clamp(-1.0, 1.0).acos();
+        let segments = (sweep.abs() / max_step.max(1e-6)).ceil().clamp(1.0, MAX_CURVE_SEGMENTS);
+
+        let point_at = |angle: f32| center + radii * Vec2::new(angle.cos(), angle.sin());
+        let start = point_at(start_angle);
+        let mut prev = start;
+
+        for i in 1..=segments as u32 {
+            // Whole turns end exactly where they start, otherwise circles aren't quite closed, and
+            // points level with their start are counted twice
+            let point = if i == segments as u32 && sweep.abs() == TAU {
+                start
+            }
+            else {
+                point_at(start_angle + sweep * i as f32 / segments)
+            };
+
+            (self.line)(prev, point);
+            prev = point;
+        }
+
+        prev
+    }
+
+    /// Flattens a rounded rectangle, without moving the cursor
+    ///
+    /// Shapes are wound clockwise on screen, which is how the shader counts them
+    fn rounded_rect(&mut self, top_left: Vec2<f32>, size: Vec2<f32>, radius: f32) {
+        let radius = radius.min(size.x * 0.5).min(size.y * 0.5).max(0.0);
+        let inner_min = top_left + radius;
+        let inner_max = top_left + size - radius;
+
+        // Each corner's center and the angle its arc starts at, each edge leads into the next corner
+        let corners = [
+            (Vec2::new(inner_max.x, inner_min.y), -FRAC_PI_2),
+            (inner_max, 0.0),
+            (Vec2::new(inner_min.x, inner_max.y), FRAC_PI_2),
+            (inner_min, PI)
+        ];
+
+        let mut prev = Vec2::new(inner_min.x, top_left.y);
+
+        for (center, start_angle) in corners {
+            let corner_start = center + Vec2::new(start_angle.cos(), start_angle.sin()) * radius;
+
+            (self.line)(prev, corner_start);
+            prev = self.arc(center, Vec2::broadcast(radius), start_angle, FRAC_PI_2);
+        }
+    }
+
+    /// Closes the current subpath if it's filled
+    fn end_subpath(&mut self) {
+        if self.fill && self.cursor != self.subpath_start {
+            (self.line)(self.cursor, self.subpath_start);
+        }
+    }
+}
+
+/// Distance from a point to a line segment
+fn line_dist(p: Vec2<f32>, a: Vec2<f32>, b: Vec2<f32>) -> f32 {
+    let ba = b - a;
+    let pa = p - a;
+    let len_sq = ba.magnitude_squared();
+
+    let h = if len_sq > 0.0 { (pa.dot(ba) / len_sq).clamp(0.0, 1.0) } else { 0.0 };
+
+    (pa - ba * h).magnitude()
+}
+
+fn mix(a: Vec2<f32>, b: Vec2<f32>, t: f32) -> Vec2<f32> {
+    a + (b - a) * t
+}
+
+fn cross2(a: Vec2<f32>, b: Vec2<f32>) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A square with a square hole, the hole wound the same way as the outside or the opposite way
+    fn square_ring(reverse_hole: bool) -> Path {
+        let hole = [Vec2::new(5.0, 5.0), Vec2::new(15.0, 5.0), Vec2::new(15.0, 15.0), Vec2::new(5.0, 15.0)];
+        let mut path = Path::new(Vec2::new(0.0, 0.0))
+            .line_to(Vec2::new(20.0, 0.0))
+            .line_to(Vec2::new(20.0, 20.0))
+            .line_to(Vec2::new(0.0, 20.0))
+            .move_to(hole[0]);
+
+        if reverse_hole {
+            for &point in hole[1..].iter().rev() {
+                path = path.line_to(point);
+            }
+        }
+        else {
+            for &point in &hole[1..] {
+                path = path.line_to(point);
+            }
+        }
+
+        path
+    }
+
+    #[test]
+    fn ring_fill_rules() {
+        let ring = Path::new(Vec2::zero())
+            .circle(Vec2::new(50.0, 50.0), 40.0)
+            .circle(Vec2::new(50.0, 50.0), 20.0);
+
+        // Both circles wind the same way, so the hole is only left out by even-odd
+        let in_ring = Vec2::new(80.0, 50.0);
+        let in_hole = Vec2::new(55.0, 45.0);
+        let outside = Vec2::new(95.0, 50.0);
+
+        assert!(ring.contains(in_ring, FillRule::NonZero));
+        assert!(ring.contains(in_ring, FillRule::EvenOdd));
+        assert!(ring.contains(in_hole, FillRule::NonZero));
+        assert!(!ring.contains(in_hole, FillRule::EvenOdd));
+        assert!(!ring.contains(outside, FillRule::NonZero));
+        assert!(!ring.contains(outside, FillRule::EvenOdd));
+    }
+
+    #[test]
+    fn ring_winding_direction() {
+        let in_ring = Vec2::new(2.0, 10.0);
+        let in_hole = Vec2::new(10.0, 10.0);
+
+        let same = square_ring(false);
+
+        assert!(same.contains(in_ring, FillRule::NonZero));
+        assert!(same.contains(in_hole, FillRule::NonZero));
+        assert!(!same.contains(in_hole, FillRule::EvenOdd));
+
+        // A hole wound the opposite way cancels out the outside, whatever the fill rule
+        let reversed = square_ring(true);
+
+        assert!(reversed.contains(in_ring, FillRule::NonZero));
+        assert!(reversed.contains(in_ring, FillRule::EvenOdd));
+        assert!(!reversed.contains(in_hole, FillRule::NonZero));
+        assert!(!reversed.contains(in_hole, FillRule::EvenOdd));
+    }
+
+    #[test]
+    fn stroke_half_width() {
+        let line = Path::new(Vec2::new(0.0, 0.0)).line_to(Vec2::new(100.0, 0.0));
+
+        assert!(line.stroke_contains(Vec2::new(50.0, 4.9), 10.0));
+        assert!(line.stroke_contains(Vec2::new(50.0, -4.9), 10.0));
+        assert!(!line.stroke_contains(Vec2::new(50.0, 5.1), 10.0));
+
+        // Caps are treated as round
+        assert!(line.stroke_contains(Vec2::new(103.0, 3.0), 10.0));
+        assert!(!line.stroke_contains(Vec2::new(104.0, 4.0), 10.0));
+
+        // Curves are measured to within the flattening tolerance
+        let circle = Path::new(Vec2::zero()).circle(Vec2::zero(), 50.0);
+
+        assert!(circle.stroke_contains(Vec2::new(0.0, 54.8), 10.0));
+        assert!(circle.stroke_contains(Vec2::new(-45.2, 0.0), 10.0));
+        assert!(!circle.stroke_contains(Vec2::new(0.0, 55.2), 10.0));
+        assert!(!circle.stroke_contains(Vec2::new(-44.8, 0.0), 10.0));
+    }
+
+    #[test]
+    fn stroke_subpaths_stay_open() {
+        let corner = Path::new(Vec2::new(0.0, 0.0))
+            .line_to(Vec2::new(100.0, 0.0))
+            .line_to(Vec2::new(100.0, 100.0));
+
+        // On the line that would close the subpath if it were filled
+        assert!(!corner.stroke_contains(Vec2::new(50.0, 50.0), 10.0));
+        assert!(corner.contains(Vec2::new(60.0, 40.0), FillRule::NonZero));
+    }
+
+    #[test]
+    fn bounds() {
+        let bounds = Path::new(Vec2::zero())
+            .circle(Vec2::new(10.0, 20.0), 5.0)
+            .bounds()
+            .unwrap();
+
+        // The flattened circle's bounds are within the flattening tolerance of the circle's
+        assert!((bounds.min - Vec2::new(5.0, 15.0)).map(f32::abs).reduce_partial_max() <= FLATTEN_TOLERANCE);
+        assert!((bounds.max - Vec2::new(15.0, 25.0)).map(f32::abs).reduce_partial_max() <= FLATTEN_TOLERANCE);
+
+        // A path that doesn't go anywhere has no lines to bound
+        assert_eq!(Path::new(Vec2::new(1.0, 1.0)).bounds(), None);
+    }
+}
\ No newline at end of file
@@ -1,130 +1,1140 @@
 use std::mem;
-use std::ffi;
+use std::ops::Range;
+use std::f32::consts::TAU;
 use std::marker::PhantomData;
 
 use vek::{Vec2, Rgba};
+use anyhow::{bail, Result};
 
+use super::paint::{Paint, ColorStop, ExtendMode};
+use super::stroke::StrokeStyle;
+use super::color::BlendMode;
+use super::transform::Transform;
+use super::font::{Font, GlyphSegment};
+use super::text::TextLayout;
+use super::svg_path::{SvgPath, PathSegment};
+use super::path::{Path, PathOp};
+use super::svg::{SvgImage, SvgItem};
+use super::scene::CanvasPicture;
+
+#[derive(Clone, Copy)]
 #[repr(u32)]
-enum CanvasOp {
+pub(super) enum CanvasOp {
     StartFill = 0,
     StartStroke = 1,
-    LineTo = 2,
-    EndContour = 3,
-    LastCommand = 4
+    StartClip = 2,
+    MoveTo = 3,
+    LineTo = 4,
+    QuadTo = 5,
+    CubicTo = 6,
+    ArcTo = 7,
+    Circle = 8,
+    Ellipse = 9,
+    RoundedRect = 10,
+    Data = 11,
+    EndContour = 12,
+    PopClip = 13,
+    SetTransform = 14,
+    PushLayer = 15,
+    PopLayer = 16,
+    BoxShadow = 17,
+    LastCommand = 18
+}
+
+impl CanvasOp {
+    /// The opcode with the given value, if there is one
+    pub(super) fn from_u32(opcode: u32) -> Option<Self> {
+        const OPS: [CanvasOp; 19] = [
+            CanvasOp::StartFill, CanvasOp::StartStroke, CanvasOp::StartClip, CanvasOp::MoveTo, CanvasOp::LineTo,
+            CanvasOp::QuadTo, CanvasOp::CubicTo, CanvasOp::ArcTo, CanvasOp::Circle, CanvasOp::Ellipse,
+            CanvasOp::RoundedRect, CanvasOp::Data, CanvasOp::EndContour, CanvasOp::PopClip, CanvasOp::SetTransform,
+            CanvasOp::PushLayer, CanvasOp::PopLayer, CanvasOp::BoxShadow, CanvasOp::LastCommand
+        ];
+        
+        OPS.get(opcode as usize).copied()
+    }
+}
+
+/// Paint types, stored in `param3.y` of contour start commands
+#[repr(u32)]
+pub(super) enum PaintType {
+    Solid = 0,
+    LinearGradient = 1,
+    RadialGradient = 2,
+    SweepGradient = 3,
+    Image = 4
+}
+
+/// Rule used to decide which areas of a fill contour are inside it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillRule {
+    /// Inside if the winding number is non zero
+    NonZero = 0,
+
+    /// Inside if the winding number is odd
+    EvenOdd = 1
 }
 
+/// A single canvas command, laid out to match the shader's `CanvasCommand`
+///
+/// Params are raw 32 bit values, holding either f32 coordinates or packed integer data
+/// depending on the opcode
+#[derive(Clone, Copy)]
 #[repr(C)]
 pub struct CanvasCommand {
-    opcode: CanvasOp,
-    param1: Vec2<u16>,
-    param2: Vec2<u16>,
-    param3: Vec2<u16>
+    pub(super) opcode: CanvasOp,
+    _padding: u32,
+    pub(super) param1: Vec2<u32>,
+    pub(super) param2: Vec2<u32>,
+    pub(super) param3: Vec2<u32>
+}
+
+impl CanvasCommand {
+    pub(super) fn new(opcode: CanvasOp, param1: Vec2<u32>, param2: Vec2<u32>, param3: Vec2<u32>) -> Self {
+        Self { opcode, _padding: 0, param1, param2, param3 }
+    }
+}
+
+/// A contour, clip contour, clip pop, layer push, layer pop or box shadow in the command list, in the order they were recorded
+///
+/// The shaders bin these into screen tiles, and only process the commands of the ones
+/// overlapping each tile
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PathInfo {
+    /// Index of the contour start, pop clip, push layer, pop layer or box shadow command
+    pub(super) cmd_idx: u32,
+    
+    /// Index of the set transform command in effect, [`NO_TRANSFORM`] if there's none
+    pub(super) transform_idx: u32
+}
+
+/// A finished recording, reused across frames to avoid reallocating
+#[derive(Clone, Default)]
+pub(super) struct CanvasRecording {
+    pub(super) cmds: Vec<CanvasCommand>,
+    pub(super) paths: Vec<PathInfo>,
+    
+    /// Transforms of the set transform commands in the order they were written, before they're
+    /// inverted, so pictures can be drawn with another transform on top
+    pub(super) transforms: Vec<Transform>,
+    
+    /// Blurred layers in the order they were popped, so nested layers come before the layers containing them
+    pub(super) blur_layers: Vec<BlurLayer>
+}
+
+impl CanvasRecording {
+    /// Deepest nesting of clips in the recording, or `None` if a clip is popped without being pushed
+    pub(super) fn max_clip_depth(&self) -> Option<usize> {
+        let mut depth = 0usize;
+        let mut max_depth = 0;
+        
+        for path in &self.paths {
+            match self.cmds.get(path.cmd_idx as usize).map(|cmd| cmd.opcode) {
+                Some(CanvasOp::StartClip) => depth += 1,
+                Some(CanvasOp::PopClip) => depth = depth.checked_sub(1)?,
+                _ => ()
+            }
+            
+            max_depth = max_depth.max(depth);
+        }
+        
+        Some(max_depth)
+    }
+}
+
+/// A layer whose contents are rasterized into an image of their own, and blurred, before it's composited
+#[derive(Clone)]
+pub(super) struct BlurLayer {
+    /// Paths inside the layer, not including its push and pop
+    pub(super) paths: Range<u32>,
+    
+    /// Standard deviation of the blur, in pixels
+    pub(super) std_dev: f32
+}
+
+/// A layer that's been pushed but not yet popped
+struct PushedLayer {
+    opacity: f32,
+    blend_mode: BlendMode,
+    
+    /// Standard deviation of the blur in pixels, if the layer is blurred
+    blur_std_dev: Option<f32>,
+    
+    /// Indices of the push layer path and command, blurred layers have their index and the
+    /// index of their pop path written to the command once they're popped
+    push_path_idx: u32,
+    push_cmd_idx: usize
 }
 
-pub(super) const CMD_LIST_BUF_SIZE: u64 = 1000 * mem::size_of::<CanvasCommand>() as u64; // space for 1000 commands
+pub(super) const CMD_SIZE: u64 = mem::size_of::<CanvasCommand>() as u64;
+pub(super) const PATH_INFO_SIZE: u64 = mem::size_of::<PathInfo>() as u64;
+
+pub(super) const NO_TRANSFORM: u32 = u32::MAX; // Must match NO_TRANSFORM in canvas_2d_common.glsl
+pub(super) const NO_BLUR_LAYER: u32 = u32::MAX; // Must match NO_BLUR_LAYER in canvas_2d_common.glsl
+pub(super) const MAX_BLUR_LAYERS: usize = 8; // Each blurred layer needs a window sized image, so they're limited per frame
+pub(super) const MAX_CLIP_DEPTH: usize = 8; // Must match MAX_CLIP_DEPTH in canvas_2d.comp
 
 pub struct InitState;
-pub struct ContourState;
 
-/// Records canvas commands into the command list buffer
+/// State inside a clip, with `Parent` being the state to return to once it's popped
+pub struct ClipState<Parent>(PhantomData<Parent>);
+
+/// State inside a layer, with `Parent` being the state to return to once it's popped
+pub struct LayerState<Parent>(PhantomData<Parent>);
+
+/// State inside a contour, with `Parent` being the state to return to once it ends
+pub struct ContourState<Parent = InitState>(PhantomData<Parent>);
+
+/// States in which new contours and clips can be started
+///
+/// Since clips and layers nest their parent state in their type, a recording can only be
+/// finished once every pushed clip and layer has been popped, and they can't overlap
+pub trait DrawState {}
+
+impl DrawState for InitState {}
+impl<Parent: DrawState> DrawState for ClipState<Parent> {}
+impl<Parent: DrawState> DrawState for LayerState<Parent> {}
+
+/// Records canvas commands into a host side command list, which is copied to the GPU once recording ends
 /// 
-/// All drawing functions use physical window coordinates with (0, 0) at top left.
-/// It is the responsibility of the user to handle DPI scaling, etc
+/// All drawing functions use coordinates that are mapped to physical window coordinates,
+/// with (0, 0) at top left, by the current transform. The transform starts out as the
+/// identity and can be changed between contours, e.g. for scrolling or DPI scaling.
+/// Coordinates are sub-pixel precise, and geometry may extend past the window's edges
 ///
 /// Uses the typestate pattern to ensure only valid patterns of commands are issued
 pub struct Canvas2DRecorder<State> {
-    ptr: *mut CanvasCommand,
+    recording: CanvasRecording,
+    transform: Transform,
+    saved_transforms: Vec<Transform>,
+    
+    /// Transform last written to the command list, which applies to the contours that follow it
+    written_transform: Transform,
+    written_transform_idx: u32,
+    
+    blend_mode: BlendMode,
+    
+    layers: Vec<PushedLayer>,
+    
+    /// Number of clips currently pushed, and whether they were ever nested deeper than
+    /// [`MAX_CLIP_DEPTH`], which fails the recording once it ends
+    clip_depth: usize,
+    clips_too_deep: bool,
+    
     _state: PhantomData<State>
 }
 
 impl<State> Canvas2DRecorder<State> {
     fn write_cmd(&mut self, cmd: CanvasCommand) {
-        unsafe {
-            self.ptr.write(cmd);
-            self.ptr = self.ptr.add(1);
+        self.recording.cmds.push(cmd);
+    }
+    
+    /// Adds a path starting at the next command to be written
+    fn write_path(&mut self) {
+        self.recording.paths.push(PathInfo {
+            cmd_idx: self.recording.cmds.len() as u32,
+            transform_idx: self.written_transform_idx
+        });
+    }
+    
+    fn transition<NewState>(self) -> Canvas2DRecorder<NewState> {
+        Canvas2DRecorder {
+            recording: self.recording,
+            transform: self.transform,
+            saved_transforms: self.saved_transforms,
+            written_transform: self.written_transform,
+            written_transform_idx: self.written_transform_idx,
+            blend_mode: self.blend_mode,
+            layers: self.layers,
+            clip_depth: self.clip_depth,
+            clips_too_deep: self.clips_too_deep,
+            _state: PhantomData
         }
     }
+    
+    /// Writes the current transform if it has changed since it was last written
+    ///
+    /// The shader maps pixels back into canvas space, so the inverse transform is written
+    fn write_transform(&mut self) {
+        if self.transform == self.written_transform {
+            return;
+        }
+        
+        self.write_set_transform(self.transform);
+    }
+    
+    /// Writes a set transform command, making it the last written transform
+    fn write_set_transform(&mut self, transform: Transform) {
+        // A degenerate transform collapses everything to a line or point, so nothing is drawn.
+        // The shader treats an all zero inverse as such
+        let inv = transform.inverse().unwrap_or(Transform::new(0.0, 0.0, 0.0, 0.0, 0.0, 0.0));
+        
+        self.written_transform_idx = self.recording.cmds.len() as u32;
+        self.written_transform = transform;
+        self.recording.transforms.push(transform);
+        self.write_matrix(CanvasOp::SetTransform, &inv);
+    }
+    
+    /// Writes a push layer command, blurred layers are given their index once they're popped
+    fn write_push_layer(&mut self, opacity: f32, blend_mode: BlendMode, blur_std_dev: Option<f32>) {
+        self.write_path();
+        
+        self.layers.push(PushedLayer {
+            opacity: opacity.clamp(0.0, 1.0),
+            blend_mode,
+            blur_std_dev,
+            push_path_idx: self.recording.paths.len() as u32 - 1,
+            push_cmd_idx: self.recording.cmds.len()
+        });
+        
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::PushLayer,
+            Vec2::new(NO_BLUR_LAYER, 0),
+            Vec2::zero(),
+            Vec2::zero()
+        ));
+    }
+    
+    /// Writes the pop command of the innermost layer, and if it's blurred, points its push at it
+    fn write_pop_layer(&mut self) {
+        // Layers are pushed and popped in pairs, so this is never empty
+        let Some(layer) = self.layers.pop() else {
+            return;
+        };
+        
+        let pop_path_idx = self.recording.paths.len() as u32;
+        let mut blur_layer_idx = NO_BLUR_LAYER;
+        
+        // Blurred layers skip straight to their pop, and get their contents from their own pass
+        if let Some(std_dev) = layer.blur_std_dev {
+            blur_layer_idx = self.recording.blur_layers.len() as u32;
+            
+            self.recording.cmds[layer.push_cmd_idx].param1 = Vec2::new(blur_layer_idx, pop_path_idx);
+            
+            self.recording.blur_layers.push(BlurLayer {
+                paths: layer.push_path_idx + 1..pop_path_idx,
+                std_dev
+            });
+        }
+        
+        self.write_path();
+        
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::PopLayer,
+            Vec2::new(layer.opacity.to_bits(), layer.blend_mode as u32),
+            Vec2::new(blur_layer_idx, 0),
+            Vec2::zero()
+        ));
+    }
+    
+    /// Writes a transform as a single command, with each param holding a column of the matrix
+    fn write_matrix(&mut self, opcode: CanvasOp, matrix: &Transform) {
+        self.write_cmd(CanvasCommand::new(
+            opcode,
+            pack_point(Vec2::new(matrix.a, matrix.b)),
+            pack_point(Vec2::new(matrix.c, matrix.d)),
+            pack_point(Vec2::new(matrix.e, matrix.f))
+        ));
+    }
+    
+    /// Writes a contour start command, the paint's data commands if it has any are written with [`write_paint()`](Self::write_paint)
+    fn write_start_cmd(&mut self, opcode: CanvasOp, start_point: Vec2<f32>, param3_x: u32, paint: &Paint) {
+        self.write_transform();
+        self.write_path();
+        
+        let (paint_type, color) = match paint {
+            Paint::Solid(color) => (PaintType::Solid, pack_color(*color)),
+            Paint::LinearGradient { .. } => (PaintType::LinearGradient, 0),
+            Paint::RadialGradient { .. } => (PaintType::RadialGradient, 0),
+            Paint::SweepGradient { .. } => (PaintType::SweepGradient, 0),
+            Paint::Image { .. } => (PaintType::Image, 0)
+        };
+        
+        self.write_cmd(CanvasCommand::new(
+            opcode,
+            pack_point(start_point),
+            Vec2::new(color, self.blend_mode as u32),
+            Vec2::new(param3_x, paint_type as u32)
+        ));
+    }
+    
+    /// Writes the paint's data commands, if it has any
+    fn write_paint(&mut self, paint: &Paint) {
+        match *paint {
+            Paint::Solid(_) => (),
+            
+            Paint::LinearGradient { start, end, stops, extend } => {
+                self.write_gradient(start, end, stops, extend);
+            },
+            
+            Paint::RadialGradient { center, radius, stops, extend } => {
+                self.write_gradient(center, Vec2::new(radius, 0.0), stops, extend);
+            },
+            
+            Paint::SweepGradient { center, start_angle, stops, extend } => {
+                self.write_gradient(center, Vec2::new(start_angle.rem_euclid(TAU), 0.0), stops, extend);
+            },
+            
+            Paint::Image { texture, transform, filter, tiling } => {
+                self.write_image(texture.0 as u32, transform, filter as u32 * 3 + tiling as u32);
+            }
+        }
+    }
+    
+    /// Writes the stroke style header and dash pattern as data commands
+    ///
+    /// Header: `param1` = (join, cap), `param2` = (miter limit, dash offset), `param3` = (number of dash pairs, pattern length)
+    /// Dash pair: `param1` = (dash length, gap length)
+    fn write_stroke_style(&mut self, style: &StrokeStyle) {
+        // Invalid patterns are ignored, drawing a solid stroke like the HTML canvas
+        let valid = style.dashes.iter().all(|&length| length.is_finite() && length >= 0.0);
+        let dashes = if valid { style.dashes } else { &[] };
+        
+        // Odd length patterns are repeated to get an even number of lengths
+        let repeats = if dashes.len() % 2 == 1 { 2 } else { 1 };
+        let pattern_len = dashes.iter().sum::<f32>() * repeats as f32;
+        let num_pairs = if pattern_len > 0.0 && pattern_len.is_finite() { dashes.len() * repeats / 2 } else { 0 };
+        
+        let dash_offset = if num_pairs > 0 { style.dash_offset.rem_euclid(pattern_len) } else { 0.0 };
+        
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::Data,
+            Vec2::new(style.join as u32, style.cap as u32),
+            pack_point(Vec2::new(style.miter_limit, dash_offset)),
+            Vec2::new(num_pairs as u32, pattern_len.to_bits())
+        ));
+        
+        for pair in 0..num_pairs {
+            self.write_cmd(CanvasCommand::new(
+                CanvasOp::Data,
+                pack_point(Vec2::new(dashes[pair * 2 % dashes.len()], dashes[(pair * 2 + 1) % dashes.len()])),
+                Vec2::zero(),
+                Vec2::zero()
+            ));
+        }
+    }
+    
+    /// Writes the image header and inverse transform as data commands
+    ///
+    /// Header: `param1` = (texture index, sampler index)
+    /// Followed by a command holding the canvas to texture transform
+    fn write_image(&mut self, texture_idx: u32, transform: Transform, sampler_idx: u32) {
+        // A degenerate transform can't map any pixel onto the texture, collapse it to a point instead
+        let inv = transform.inverse().unwrap_or(Transform::new(0.0, 0.0, 0.0, 0.0, 0.0, 0.0));
+        
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::Data,
+            Vec2::new(texture_idx, sampler_idx),
+            Vec2::zero(),
+            Vec2::zero()
+        ));
+        
+        self.write_matrix(CanvasOp::Data, &inv);
+    }
+    
+    /// Writes the gradient header and stops as data commands
+    ///
+    /// Header: `param1` and `param2` = gradient geometry, `param3` = (extend mode, number of stops)
+    /// Stop: `param1.x` = offset, `param2.x` = color
+    fn write_gradient(&mut self, geometry1: Vec2<f32>, geometry2: Vec2<f32>, stops: &[ColorStop], extend: ExtendMode) {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::Data,
+            pack_point(geometry1),
+            pack_point(geometry2),
+            Vec2::new(extend as u32, stops.len() as u32)
+        ));
+        
+        for stop in stops {
+            self.write_cmd(CanvasCommand::new(
+                CanvasOp::Data,
+                pack_scalar(stop.offset.clamp(0.0, 1.0)),
+                Vec2::new(pack_color(stop.color), 0),
+                Vec2::zero()
+            ));
+        }
+    }
+}
+
+/// Stores a point or pair of f32s as a command param
+fn pack_point(point: Vec2<f32>) -> Vec2<u32> {
+    point.map(f32::to_bits)
+}
+
+/// Stores a single f32 in the x component of a command param
+fn pack_scalar(value: f32) -> Vec2<u32> {
+    Vec2::new(value.to_bits(), 0)
+}
+
+/// Packs a color into a u32, red in the lowest byte
+pub(super) fn pack_color(color: Rgba<u8>) -> u32 {
+    u32::from_le_bytes([color.r, color.g, color.b, color.a])
+}
+
+/// Converts a glyph outline into path commands, scaled from font units with the glyph's origin at (0, 0)
+pub(super) fn glyph_commands(segments: &[GlyphSegment], scale: f32) -> Vec<CanvasCommand> {
+    // Font units have y pointing up
+    let to_canvas = |point: Vec2<f32>| pack_point(Vec2::new(point.x * scale, -point.y * scale));
+    
+    segments
+        .iter()
+        .map(|segment| match *segment {
+            GlyphSegment::Move(point) => CanvasCommand::new(CanvasOp::MoveTo, to_canvas(point), Vec2::zero(), Vec2::zero()),
+            GlyphSegment::Line(point) => CanvasCommand::new(CanvasOp::LineTo, to_canvas(point), Vec2::zero(), Vec2::zero()),
+            GlyphSegment::Quad(control, point) => {
+                CanvasCommand::new(CanvasOp::QuadTo, to_canvas(control), to_canvas(point), Vec2::zero())
+            },
+            GlyphSegment::Cubic(control1, control2, point) => {
+                CanvasCommand::new(CanvasOp::CubicTo, to_canvas(control1), to_canvas(control2), to_canvas(point))
+            }
+        })
+        .collect()
 }
 
 impl Canvas2DRecorder<InitState> {
-    pub(super) fn new(cmd_list_ptr: *mut ffi::c_void) -> Self {
+    /// Starts recording into `recording`, which is cleared first so its allocations can be reused across frames
+    pub(super) fn new(mut recording: CanvasRecording) -> Self {
+        recording.cmds.clear();
+        recording.paths.clear();
+        recording.transforms.clear();
+        recording.blur_layers.clear();
+        
         Self {
-            ptr: cmd_list_ptr as *mut CanvasCommand,
+            recording,
+            transform: Transform::IDENTITY,
+            saved_transforms: vec![],
+            written_transform: Transform::IDENTITY,
+            written_transform_idx: NO_TRANSFORM,
+            blend_mode: BlendMode::SrcOver,
+            layers: vec![],
+            clip_depth: 0,
+            clips_too_deep: false,
             _state: PhantomData
         }
     }
     
-    pub(super) fn end(mut self) {
-        self.write_cmd(CanvasCommand {
-            opcode: CanvasOp::LastCommand,
-            param1: Vec2::zero(),
-            param2: Vec2::zero(),
-            param3: Vec2::zero()
-        });
+    /// Ends the recording, returning the finished command and path lists
+    ///
+    /// Fails if clips were nested more than [`MAX_CLIP_DEPTH`] deep, which the shaders have no room for
+    pub(super) fn end(self) -> Result<CanvasRecording> {
+        if self.clips_too_deep {
+            bail!("Canvas clips are nested more than {MAX_CLIP_DEPTH} deep");
+        }
+        
+        Ok(self.terminate())
     }
     
-    pub fn start_fill(mut self, start_point: Vec2<u16>, color: Rgba<u8>) -> Canvas2DRecorder<ContourState> {
-        let packed_color = Vec2::new(
-            color.r as u16 | (color.g as u16) << 8,
-            color.b as u16 | (color.a as u16) << 8
-        );
-                
-        self.write_cmd(CanvasCommand {
-            opcode: CanvasOp::StartFill,
-            param1: start_point,
-            param2: packed_color,
-            param3: Vec2::zero()
-        });
+    /// A finished recording that draws nothing
+    pub(super) fn empty_recording() -> CanvasRecording {
+        Self::new(CanvasRecording::default()).terminate()
+    }
+    
+    fn terminate(mut self) -> CanvasRecording {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::LastCommand,
+            Vec2::zero(),
+            Vec2::zero(),
+            Vec2::zero()
+        ));
         
-        Canvas2DRecorder {
-            ptr: self.ptr,
-            _state: PhantomData
+        self.recording
+    }
+    
+    /// Starts recording a picture, see [`CanvasPicture`]
+    pub(super) fn new_picture(recording: CanvasRecording) -> Self {
+        let mut recorder = Self::new(recording);
+        
+        // Nothing equals a transform of NaNs, so the transform is written before the first contour
+        // even if it's the identity, and every contour's transform can be combined with the one the
+        // picture is drawn with
+        recorder.written_transform = Transform::new(f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN);
+        recorder
+    }
+    
+    /// Ends a picture recording, pictures are drawn into other recordings so they aren't terminated
+    ///
+    /// Fails like [`end()`](Self::end)
+    pub(super) fn end_picture(self) -> Result<CanvasRecording> {
+        if self.clips_too_deep {
+            bail!("Canvas clips are nested more than {MAX_CLIP_DEPTH} deep");
+        }
+        
+        Ok(self.recording)
+    }
+}
+
+/// Average scale of a transform, the square root of its area scale
+pub(super) fn average_scale(transform: &Transform) -> f32 {
+    (transform.a * transform.d - transform.b * transform.c).abs().sqrt()
+}
+
+impl<State: DrawState> Canvas2DRecorder<State> {
+    /// Starts a fill contour
+    ///
+    /// Every subpath of a fill contour is implicitly closed with a line back to its starting point
+    pub fn start_fill<'a>(mut self, start_point: Vec2<f32>, paint: impl Into<Paint<'a>>, fill_rule: FillRule) -> Canvas2DRecorder<ContourState<State>> {
+        let paint = paint.into();
+        
+        self.write_start_cmd(CanvasOp::StartFill, start_point, fill_rule as u32, &paint);
+        self.write_paint(&paint);
+        self.transition()
+    }
+    
+    /// Starts a stroke contour, outlining its subpaths as described by `style`
+    ///
+    /// A width can be passed as the style for a solid stroke with the default joins and caps.
+    /// Stroke subpaths are left open, shapes are the only closed outlines
+    pub fn start_stroke<'a, 'b>(
+        mut self,
+        start_point: Vec2<f32>,
+        paint: impl Into<Paint<'a>>,
+        style: impl Into<StrokeStyle<'b>>
+    ) -> Canvas2DRecorder<ContourState<State>> {
+        let paint = paint.into();
+        let style = style.into();
+        
+        self.write_start_cmd(CanvasOp::StartStroke, start_point, style.width.to_bits(), &paint);
+        self.write_stroke_style(&style);
+        self.write_paint(&paint);
+        self.transition()
+    }
+    
+    /// Starts a clip path, everything drawn till the matching [`pop_clip()`](Canvas2DRecorder::pop_clip)
+    /// is limited to the area the path covers
+    ///
+    /// The path is filled like a fill contour, and intersects with any clips already pushed.
+    /// Clips can be nested upto 8 deep, including the clips in pictures drawn inside them, and
+    /// recordings that nest them deeper fail when they end
+    pub fn push_clip_path(mut self, start_point: Vec2<f32>, fill_rule: FillRule) -> Canvas2DRecorder<ContourState<ClipState<State>>> {
+        self.clip_depth += 1;
+        self.clips_too_deep |= self.clip_depth > MAX_CLIP_DEPTH;
+        
+        self.write_transform();
+        self.write_path();
+        
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::StartClip,
+            pack_point(start_point),
+            Vec2::zero(),
+            Vec2::new(fill_rule as u32, 0)
+        ));
+        
+        self.transition()
+    }
+    
+    /// Pushes a rectangular clip, see [`push_clip_path()`](Canvas2DRecorder::push_clip_path)
+    pub fn push_clip_rect(self, top_left: Vec2<f32>, size: Vec2<f32>) -> Canvas2DRecorder<ClipState<State>> {
+        self.push_clip_path(top_left, FillRule::NonZero)
+            .rounded_rect(top_left, size, 0.0)
+            .end()
+    }
+    
+    /// Pushes a layer, contours drawn till the matching [`pop_layer()`](Canvas2DRecorder::pop_layer) are
+    /// composited into it rather than onto what's already been drawn
+    ///
+    /// Layers start out transparent, and once popped, are composited all at once with `opacity` and
+    /// `blend_mode`. This way overlapping contours in a layer don't show through each other when it's
+    /// faded. Layers cover the whole canvas, limited only by the clips pushed before them, so e.g.
+    /// [`BlendMode::SrcIn`] clears everything not drawn in the layer. Layers can be nested upto 4 deep,
+    /// deeper layers are drawn straight onto their parent
+    pub fn push_layer(mut self, opacity: f32, blend_mode: BlendMode) -> Canvas2DRecorder<LayerState<State>> {
+        self.write_push_layer(opacity, blend_mode, None);
+        self.transition()
+    }
+    
+    /// Pushes a layer that's blurred before it's composited, see [`push_layer()`](Canvas2DRecorder::push_layer)
+    ///
+    /// The blur is a gaussian, with `blur_radius` being twice its standard deviation like the CSS
+    /// blur filter, scaled to pixels by the current transform. Drawing a shape offset and in a
+    /// shadow color in a blurred layer gives it a drop shadow.
+    ///
+    /// Each blurred layer is rasterized on its own and blurred by extra passes over the whole window,
+    /// so they're much more expensive than plain layers. Upto 8 can be drawn per frame, and once
+    /// the limit is reached, or if the blur is 0, they're drawn as plain layers
+    pub fn push_blur_layer(mut self, opacity: f32, blend_mode: BlendMode, blur_radius: f32) -> Canvas2DRecorder<LayerState<State>> {
+        let std_dev = 0.5 * blur_radius * average_scale(&self.transform);
+        
+        let num_blurred = self.recording.blur_layers.len()
+            + self.layers.iter().filter(|layer| layer.blur_std_dev.is_some()).count();
+        
+        let blur_std_dev = (std_dev > 0.0 && std_dev.is_finite() && num_blurred < MAX_BLUR_LAYERS).then_some(std_dev);
+        
+        self.write_push_layer(opacity, blend_mode, blur_std_dev);
+        self.transition()
+    }
+    
+    /// Draws the soft shadow cast by a rounded rectangle, without the rectangle itself
+    ///
+    /// The shadow is the rectangle moved by `offset` and blurred by a gaussian, with `blur_radius`
+    /// being twice its standard deviation like the CSS `box-shadow`. It's drawn analytically, so
+    /// unlike blurred layers it's about as cheap as filling the rectangle
+    pub fn draw_box_shadow(
+        mut self,
+        top_left: Vec2<f32>,
+        size: Vec2<f32>,
+        radius: f32,
+        blur_radius: f32,
+        offset: Vec2<f32>,
+        color: Rgba<u8>
+    ) -> Self {
+        self.write_transform();
+        self.write_path();
+        
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::BoxShadow,
+            pack_point(top_left + offset),
+            pack_point(size),
+            pack_point(Vec2::new(radius, 0.5 * blur_radius.abs()))
+        ));
+        
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::Data,
+            Vec2::new(pack_color(color), self.blend_mode as u32),
+            Vec2::zero(),
+            Vec2::zero()
+        ));
+        
+        self
+    }
+    
+    /// Pushes the current transform onto a stack, so it can be brought back by [`restore()`](Canvas2DRecorder::restore)
+    ///
+    /// Clips aren't affected, they're scoped by [`pop_clip()`](Canvas2DRecorder::pop_clip) instead
+    pub fn save(mut self) -> Self {
+        self.saved_transforms.push(self.transform);
+        self
+    }
+    
+    /// Pops the most recently saved transform and makes it current, does nothing if none are saved
+    pub fn restore(mut self) -> Self {
+        if let Some(transform) = self.saved_transforms.pop() {
+            self.transform = transform;
+        }
+        
+        self
+    }
+    
+    /// Translates everything drawn after this by `offset`, in the current coordinate space
+    pub fn translate(self, offset: Vec2<f32>) -> Self {
+        self.transform(Transform::translation(offset))
+    }
+    
+    /// Scales everything drawn after this about the current origin, this scales stroke widths as well
+    pub fn scale(self, scale: Vec2<f32>) -> Self {
+        self.transform(Transform::scaling(scale))
+    }
+    
+    /// Rotates everything drawn after this clockwise about the current origin, in radians
+    pub fn rotate(self, angle: f32) -> Self {
+        self.transform(Transform::rotation(angle))
+    }
+    
+    /// Applies `transform` to everything drawn after this, before the current transform
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.transform = transform.then(&self.transform);
+        self
+    }
+    
+    /// Replaces the current transform, ignoring any previously applied transforms
+    pub fn set_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+    
+    /// The current transform, mapping canvas coordinates to window coordinates
+    pub fn current_transform(&self) -> Transform {
+        self.transform
+    }
+    
+    /// Sets how contours started after this are combined with what's already been drawn,
+    /// starts out as [`BlendMode::SrcOver`]
+    pub fn set_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+    
+    /// The blend mode contours are currently started with
+    pub fn current_blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+    
+    /// Draws a single line of text, starting at `position` on the baseline
+    ///
+    /// Each glyph is filled as a contour of its own from the commands the font cached for it at
+    /// `size`, with a transform moving it into place, so outlines aren't converted again every
+    /// time text is drawn. Glyphs that overlap, like some marks, are blended over each other
+    pub fn draw_text<'a>(mut self, position: Vec2<f32>, text: &str, font: &Font, size: f32, paint: impl Into<Paint<'a>>) -> Self {
+        let paint = paint.into();
+        
+        for positioned in font.layout_line(text, size) {
+            // Font units have y pointing up
+            let origin = position + Vec2::new(positioned.offset.x, -positioned.offset.y);
+            
+            self = self.fill_glyph(&font.glyph(positioned.id, size), origin, &paint);
         }
+        
+        self
     }
     
-    pub fn start_stroke(mut self, start_point: Vec2<u16>, color: Rgba<u8>, width: u16) -> Canvas2DRecorder<ContourState> {        
-        let packed_color = Vec2::new(
-            color.r as u16 | (color.g as u16) << 8,
-            color.b as u16 | (color.a as u16) << 8
-        );
+    /// Draws a text layout with its top left corner at `position`
+    ///
+    /// Glyphs are filled with their span's paint like in [`draw_text()`](Self::draw_text)
+    pub fn draw_text_layout(mut self, position: Vec2<f32>, layout: &TextLayout) -> Self {
+        for run in layout.glyph_runs() {
+            let font = run.span.font;
+            
+            for (glyph_id, offset) in run.glyphs {
+                self = self.fill_glyph(&font.glyph(glyph_id, run.span.size), position + offset, &run.span.paint);
+            }
+        }
+        
+        self
+    }
+    
+    /// Fills a glyph's cached path commands as a contour, with its origin at `origin`
+    ///
+    /// The glyph is drawn with a transform translating it to `origin`, so the paint is moved the
+    /// other way to stay where it is in the current coordinate space
+    fn fill_glyph(mut self, glyph: &[CanvasCommand], origin: Vec2<f32>, paint: &Paint) -> Self {
+        // Whitespace has no outline
+        if glyph.is_empty() {
+            return self;
+        }
+        
+        let transform = self.transform;
+        self.transform = Transform::translation(origin).then(&transform);
+        
+        let mut recorder = self.start_fill(Vec2::zero(), paint.translated(-origin), FillRule::NonZero);
+        recorder.recording.cmds.extend_from_slice(glyph);
+        
+        let mut recorder = recorder.end();
+        recorder.transform = transform;
+        recorder
+    }
+    
+    /// Fills a path as a contour of its own
+    pub fn fill_path<'a>(self, path: &Path, paint: impl Into<Paint<'a>>, fill_rule: FillRule) -> Self {
+        self.start_fill(path.start_point, paint, fill_rule).path_ops(path).end()
+    }
+    
+    /// Strokes a path as a contour of its own
+    pub fn stroke_path<'a, 'b>(self, path: &Path, paint: impl Into<Paint<'a>>, style: impl Into<StrokeStyle<'b>>) -> Self {
+        self.start_stroke(path.start_point, paint, style).path_ops(path).end()
+    }
+    
+    /// Draws an SVG image with its top left corner at the origin, scaled to [`SvgImage::size()`]
+    ///
+    /// Shapes are drawn with the current blend mode, and groups and shapes with an opacity are
+    /// drawn in layers
+    pub fn draw_svg(mut self, image: &SvgImage) -> Self {
+        let transform = self.transform;
+        
+        for item in &image.items {
+            match item {
+                SvgItem::PushLayer(opacity) => self.write_push_layer(*opacity, BlendMode::SrcOver, None),
+                SvgItem::PopLayer => self.write_pop_layer(),
                 
-        self.write_cmd(CanvasCommand {
-            opcode: CanvasOp::StartStroke,
-            param1: start_point,
-            param2: packed_color,
-            param3: Vec2::new(width, 0)
-        });
+                SvgItem::Fill { shape, transform: shape_transform, color, fill_rule } => {
+                    self.transform = shape_transform.then(&transform);
+                    self = shape.record(self.start_fill(Vec2::zero(), *color, *fill_rule)).end();
+                },
+                
+                SvgItem::Stroke { shape, transform: shape_transform, color, width, join, cap, miter_limit } => {
+                    let style = StrokeStyle {
+                        join: *join,
+                        cap: *cap,
+                        miter_limit: *miter_limit,
+                        ..StrokeStyle::new(*width)
+                    };
+                    
+                    self.transform = shape_transform.then(&transform);
+                    self = shape.record(self.start_stroke(Vec2::zero(), *color, style)).end();
+                }
+            }
+        }
         
-        Canvas2DRecorder {
-            ptr: self.ptr,
-            _state: PhantomData
+        self.transform = transform;
+        self
+    }
+    
+    /// Draws a recorded picture, with the current transform applied on top of the transforms it
+    /// was recorded with
+    ///
+    /// The picture's blend modes are kept, and its blurred layers count towards the limit of 8
+    /// per frame. Once the limit is reached, or if the current transform is degenerate, they're
+    /// drawn as plain layers. Its clips are nested inside the ones currently pushed, and count
+    /// towards the clip depth limit
+    pub fn draw_picture(mut self, picture: &CanvasPicture) -> Self {
+        let picture = &picture.recording;
+        // Pictures are recorded like any other recording, so their clips are always pushed before they're popped
+        self.clips_too_deep |= self.clip_depth + picture.max_clip_depth().unwrap_or(0) > MAX_CLIP_DEPTH;
+        
+        let cmd_base = self.recording.cmds.len() as u32;
+        let path_base = self.recording.paths.len() as u32;
+        
+        // Add the picture's blurred layers that fit under the limit, mapping their indices in the
+        // picture to their indices in this recording
+        let scale = average_scale(&self.transform);
+        let mut num_blurred = self.recording.blur_layers.len()
+            + self.layers.iter().filter(|layer| layer.blur_std_dev.is_some()).count();
+        
+        let blur_layer_map: Vec<u32> = picture.blur_layers
+            .iter()
+            .map(|blur_layer| {
+                let std_dev = blur_layer.std_dev * scale;
+                
+                if !(std_dev > 0.0 && std_dev.is_finite() && num_blurred < MAX_BLUR_LAYERS) {
+                    return NO_BLUR_LAYER;
+                }
+                
+                num_blurred += 1;
+                
+                self.recording.blur_layers.push(BlurLayer {
+                    paths: blur_layer.paths.start + path_base..blur_layer.paths.end + path_base,
+                    std_dev
+                });
+                
+                self.recording.blur_layers.len() as u32 - 1
+            })
+            .collect();
+        
+        let map_blur_layer = |idx: u32| blur_layer_map.get(idx as usize).copied().unwrap_or(NO_BLUR_LAYER);
+        
+        // Copy the commands, combining each transform with the current one and pointing blurred
+        // layers at their new indices
+        let transform = self.transform;
+        let mut picture_transforms = picture.transforms.iter();
+        
+        for &cmd in &picture.cmds {
+            match cmd.opcode {
+                CanvasOp::SetTransform => {
+                    // There's one transform for each set transform command
+                    let picture_transform = picture_transforms.next().copied().unwrap_or(Transform::IDENTITY);
+                    
+                    self.write_set_transform(picture_transform.then(&transform));
+                    continue;
+                },
+                
+                CanvasOp::PushLayer => {
+                    let blur_layer_idx = map_blur_layer(cmd.param1.x);
+                    let pop_path_idx = if blur_layer_idx == NO_BLUR_LAYER { 0 } else { cmd.param1.y + path_base };
+                    
+                    self.write_cmd(CanvasCommand { param1: Vec2::new(blur_layer_idx, pop_path_idx), ..cmd });
+                },
+                
+                CanvasOp::PopLayer => {
+                    self.write_cmd(CanvasCommand { param2: Vec2::new(map_blur_layer(cmd.param2.x), 0), ..cmd });
+                },
+                
+                _ => self.write_cmd(cmd)
+            }
         }
+        
+        // Paths before the picture's first transform are layer pushes, which don't use one
+        self.recording.paths.extend(picture.paths.iter().map(|path| PathInfo {
+            cmd_idx: path.cmd_idx + cmd_base,
+            transform_idx: if path.transform_idx == NO_TRANSFORM { NO_TRANSFORM } else { path.transform_idx + cmd_base }
+        }));
+        
+        self
     }
 }
 
-impl Canvas2DRecorder<ContourState> {
-    pub fn line_to(mut self, point: Vec2<u16>) -> Self {
-        self.write_cmd(CanvasCommand {
-            opcode: CanvasOp::LineTo,
-            param1: point,
-            param2: Vec2::zero(),
-            param3: Vec2::zero()
-        });
+impl<Parent: DrawState> Canvas2DRecorder<ClipState<Parent>> {
+    /// Pops the most recently pushed clip
+    pub fn pop_clip(mut self) -> Canvas2DRecorder<Parent> {
+        self.clip_depth -= 1;
+        self.write_path();
+        
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::PopClip,
+            Vec2::zero(),
+            Vec2::zero(),
+            Vec2::zero()
+        ));
+        
+        self.transition()
+    }
+}
+
+impl<Parent: DrawState> Canvas2DRecorder<LayerState<Parent>> {
+    /// Pops the most recently pushed layer, compositing it onto what was drawn before it
+    pub fn pop_layer(mut self) -> Canvas2DRecorder<Parent> {
+        self.write_pop_layer();
+        self.transition()
+    }
+}
+
+impl<Parent: DrawState> Canvas2DRecorder<ContourState<Parent>> {
+    /// Starts a new subpath within the contour
+    ///
+    /// All subpaths of a fill contour share the same winding number, so holes can be cut
+    /// out by drawing them as additional subpaths
+    pub fn move_to(mut self, point: Vec2<f32>) -> Self {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::MoveTo,
+            pack_point(point),
+            Vec2::zero(),
+            Vec2::zero()
+        ));
         
         self
     }
     
-    pub fn end(mut self) -> Canvas2DRecorder<InitState> {
-        self.write_cmd(CanvasCommand {
-            opcode: CanvasOp::EndContour,
-            param1: Vec2::zero(),
-            param2: Vec2::zero(),
-            param3: Vec2::zero()
-        });
+    pub fn line_to(mut self, point: Vec2<f32>) -> Self {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::LineTo,
+            pack_point(point),
+            Vec2::zero(),
+            Vec2::zero()
+        ));
         
-        Canvas2DRecorder {
-            ptr: self.ptr,
-            _state: PhantomData
+        self
+    }
+    
+    /// Quadratic bezier curve from the current point to `point`
+    pub fn quad_to(mut self, control: Vec2<f32>, point: Vec2<f32>) -> Self {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::QuadTo,
+            pack_point(control),
+            pack_point(point),
+            Vec2::zero()
+        ));
+        
+        self
+    }
+    
+    /// Cubic bezier curve from the current point to `point`
+    pub fn cubic_to(mut self, control1: Vec2<f32>, control2: Vec2<f32>, point: Vec2<f32>) -> Self {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::CubicTo,
+            pack_point(control1),
+            pack_point(control2),
+            pack_point(point)
+        ));
+        
+        self
+    }
+    
+    /// Circular arc of the given radius, tangent to the line from the current point to `corner`
+    /// and to the line from `corner` to `point`
+    ///
+    /// Like the HTML canvas `arcTo()`, a line is drawn from the current point to the start of the arc,
+    /// and the current point ends up at the end of the arc, not at `point`
+    pub fn arc_to(mut self, corner: Vec2<f32>, point: Vec2<f32>, radius: f32) -> Self {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::ArcTo,
+            pack_point(corner),
+            pack_point(point),
+            pack_scalar(radius)
+        ));
+        
+        self
+    }
+    
+    /// Adds a closed circle to the contour
+    ///
+    /// Shapes are separate from the rest of the contour and don't move the current point
+    pub fn circle(mut self, center: Vec2<f32>, radius: f32) -> Self {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::Circle,
+            pack_point(center),
+            pack_scalar(radius),
+            Vec2::zero()
+        ));
+        
+        self
+    }
+    
+    /// Adds a closed axis aligned ellipse to the contour
+    ///
+    /// Shapes are separate from the rest of the contour and don't move the current point
+    pub fn ellipse(mut self, center: Vec2<f32>, radii: Vec2<f32>) -> Self {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::Ellipse,
+            pack_point(center),
+            pack_point(radii),
+            Vec2::zero()
+        ));
+        
+        self
+    }
+    
+    /// Adds a closed rectangle with rounded corners to the contour
+    ///
+    /// Shapes are separate from the rest of the contour and don't move the current point
+    pub fn rounded_rect(mut self, top_left: Vec2<f32>, size: Vec2<f32>, radius: f32) -> Self {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::RoundedRect,
+            pack_point(top_left),
+            pack_point(size),
+            pack_scalar(radius)
+        ));
+        
+        self
+    }
+    
+    /// Adds every subpath of a parsed SVG path to the contour
+    ///
+    /// Closed subpaths end with a line back to their start, when stroked that line is capped
+    /// rather than joined to the start of the subpath
+    pub fn svg_path(mut self, path: &SvgPath) -> Self {
+        let mut cursor = Vec2::zero();
+        let mut subpath_start = Vec2::zero();
+        
+        for segment in path.segments() {
+            self = match *segment {
+                PathSegment::MoveTo(point) => {
+                    subpath_start = point;
+                    self.move_to(point)
+                },
+                PathSegment::LineTo(point) => self.line_to(point),
+                PathSegment::QuadTo(control, point) => self.quad_to(control, point),
+                PathSegment::CubicTo(control1, control2, point) => self.cubic_to(control1, control2, point),
+                PathSegment::Close if cursor != subpath_start => self.line_to(subpath_start),
+                PathSegment::Close => self
+            };
+            
+            cursor = match *segment {
+                PathSegment::MoveTo(point) | PathSegment::LineTo(point) => point,
+                PathSegment::QuadTo(_, point) | PathSegment::CubicTo(_, _, point) => point,
+                PathSegment::Close => subpath_start
+            };
         }
+        
+        self
+    }
+    
+    /// Adds a path to the contour, starting a new subpath at its start point
+    pub fn path(self, path: &Path) -> Self {
+        self.move_to(path.start_point).path_ops(path)
+    }
+    
+    fn path_ops(mut self, path: &Path) -> Self {
+        for op in &path.ops {
+            self = match *op {
+                PathOp::MoveTo(point) => self.move_to(point),
+                PathOp::LineTo(point) => self.line_to(point),
+                PathOp::QuadTo(control, point) => self.quad_to(control, point),
+                PathOp::CubicTo(control1, control2, point) => self.cubic_to(control1, control2, point),
+                PathOp::ArcTo(corner, point, radius) => self.arc_to(corner, point, radius),
+                PathOp::Circle(center, radius) => self.circle(center, radius),
+                PathOp::Ellipse(center, radii) => self.ellipse(center, radii),
+                PathOp::RoundedRect(top_left, size, radius) => self.rounded_rect(top_left, size, radius)
+            };
+        }
+        
+        self
+    }
+    
+    pub fn end(mut self) -> Canvas2DRecorder<Parent> {
+        self.write_cmd(CanvasCommand::new(
+            CanvasOp::EndContour,
+            Vec2::zero(),
+            Vec2::zero(),
+            Vec2::zero()
+        ));
+        
+        self.transition()
     }
 }
\ No newline at end of file
@@ -1,55 +1,264 @@
+use std::mem;
+use std::ptr;
 use std::slice;
 use std::ffi::CString;
 
 use ash::{vk, Device};
-use anyhow::{Result, Context};
+use anyhow::{bail, Result, Context};
+use vek::{Aabr, Rect, Rgba, Vec2};
 
 use crate::renderer::vk_util::{
     frame_queue::{FrameQueue, FrameInfo},
-    vma::VmaAllocator,
-    buffer::TransferBuffer
+    vma::{VmaAllocator, AllocInfo, VmaBuffer},
+    buffer::TransferBuffer,
+    image::Image2D
 };
 
-use super::recorder::{CMD_LIST_BUF_SIZE, Canvas2DRecorder, InitState};
+use super::recorder::{
+    CMD_SIZE, PATH_INFO_SIZE, NO_BLUR_LAYER,
+    CanvasCommand, PathInfo, CanvasRecording, Canvas2DRecorder, InitState, pack_color
+};
+use super::paint::TextureId;
+use super::scene::CanvasScene;
+use super::capture::CanvasCapture;
+use super::damage::{self, DamageTracker};
+
+pub(super) const TILE_SIZE: u32 = 16; // Must match TILE_SIZE in canvas_2d_common.glsl, the raster pass uses one workgroup per tile
+const BIN_WG_SIZE: u32 = 64; // Workgroup size of the bounding box and coarse binning passes
+const BBOX_SIZE: u64 = 16; // Each path's bounding box is a vec4
+const INITIAL_BUF_SIZE: u64 = 1 << 15; // Frame buffers start out with 32 KiB, e.g. space for 1024 commands
+const MAX_BUF_SIZE: u64 = 1 << 27; // Smallest maxStorageBufferRange allowed by the Vulkan spec
+pub(super) const MAX_TEXTURES: u32 = 64; // Must match MAX_TEXTURES in canvas_2d.comp
+pub(super) const MAX_REGISTERED_TEXTURES: u32 = MAX_TEXTURES - 1; // The last slot is kept for PLACEHOLDER_TEXTURE
+const MAX_BLUR_RADIUS: u32 = 128; // Must match MAX_BLUR_RADIUS in canvas_2d_blur.comp
+
+// One sampler per (filter mode, extend mode) pair, indexed by filter * 3 + extend
+const SAMPLER_MODES: [(vk::Filter, vk::SamplerAddressMode); 6] = [
+    (vk::Filter::NEAREST, vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    (vk::Filter::NEAREST, vk::SamplerAddressMode::REPEAT),
+    (vk::Filter::NEAREST, vk::SamplerAddressMode::MIRRORED_REPEAT),
+    (vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    (vk::Filter::LINEAR, vk::SamplerAddressMode::REPEAT),
+    (vk::Filter::LINEAR, vk::SamplerAddressMode::MIRRORED_REPEAT)
+];
+
+const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB; // Sampling converts texels to linear light, which the shader composites in
+const LAYER_IMAGE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT; // Must match layerImages in canvas_2d_common.glsl
+const TARGET_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM; // Storage images can't have sRGB formats, so render targets are sampled through a TEXTURE_FORMAT view
+const WINDOW_CLEAR_COLOR: Rgba<u8> = Rgba { r: 0, g: 0, b: 0, a: 255 };
 
-const WG_SIZE: u32 = 8; // Workgroup size = (8, 8)
+/// Texture slot that's never registered, so it always points to the placeholder texture. Replayed
+/// captures sample it in place of the textures they're missing
+pub(super) const PLACEHOLDER_TEXTURE: TextureId = TextureId(MAX_REGISTERED_TEXTURES as u16);
+
+/// Includes a compiled shader from the shaders directory
+macro_rules! include_shader {
+    ($name:literal) => {
+        include_bytes!(concat!(
+            "..", env!("PATH_SEPERATOR"),
+            "..", env!("PATH_SEPERATOR"),
+            "..", env!("PATH_SEPERATOR"),
+            "shaders", env!("PATH_SEPERATOR"),
+            $name
+        )).as_slice()
+    };
+}
 
 /// Canvas2D renderer
+///
+/// Rendering is split into three compute passes:
+/// 1) Compute the window space bounding box of each path
+/// 2) Bin the paths into the 16x16 tiles their bounding boxes overlap
+/// 3) Rasterize each tile, only processing the paths binned into it
+///
+/// Blurred layers add passes between 2 and 3, each one has its contents rasterized into an
+/// image of its own, which is then blurred horizontally and vertically
+///
+/// Swapchain images keep what was last drawn to them, so when the parts of the window that
+/// changed are known, the rasterization and blur passes are only dispatched over the tiles
+/// that are out of date
+///
+/// Besides the window, the canvas can be drawn into offscreen render targets, which can then be
+/// drawn like any other texture
 pub struct Canvas2DRenderer {
-    cmd_list_bufs: Vec<TransferBuffer>,
+    recording: CanvasRecording,
+    window: TargetFrames,
+    
+    /// Rectangles changed by the previous frame, or `None` if everything might have
+    present_damage: Option<Vec<vk::RectLayerKHR>>,
+    
+    /// Whether the next window frame should be captured, and the capture once it has been
+    capture_next: bool,
+    capture: Option<CanvasCapture>,
+    antialiasing: Antialiasing,
     desc_pool: vk::DescriptorPool,
-    cmd_list_desc_sets: Vec<vk::DescriptorSet>,
+    frame_set_layout: vk::DescriptorSetLayout,
+    image_set_layout: vk::DescriptorSetLayout,
     image_desc_sets: Vec<vk::DescriptorSet>,
+    targets: Vec<Option<RenderTarget>>,
+    texture_desc_set: vk::DescriptorSet,
+    samplers: Vec<vk::Sampler>,
+    textures: Vec<Option<Texture>>,
+    placeholder_texture: Image2D,
+    
+    /// Largest width or height of a texture or render target, the device's `maxImageDimension2D`
+    max_texture_size: u32,
+    
     pipeline_layout: vk::PipelineLayout,
-    pipeline: vk::Pipeline
+    bbox_pipeline: vk::Pipeline,
+    coarse_pipeline: vk::Pipeline,
+    raster_pipeline: vk::Pipeline,
+    blur_pipeline: vk::Pipeline
+}
+
+/// Handle to an offscreen render target created with [`Canvas2DRenderer::create_target()`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TargetId(u16);
+
+/// How the edges of fill and clip contours are antialiased, strokes are antialiased by their
+/// distance to the pixel either way
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Antialiasing {
+    /// Exact area of each pixel covered, like font rasterizers compute it. Only edges of
+    /// opposite windings overlapping within a pixel are approximated
+    #[default]
+    AreaCoverage = 0,
+    
+    /// Fraction of a 4x4 grid of samples in each pixel that are covered, much slower than area
+    /// coverage, for comparing it against
+    Supersampled = 1
+}
+
+/// An offscreen image the canvas is drawn into like the window
+struct RenderTarget {
+    /// Written by the raster pass through its [`TARGET_FORMAT`] view
+    image: Image2D,
+    
+    /// Decodes texels to linear light when the target is sampled as a texture
+    srgb_view: vk::ImageView,
+    clear_color: Rgba<u8>,
+    texture_id: TextureId,
+    
+    /// Holds the target's frame buffer and image descriptor sets
+    desc_pool: vk::DescriptorPool,
+    image_desc_set: vk::DescriptorSet,
+    frames: TargetFrames
+}
+
+/// Buffers for drawing into the window or a render target, and what's been drawn into it
+struct TargetFrames {
+    /// One set per frame in flight
+    frame_bufs: Vec<FrameBuffers>,
+    damage_tracker: DamageTracker,
+    
+    /// ID of the scene drawn by the previous frame, if it drew one
+    drawn_scene: Option<u64>
+}
+
+/// What a texture slot holds
+enum Texture {
+    /// An uploaded image
+    Image(Image2D),
+    
+    /// The sRGB view of a render target, which owns the image
+    Target
+}
+
+/// Where a recording is drawn
+#[derive(Clone, Copy)]
+enum DrawTarget {
+    /// The frame's swapchain image
+    Window,
+    
+    /// The render target in the given slot
+    Offscreen(usize)
+}
+
+/// Push constants shared by all passes, laid out to match `PushConstants` in canvas_2d_common.glsl
+#[repr(C)]
+struct PushConstants {
+    num_tiles: [u32; 2],
+    tile_offset: [u32; 2],
+    num_paths: u32,
+    mask_words: u32,
+    first_path: u32,
+    end_path: u32,
+    target_layer: u32,
+    blur_std_dev: f32,
+    blur_vertical: u32,
+    clear_color: u32,
+    antialiasing: u32
 }
 
+/// Buffers used to render a frame, each frame in flight has its own set
+///
+/// These are grown as needed to fit each frame's recording
+struct FrameBuffers {
+    cmd_list: TransferBuffer,
+    paths: TransferBuffer,
+    bboxes: DeviceBuffer,
+    tile_masks: DeviceBuffer,
+    
+    /// Array layer 0 holds horizontally blurred layers, and array layer i + 1 holds blurred layer i
+    layer_images: Image2D,
+    desc_set: vk::DescriptorSet,
+    
+    /// ID of the scene whose commands and paths are in the buffers, so they're only uploaded when it changes
+    uploaded_scene: Option<u64>
+}
+
+/// A buffer only accessed by the shaders
+struct DeviceBuffer {
+    buf: VmaBuffer,
+    size: u64
+}
+
+unsafe impl Send for DeviceBuffer {}
+
 impl Canvas2DRenderer {
     pub fn new(
         device: &Device,
         frame_queue: &FrameQueue,
         vma_alloc: &VmaAllocator,
-        frames_in_flight: u32
+        cmd_pool: vk::CommandPool,
+        queue: vk::Queue,
+        frames_in_flight: u32,
+        max_texture_size: u32
     ) -> Result<Self> {
-        // Create canvas command list buffers
-        let cmd_list_bufs = {
-            let create_info = vk::BufferCreateInfo::builder()
-                .size(CMD_LIST_BUF_SIZE)
-                .usage(vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
-                .sharing_mode(vk::SharingMode::EXCLUSIVE);
-                
-            (0..frames_in_flight)
-                .map(|_| TransferBuffer::new(vma_alloc, &create_info))
-                .collect::<Result<Vec<TransferBuffer>>>()
-                .context("Failed to create canvas command list buffers")?
-        };
-            
         // Create descriptor set layouts
-        let cmd_list_set_layout = unsafe {
+        // Frame buffers are bound as: 0 = command list, 1 = paths, 2 = bounding boxes, 3 = tile masks,
+        // 4 = layer images
+        let frame_set_layout = unsafe {
+            let bindings = (0..5)
+                .map(|binding| {
+                    let descriptor_type = if binding == 4 {
+                        vk::DescriptorType::STORAGE_IMAGE
+                    }
+                    else {
+                        vk::DescriptorType::STORAGE_BUFFER
+                    };
+                    
+                    vk::DescriptorSetLayoutBinding::builder()
+                        .binding(binding)
+                        .descriptor_type(descriptor_type)
+                        .descriptor_count(1)
+                        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                        .build()
+                })
+                .collect::<Vec<_>>();
+            
+            let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        
+            device
+                .create_descriptor_set_layout(&create_info, None)
+                .context("Failed to create canvas frame buffer descriptor set layout")?
+        };
+        
+        let image_set_layout = unsafe {
             let bindings = [
                 vk::DescriptorSetLayoutBinding::builder()
                     .binding(0)
-                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
                     .descriptor_count(1)
                     .stage_flags(vk::ShaderStageFlags::COMPUTE)
                     .build()
@@ -59,16 +268,41 @@ impl Canvas2DRenderer {
         
             device
                 .create_descriptor_set_layout(&create_info, None)
-                .context("Failed to create canvas command list buffer descriptor set layout")?
+                .context("Failed to create swapchain image descriptor set layout")?
         };
         
-        let image_set_layout = unsafe {
+        // Create texture samplers
+        let samplers = SAMPLER_MODES
+            .iter()
+            .map(|&(filter, address_mode)| unsafe {
+                let create_info = vk::SamplerCreateInfo::builder()
+                    .mag_filter(filter)
+                    .min_filter(filter)
+                    .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                    .address_mode_u(address_mode)
+                    .address_mode_v(address_mode)
+                    .address_mode_w(address_mode)
+                    .max_lod(0.0);
+                
+                device.create_sampler(&create_info, None)
+            })
+            .collect::<Result<Vec<vk::Sampler>, vk::Result>>()
+            .context("Failed to create texture samplers")?;
+        
+        let texture_set_layout = unsafe {
             let bindings = [
                 vk::DescriptorSetLayoutBinding::builder()
                     .binding(0)
-                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
-                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .descriptor_count(MAX_TEXTURES)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .build(),
+                    
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(1)
+                    .descriptor_type(vk::DescriptorType::SAMPLER)
                     .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .immutable_samplers(&samplers)
                     .build()
             ];
             
@@ -76,30 +310,42 @@ impl Canvas2DRenderer {
         
             device
                 .create_descriptor_set_layout(&create_info, None)
-                .context("Failed to create swapchain image descriptor set layout")?
+                .context("Failed to create texture descriptor set layout")?
         };
         
         // Create descriptor pool
-        // Number of UNIFORM_BUFFER descriptors = 1 per frame in flight
-        // Number of STORAGE_IMAGE descriptors = number of swapchain images
-        // Number of descriptor sets = frames in flight + number of swapchain images
+        // Number of STORAGE_BUFFER descriptors = 4 per frame in flight
+        // Number of STORAGE_IMAGE descriptors = number of swapchain images + 1 per frame in flight
+        // Number of SAMPLED_IMAGE descriptors = max textures
+        // Number of SAMPLER descriptors = number of samplers
+        // Number of descriptor sets = frames in flight + number of swapchain images + 1 texture set
         let num_swap_images = frame_queue.swap_image_views().len();
         
         let desc_pool = unsafe {
             let pool_sizes = [
                 vk::DescriptorPoolSize::builder()
-                    .ty(vk::DescriptorType::UNIFORM_BUFFER)
-                    .descriptor_count(frames_in_flight)
+                    .ty(vk::DescriptorType::STORAGE_BUFFER)
+                    .descriptor_count(4 * frames_in_flight)
                     .build(),
     
                 vk::DescriptorPoolSize::builder()
                     .ty(vk::DescriptorType::STORAGE_IMAGE)
-                    .descriptor_count(num_swap_images as u32)
+                    .descriptor_count(num_swap_images as u32 + frames_in_flight)
+                    .build(),
+                    
+                vk::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::SAMPLED_IMAGE)
+                    .descriptor_count(MAX_TEXTURES)
+                    .build(),
+                    
+                vk::DescriptorPoolSize::builder()
+                    .ty(vk::DescriptorType::SAMPLER)
+                    .descriptor_count(samplers.len() as u32)
                     .build()
             ];
             
             let create_info = vk::DescriptorPoolCreateInfo::builder()
-                .max_sets(frames_in_flight + num_swap_images as u32)
+                .max_sets(frames_in_flight + num_swap_images as u32 + 1)
                 .pool_sizes(&pool_sizes);
     
             device
@@ -108,8 +354,8 @@ impl Canvas2DRenderer {
         };
         
         // Allocate descriptor sets
-        let cmd_list_desc_sets = unsafe {
-            let set_layouts = vec![cmd_list_set_layout; frames_in_flight as usize];
+        let frame_desc_sets = unsafe {
+            let set_layouts = vec![frame_set_layout; frames_in_flight as usize];
 
             let alloc_info = vk::DescriptorSetAllocateInfo::builder()
                 .descriptor_pool(desc_pool)
@@ -117,7 +363,7 @@ impl Canvas2DRenderer {
                 
             device
                 .allocate_descriptor_sets(&alloc_info)
-                .context("Failed to allocate canvas command list descriptor sets")?
+                .context("Failed to allocate canvas frame buffer descriptor sets")?
         };
         
         let image_desc_sets = unsafe {
@@ -132,210 +378,1330 @@ impl Canvas2DRenderer {
                 .context("Failed to allocate swapchain image descriptor sets")?
         };
         
-        // Update descriptor sets
-        // Update command list descriptor sets
-        unsafe {
-            let buf_infos = cmd_list_bufs
-                .iter()
-                .map(|buf| {
-                    let info = vk::DescriptorBufferInfo {
-                        buffer: buf.buf(),
-                        offset: 0,
-                        range: buf.size()
-                    };
-                    
-                    [info]
-                })
-                .collect::<Vec<_>>();
-                
-            let writes = cmd_list_desc_sets
-                .iter()
-                .zip(&buf_infos)
-                .map(|(desc_set, buf_info)| {
-                    vk::WriteDescriptorSet::builder()
-                        .dst_set(*desc_set)
-                        .dst_binding(0)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                        .buffer_info(buf_info)
-                        .build()
-                })
-                .collect::<Vec<_>>();
+        let texture_desc_set = unsafe {
+            let set_layouts = [texture_set_layout];
+
+            let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(desc_pool)
+                .set_layouts(&set_layouts);
                 
-            device.update_descriptor_sets(&writes, &[]);
-        }
+            device
+                .allocate_descriptor_sets(&alloc_info)
+                .context("Failed to allocate texture descriptor set")?[0]
+        };
+        
+        // Create placeholder texture, unused texture slots point to this
+        let placeholder_texture = Image2D::new(
+            device,
+            vma_alloc,
+            vk::Extent2D { width: 1, height: 1 },
+            TEXTURE_FORMAT,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST
+        )?;
+        
+        placeholder_texture
+            .upload(device, vma_alloc, cmd_pool, queue, &[255; 4], vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .context("Failed to upload placeholder texture")?;
+        
+        // Create frame buffers, these are grown as needed while rendering
+        let window = TargetFrames::new(device, vma_alloc, frame_desc_sets, num_swap_images)?;
+        
+        // Update descriptor sets
         
         // Update swapchain image descriptor sets
+        write_image_desc_sets(device, &image_desc_sets, frame_queue.swap_image_views());
+        
+        // Point all texture slots to the placeholder texture
         unsafe {
-            let image_infos = frame_queue
-                .swap_image_views()
-                .iter()
-                .map(|&image_view| {
-                    let info = vk::DescriptorImageInfo {
-                        sampler: vk::Sampler::null(),
-                        image_view,
-                        image_layout: vk::ImageLayout::GENERAL
-                    };
-                    
-                    [info]
-                })
-                .collect::<Vec<_>>();
-                
-            let writes = image_desc_sets
-                .iter()
-                .zip(&image_infos)
-                .map(|(desc_set, image_info)| {
-                    vk::WriteDescriptorSet::builder()
-                        .dst_set(*desc_set)
-                        .dst_binding(0)
-                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
-                        .image_info(image_info)
-                        .build()
-                })
-                .collect::<Vec<_>>();
+            let image_infos = vec![
+                vk::DescriptorImageInfo {
+                    sampler: vk::Sampler::null(),
+                    image_view: placeholder_texture.view(),
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                };
+                MAX_TEXTURES as usize
+            ];
+            
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(texture_desc_set)
+                .dst_binding(0)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&image_infos)
+                .build();
                 
-            device.update_descriptor_sets(&writes, &[]);
+            device.update_descriptor_sets(&[write], &[]);
         }
         
-        // Create compute pipeline layout
+        // Create compute pipeline layout, shared by all passes
         let pipeline_layout = unsafe {
-            let set_layouts = [cmd_list_set_layout, image_set_layout];
-            let create_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
+            let set_layouts = [frame_set_layout, image_set_layout, texture_set_layout];
+            let push_constant_ranges = [
+                vk::PushConstantRange::builder()
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .offset(0)
+                    .size(mem::size_of::<PushConstants>() as u32)
+                    .build()
+            ];
+            
+            let create_info = vk::PipelineLayoutCreateInfo::builder()
+                .set_layouts(&set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
             
             device
                 .create_pipeline_layout(&create_info, None)
                 .context("Failed to create pipeline layout")?
         };
         
-        // Create shader module
-        let shader_module = unsafe {
-            let shader_spv = include_bytes!(concat!(
-                "..", env!("PATH_SEPERATOR"),
-                "..", env!("PATH_SEPERATOR"),
-                "..", env!("PATH_SEPERATOR"),
-                "shaders", env!("PATH_SEPERATOR"),
-                "canvas_2d.spv"
-            )).as_slice();
-            
-            // Convert [u8] to [u32]
-            let shader_spv = {
-                let len = shader_spv.len() / 4;
-                slice::from_raw_parts(shader_spv.as_ptr() as *const u32, len)
-            };
-            
-            let create_info = vk::ShaderModuleCreateInfo::builder().code(shader_spv);
-            
-            device
-                .create_shader_module(&create_info, None)
-                .context("Failed to create shader module")?
-        };
+        // Create compute pipelines
+        let bbox_pipeline = create_pipeline(device, pipeline_layout, include_shader!("canvas_2d_bbox.spv"))
+            .context("Failed to create canvas bounding box pipeline")?;
         
-        // Create compute pipeline
-        let pipeline = unsafe {
-            // Set workgroup size specialization constant
-            let spec_consts_buf = [WG_SIZE];
-            
-            // Convert [u32] to [u8]
-            let spec_consts_buf = {
-                let len = spec_consts_buf.len() * 4;
-                slice::from_raw_parts(spec_consts_buf.as_ptr() as *const u8, len)
-            };
-            
-            let spec_consts_entries = [
-                vk::SpecializationMapEntry::builder()
-                    .constant_id(0)
-                    .offset(0)
-                    .size(4)
-                    .build()
-            ];
-            
-            let spec_info = vk::SpecializationInfo::builder()
-                .map_entries(&spec_consts_entries)
-                .data(spec_consts_buf);
-    
-            let entry_point = CString::new("main").unwrap();
-    
-            let stage_create_info = vk::PipelineShaderStageCreateInfo::builder()
-                .stage(vk::ShaderStageFlags::COMPUTE)
-                .module(shader_module)
-                .name(&entry_point)
-                .specialization_info(&spec_info)
-                .build();
-    
-            let create_info = vk::ComputePipelineCreateInfo::builder()
-                .stage(stage_create_info)
-                .layout(pipeline_layout)
-                .build();
-                
-            device
-                .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
-                .map_err(|(_, result)| result)
-                .context("Failed to create canvas compute pipeline")?[0]
-        };
+        let coarse_pipeline = create_pipeline(device, pipeline_layout, include_shader!("canvas_2d_coarse.spv"))
+            .context("Failed to create canvas coarse binning pipeline")?;
+        
+        let raster_pipeline = create_pipeline(device, pipeline_layout, include_shader!("canvas_2d.spv"))
+            .context("Failed to create canvas raster pipeline")?;
         
-        // Destroy unneeded objects
+        let blur_pipeline = create_pipeline(device, pipeline_layout, include_shader!("canvas_2d_blur.spv"))
+            .context("Failed to create canvas blur pipeline")?;
+        
+        // Destroy unneeded objects, the frame buffer and image set layouts are kept around for
+        // allocating render target descriptor sets
         unsafe {
-            device.destroy_shader_module(shader_module, None);
-            device.destroy_descriptor_set_layout(cmd_list_set_layout, None);
-            device.destroy_descriptor_set_layout(image_set_layout, None);
+            device.destroy_descriptor_set_layout(texture_set_layout, None);
         }
         
         Ok(Self {
-            cmd_list_bufs,
+            recording: CanvasRecording::default(),
+            window,
+            present_damage: None,
+            capture_next: false,
+            capture: None,
+            antialiasing: Antialiasing::default(),
             desc_pool,
-            cmd_list_desc_sets,
+            frame_set_layout,
+            image_set_layout,
             image_desc_sets,
+            targets: vec![],
+            texture_desc_set,
+            samplers,
+            textures: vec![],
+            placeholder_texture,
+            max_texture_size,
             pipeline_layout,
-            pipeline
+            bbox_pipeline,
+            coarse_pipeline,
+            raster_pipeline,
+            blur_pipeline
         })
     }
     
+    /// Uploads an RGBA8 image and registers it for use in [`Paint::Image`](super::Paint::Image)
+    ///
+    /// Pixels are sRGB encoded with straight, not premultiplied, alpha, and there must be exactly
+    /// `width * height` of them. Neither side can be 0 or larger than the device's limit
+    ///
+    /// This blocks till the upload is done and the device is idle, so textures should be
+    /// registered up front rather than every frame
+    #[allow(clippy::too_many_arguments)]
+    pub fn register_texture(
+        &mut self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_pool: vk::CommandPool,
+        queue: vk::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[u8]
+    ) -> Result<TextureId> {
+        self.check_texture_size(width, height)?;
+        
+        let expected_len = width as usize * height as usize * 4;
+        
+        if pixels.len() != expected_len {
+            bail!("Texture data is {} bytes, expected {expected_len} for a {width}x{height} texture", pixels.len());
+        }
+        
+        let slot = self.free_texture_slot()?;
+        
+        let texture = Image2D::new(
+            device,
+            vma_alloc,
+            vk::Extent2D { width, height },
+            TEXTURE_FORMAT,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC
+        )?;
+        
+        let upload_result = texture.upload(
+            device,
+            vma_alloc,
+            cmd_pool,
+            queue,
+            pixels,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+        
+        if let Err(err) = upload_result {
+            texture.destroy(device, vma_alloc);
+            return Err(err).context("Failed to upload canvas texture");
+        }
+        
+        // The texture descriptor set is shared between frames, so it can only be updated
+        // once no frame is using it
+        if let Err(err) = unsafe { device.device_wait_idle() } {
+            texture.destroy(device, vma_alloc);
+            return Err(err).context("Failed to wait for device idle");
+        }
+        
+        self.write_texture_desc(device, slot, texture.view());
+        self.textures[slot] = Some(Texture::Image(texture));
+        
+        Ok(TextureId(slot as u16))
+    }
+    
+    /// Destroys a registered texture, its slot may be reused by future registrations
+    ///
+    /// Render target textures are destroyed along with their targets by [`destroy_target()`](Self::destroy_target)
+    pub fn unregister_texture(&mut self, device: &Device, vma_alloc: &VmaAllocator, texture_id: TextureId) -> Result<()> {
+        let slot = texture_id.0 as usize;
+        
+        match self.textures.get(slot) {
+            Some(Some(Texture::Image(_))) => {},
+            Some(Some(Texture::Target)) => bail!("Texture belongs to a render target, destroy the target instead"),
+            _ => bail!("Texture not registered")
+        }
+            
+        unsafe {
+            device.device_wait_idle().context("Failed to wait for device idle")?;
+        }
+        
+        self.write_texture_desc(device, slot, self.placeholder_texture.view());
+        
+        if let Some(Texture::Image(texture)) = self.textures[slot].take() {
+            texture.destroy(device, vma_alloc);
+        }
+        
+        Ok(())
+    }
+    
+    /// Creates an offscreen render target, drawn into with [`cmd_render_scene_target()`](Self::cmd_render_scene_target)
+    /// and drawn like any other texture with [`target_texture()`](Self::target_texture)
+    ///
+    /// The target starts out as `clear_color`, which every frame drawn into it is drawn on top of.
+    /// Like the window, it keeps what was last drawn into it, so only damaged tiles are redrawn.
+    /// Each target takes up one of the texture slots
+    ///
+    /// This blocks till the device is idle, so targets should be created up front rather than
+    /// every frame
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_target(
+        &mut self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_pool: vk::CommandPool,
+        queue: vk::Queue,
+        width: u32,
+        height: u32,
+        clear_color: Rgba<u8>
+    ) -> Result<TargetId> {
+        self.check_texture_size(width, height)?;
+        
+        let texture_slot = self.free_texture_slot()?;
+        
+        let target_slot = match self.targets.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => {
+                self.targets.push(None);
+                self.targets.len() - 1
+            }
+        };
+        
+        let image = Image2D::new_mutable(
+            device,
+            vma_alloc,
+            vk::Extent2D { width, height },
+            TARGET_FORMAT,
+            vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_DST
+                | vk::ImageUsageFlags::TRANSFER_SRC
+        ).context("Failed to create render target image")?;
+        
+        // The raster pass writes sRGB encoded colors with straight alpha, so the clear color is
+        // stored as is
+        let clear_value = clear_color.map(|c| c as f32 / 255.0).into_array();
+        
+        let srgb_view = image
+            .clear(device, cmd_pool, queue, clear_value, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .and_then(|_| image.create_view(device, TEXTURE_FORMAT));
+        
+        let srgb_view = match srgb_view {
+            Ok(srgb_view) => srgb_view,
+            Err(err) => {
+                image.destroy(device, vma_alloc);
+                return Err(err).context("Failed to create render target");
+            }
+        };
+        
+        // Create the target's own descriptor sets and frame buffers, so it can be drawn into in
+        // the same frames as the window
+        let frames_in_flight = self.window.frame_bufs.len();
+        
+        let desc_sets = create_target_desc_sets(device, self.frame_set_layout, self.image_set_layout, frames_in_flight);
+        let frames = desc_sets.and_then(|(desc_pool, frame_desc_sets, image_desc_set)| {
+            match TargetFrames::new(device, vma_alloc, frame_desc_sets, 1) {
+                Ok(frames) => Ok((desc_pool, image_desc_set, frames)),
+                Err(err) => {
+                    unsafe { device.destroy_descriptor_pool(desc_pool, None) };
+                    Err(err)
+                }
+            }
+        });
+        
+        let (desc_pool, image_desc_set, frames) = match frames {
+            Ok(frames) => frames,
+            Err(err) => {
+                unsafe { device.destroy_image_view(srgb_view, None) };
+                image.destroy(device, vma_alloc);
+                return Err(err).context("Failed to create render target");
+            }
+        };
+        
+        write_image_desc_sets(device, &[image_desc_set], &[image.view()]);
+        
+        let target = RenderTarget {
+            image,
+            srgb_view,
+            clear_color,
+            texture_id: TextureId(texture_slot as u16),
+            desc_pool,
+            image_desc_set,
+            frames
+        };
+        
+        // The texture descriptor set is shared between frames, so it can only be updated
+        // once no frame is using it
+        if let Err(err) = unsafe { device.device_wait_idle() } {
+            target.destroy(device, vma_alloc);
+            return Err(err).context("Failed to wait for device idle");
+        }
+        
+        self.write_texture_desc(device, texture_slot, srgb_view);
+        self.textures[texture_slot] = Some(Texture::Target);
+        self.targets[target_slot] = Some(target);
+        
+        Ok(TargetId(target_slot as u16))
+    }
+    
+    /// Destroys a render target along with its texture, their slots may be reused by future
+    /// targets and textures
+    ///
+    /// Blocks till the device is idle
+    pub fn destroy_target(&mut self, device: &Device, vma_alloc: &VmaAllocator, target_id: TargetId) -> Result<()> {
+        let target = self.targets
+            .get_mut(target_id.0 as usize)
+            .and_then(Option::take)
+            .context("Render target not created")?;
+            
+        unsafe {
+            device.device_wait_idle().context("Failed to wait for device idle")?;
+        }
+        
+        let texture_slot = target.texture_id.0 as usize;
+        
+        self.write_texture_desc(device, texture_slot, self.placeholder_texture.view());
+        self.textures[texture_slot] = None;
+        target.destroy(device, vma_alloc);
+        
+        Ok(())
+    }
+    
+    /// Texture that draws a render target's contents with [`Paint::Image`](super::Paint::Image),
+    /// or `None` if the target doesn't exist
+    ///
+    /// A target's own texture can't be drawn into it
+    pub fn target_texture(&self, target_id: TargetId) -> Option<TextureId> {
+        self.target(target_id).map(|target| target.texture_id)
+    }
+    
+    fn target(&self, target_id: TargetId) -> Option<&RenderTarget> {
+        self.targets.get(target_id.0 as usize)?.as_ref()
+    }
+    
+    /// Checks that a texture or render target of the given size can be created
+    fn check_texture_size(&self, width: u32, height: u32) -> Result<()> {
+        if width == 0 || height == 0 {
+            bail!("Canvas texture can't be {width}x{height}, it must be at least 1 pixel wide and high");
+        }
+        
+        if width > self.max_texture_size || height > self.max_texture_size {
+            bail!("Canvas texture is {width}x{height}, larger than the device's limit of {0}x{0}", self.max_texture_size);
+        }
+        
+        Ok(())
+    }
+    
+    /// Finds a free texture slot, adding one if there's none and the texture limit hasn't been reached
+    fn free_texture_slot(&mut self) -> Result<usize> {
+        match self.textures.iter().position(Option::is_none) {
+            Some(slot) => Ok(slot),
+            None if self.textures.len() < MAX_REGISTERED_TEXTURES as usize => {
+                self.textures.push(None);
+                Ok(self.textures.len() - 1)
+            },
+            None => bail!("Canvas texture limit of {MAX_REGISTERED_TEXTURES} reached")
+        }
+    }
+    
+    fn write_texture_desc(&self, device: &Device, slot: usize, image_view: vk::ImageView) {
+        let image_info = [
+            vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            }
+        ];
+        
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.texture_desc_set)
+            .dst_binding(0)
+            .dst_array_element(slot as u32)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(&image_info)
+            .build();
+            
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+    }
+    
+    /// Records the canvas commands for a frame and the dispatches that draw them
+    ///
+    /// `damage` holds the window space rectangles that changed since the previous frame, only
+    /// the tiles they touch are redrawn. If it's `None`, the whole window is
+    ///
+    /// The frame's buffers are grown if the recording doesn't fit in them, this fails if
+    /// the recording is too big for any buffer the device is guaranteed to support, or if its
+    /// clips are nested more than 8 deep
     pub fn cmd_render(
         &mut self,
         device: &Device,
+        vma_alloc: &VmaAllocator,
         cmd_buf: vk::CommandBuffer,
         frame_info: &FrameInfo,
+        damage: Option<&[Rect<f32, f32>]>,
         record_fn: impl Fn(Canvas2DRecorder<InitState>) -> Canvas2DRecorder<InitState>
-    ) {
+    ) -> Result<()> {
+        // Record canvas commands
+        let recording = record_fn(Canvas2DRecorder::new(mem::take(&mut self.recording))).end()?;
+        
+        self.window.drawn_scene = None;
+        
+        let result = self.cmd_draw_recording(device, vma_alloc, cmd_buf, frame_info, DrawTarget::Window, &recording, None, damage);
+        
+        // Keep the recording's allocations around for the next frame
+        self.recording = recording;
+        
+        result
+    }
+    
+    /// Records the dispatches that draw a retained scene, see [`cmd_render()`](Self::cmd_render)
+    ///
+    /// The scene's commands are only uploaded if the frame's buffers don't hold them already,
+    /// i.e. the first few frames after it's recorded. Only the damage reported while recording
+    /// it with [`CanvasScene::record_damaged()`] is redrawn, and nothing is if it hasn't changed
+    pub fn cmd_render_scene(
+        &mut self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_buf: vk::CommandBuffer,
+        frame_info: &FrameInfo,
+        scene: &CanvasScene
+    ) -> Result<()> {
+        self.cmd_render_scene_into(device, vma_alloc, cmd_buf, frame_info, DrawTarget::Window, scene)
+    }
+    
+    /// Records the dispatches that draw a retained scene into a render target, see
+    /// [`cmd_render_scene()`](Self::cmd_render_scene)
+    ///
+    /// Damage is in the target's pixels, and targets can be drawn into before or after the window
+    /// in the same frame. If the target's texture is drawn into the window, the parts of the
+    /// window it's drawn over have to be damaged whenever the target changes
+    pub fn cmd_render_scene_target(
+        &mut self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_buf: vk::CommandBuffer,
+        frame_info: &FrameInfo,
+        target_id: TargetId,
+        scene: &CanvasScene
+    ) -> Result<()> {
+        let target = self.draw_target(target_id)?;
+        
+        self.cmd_render_scene_into(device, vma_alloc, cmd_buf, frame_info, target, scene)
+    }
+    
+    /// Captures the next recording drawn into the window, which is taken with [`take_capture()`](Self::take_capture)
+    /// once the frame's been submitted
+    pub fn capture_next_frame(&mut self) {
+        self.capture_next = true;
+        self.capture = None;
+    }
+    
+    /// Takes the capture requested with [`capture_next_frame()`](Self::capture_next_frame),
+    /// downloading the textures its image paints sample
+    ///
+    /// Textures that were unregistered since the frame was drawn are left out. This blocks till
+    /// the device is idle, so captures should only be taken for debugging
+    pub fn take_capture(
+        &mut self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_pool: vk::CommandPool,
+        queue: vk::Queue
+    ) -> Result<CanvasCapture> {
+        let mut capture = self.capture.take().context("No canvas frame captured")?;
+        
+        // Wait for the captured frame to finish drawing into render targets it samples
+        unsafe {
+            device.device_wait_idle().context("Failed to wait for device idle")?;
+        }
+        
+        for texture_id in capture.sampled_textures() {
+            let image = match self.textures.get(texture_id.0 as usize) {
+                Some(Some(Texture::Image(image))) => image,
+                Some(Some(Texture::Target)) => {
+                    let target = self.targets
+                        .iter()
+                        .flatten()
+                        .find(|target| target.texture_id == texture_id)
+                        .unwrap();
+                        
+                    &target.image
+                },
+                _ => continue
+            };
+            
+            let pixels = image
+                .download(device, vma_alloc, cmd_pool, queue, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .context("Failed to download captured canvas texture")?;
+                
+            let extent = image.extent();
+            capture.add_texture(texture_id, extent.width, extent.height, pixels);
+        }
+        
+        Ok(capture)
+    }
+    
+    /// Registers a capture's textures, and turns its recording into a scene that samples them,
+    /// which can be drawn with [`cmd_render_scene()`](Self::cmd_render_scene)
+    ///
+    /// Returns the scene along with the registered textures, which are unregistered by the caller
+    /// once the scene is no longer drawn. Like [`register_texture()`](Self::register_texture),
+    /// this blocks till the device is idle
+    pub fn load_capture(
+        &mut self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_pool: vk::CommandPool,
+        queue: vk::Queue,
+        capture: &CanvasCapture
+    ) -> Result<(CanvasScene, Vec<TextureId>)> {
+        let mut remap = vec![];
+        
+        for texture in capture.textures() {
+            let texture_id = self.register_texture(device, vma_alloc, cmd_pool, queue, texture.width, texture.height, &texture.pixels);
+            
+            match texture_id {
+                Ok(texture_id) => remap.push((texture.id, texture_id)),
+                Err(err) => {
+                    for (_, texture_id) in remap {
+                        self.unregister_texture(device, vma_alloc, texture_id)?;
+                    }
+                    
+                    return Err(err).context("Failed to register captured canvas texture");
+                }
+            }
+        }
+        
+        let texture_ids = remap.iter().map(|(_, texture_id)| *texture_id).collect();
+        let scene = CanvasScene::from_recording(capture.replay_recording(&remap));
+        
+        Ok((scene, texture_ids))
+    }
+    
+    /// Sets how fills are antialiased, the window and every render target are fully redrawn the
+    /// next time they're drawn if it changes
+    pub fn set_antialiasing(&mut self, antialiasing: Antialiasing) {
+        if antialiasing == self.antialiasing {
+            return;
+        }
+        
+        self.antialiasing = antialiasing;
+        self.window.damage_tracker.invalidate();
+        
+        for target in self.targets.iter_mut().flatten() {
+            target.frames.damage_tracker.invalidate();
+        }
+    }
+    
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.antialiasing
+    }
+    
+    /// Rectangles of the swapchain image changed by the last frame recorded, for passing on to
+    /// the presentation engine with `VK_KHR_incremental_present`, or `None` if all of it might have
+    pub fn present_damage(&self) -> Option<&[vk::RectLayerKHR]> {
+        self.present_damage.as_deref()
+    }
+    
+    fn draw_target(&self, target_id: TargetId) -> Result<DrawTarget> {
+        self.target(target_id).context("Render target not created")?;
+        
+        Ok(DrawTarget::Offscreen(target_id.0 as usize))
+    }
+    
+    fn cmd_render_scene_into(
+        &mut self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_buf: vk::CommandBuffer,
+        frame_info: &FrameInfo,
+        target: DrawTarget,
+        scene: &CanvasScene
+    ) -> Result<()> {
+        let frames = self.target_frames(target);
+        let damage = frames.drawn_scene.and_then(|scene_id| scene.damage_since(scene_id));
+        frames.drawn_scene = Some(scene.id);
+        
+        self.cmd_draw_recording(device, vma_alloc, cmd_buf, frame_info, target, &scene.recording, Some(scene.id), damage.as_deref())
+    }
+    
+    fn target_frames(&mut self, target: DrawTarget) -> &mut TargetFrames {
+        match target {
+            DrawTarget::Window => &mut self.window,
+            DrawTarget::Offscreen(slot) => &mut self.targets[slot].as_mut().unwrap().frames
+        }
+    }
+    
+    /// Records the dispatches that draw a finished recording, uploading it unless it's the scene
+    /// with the given ID and the frame's buffers already hold it
+    #[allow(clippy::too_many_arguments)]
+    fn cmd_draw_recording(
+        &mut self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_buf: vk::CommandBuffer,
+        frame_info: &FrameInfo,
+        target: DrawTarget,
+        recording: &CanvasRecording,
+        scene_id: Option<u64>,
+        damage: Option<&[Rect<f32, f32>]>
+    ) -> Result<()> {
+        // The target's buffers and image, and which of its images is drawn into
+        let (frames, extent, clear_color, image_desc_set, image_idx, target_image) = match target {
+            DrawTarget::Window => (
+                &mut self.window,
+                *frame_info.swap_image_extent(),
+                WINDOW_CLEAR_COLOR,
+                self.image_desc_sets[frame_info.swap_image_idx()],
+                frame_info.swap_image_idx(),
+                None
+            ),
+            
+            DrawTarget::Offscreen(slot) => {
+                let render_target = self.targets[slot].as_mut().unwrap();
+                
+                (
+                    &mut render_target.frames,
+                    render_target.image.extent(),
+                    render_target.clear_color,
+                    render_target.image_desc_set,
+                    0,
+                    Some(&render_target.image)
+                )
+            }
+        };
+        
+        let push_constants = PushConstants {
+            num_tiles: [extent.width.div_ceil(TILE_SIZE), extent.height.div_ceil(TILE_SIZE)],
+            tile_offset: [0, 0],
+            num_paths: recording.paths.len() as u32,
+            mask_words: (recording.paths.len() as u32).div_ceil(32).max(1),
+            first_path: 0,
+            end_path: recording.paths.len() as u32,
+            target_layer: NO_BLUR_LAYER,
+            blur_std_dev: 0.0,
+            blur_vertical: 0,
+            clear_color: pack_color(clear_color),
+            antialiasing: self.antialiasing as u32
+        };
+        
+        let num_tiles = push_constants.num_tiles;
+        
+        if let DrawTarget::Window = target {
+            if mem::take(&mut self.capture_next) {
+                self.capture = Some(CanvasCapture::new(recording.clone(), extent.width, extent.height, clear_color));
+            }
+        }
+        
+        // Work out which tiles are out of date, the ones changed by this frame and by the frames
+        // since the image was last drawn to
+        let frame_damage = damage.map(|rects| damage::tile_rects(rects, num_tiles));
+        
+        if let DrawTarget::Window = target {
+            self.present_damage = frame_damage.as_ref().map(|tiles| present_rects(tiles, &extent));
+        }
+        
+        let redraw = frames.damage_tracker
+            .next_frame(image_idx, num_tiles, frame_damage)
+            .unwrap_or_else(|| vec![Aabr { min: Vec2::zero(), max: Vec2::from(num_tiles) }]);
+        
         // The resources to use for this frame
-        let cmd_list_buf = &self.cmd_list_bufs[frame_info.frame_idx()];
-        let cmd_list_desc_set = self.cmd_list_desc_sets[frame_info.frame_idx()];
-        let image_desc_set = self.image_desc_sets[frame_info.swap_image_idx()];
+        let frame_bufs = &mut frames.frame_bufs[frame_info.frame_idx()];
+        frame_bufs.reserve(device, vma_alloc, recording, &push_constants)?;
         
-        // Record canvas commands
-        record_fn(Canvas2DRecorder::new(cmd_list_buf.ptr())).end();
+        let upload = scene_id.is_none() || frame_bufs.uploaded_scene != scene_id;
+        frame_bufs.uploaded_scene = scene_id;
+        
+        let cmd_list_size = recording.cmds.len() as u64 * CMD_SIZE;
+        let paths_size = recording.paths.len() as u64 * PATH_INFO_SIZE;
         
         unsafe {
-            // Transfer canvas command list buffer
-            cmd_list_buf.cmd_transfer(device, cmd_buf);
+            // Copy the recording to the frame buffers, and transfer them
+            if upload {
+                ptr::copy_nonoverlapping(recording.cmds.as_ptr(), frame_bufs.cmd_list.ptr() as *mut CanvasCommand, recording.cmds.len());
+                ptr::copy_nonoverlapping(recording.paths.as_ptr(), frame_bufs.paths.ptr() as *mut PathInfo, recording.paths.len());
+                
+                frame_bufs.cmd_list.cmd_transfer(device, cmd_buf, cmd_list_size);
+                
+                if paths_size > 0 {
+                    frame_bufs.paths.cmd_transfer(device, cmd_buf, paths_size);
+                }
+                
+                cmd_memory_barrier(
+                    device,
+                    cmd_buf,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::AccessFlags::TRANSFER_WRITE
+                );
+            }
             
-            // Bind pipeline and descriptor sets
-            device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            // The image already holds this frame
+            if redraw.is_empty() {
+                return Ok(());
+            }
+            
+            // Render targets are sampled between frames, their contents are kept so only the
+            // damaged tiles are redrawn
+            if let Some(image) = target_image {
+                let barrier = vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                    .old_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .new_layout(vk::ImageLayout::GENERAL)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(image.image())
+                    .subresource_range(image.subresource_range())
+                    .build();
+                
+                device.cmd_pipeline_barrier(
+                    cmd_buf,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[barrier]
+                );
+            }
+            
+            // Bind descriptor sets and push constants, shared by all passes
             device.cmd_bind_descriptor_sets(
                 cmd_buf,
                 vk::PipelineBindPoint::COMPUTE,
                 self.pipeline_layout,
                 0,
-                &[cmd_list_desc_set, image_desc_set],
+                &[frame_bufs.desc_set, image_desc_set, self.texture_desc_set],
                 &[]
             );
             
-            // Dispatch workgroups
-            let workgroups_x = (frame_info.swap_image_extent().width + WG_SIZE - 1) / WG_SIZE;
-            let workgroups_y = (frame_info.swap_image_extent().height + WG_SIZE - 1) / WG_SIZE;
+            cmd_push_constants(device, cmd_buf, self.pipeline_layout, &push_constants);
+            
+            // Compute path bounding boxes
+            if push_constants.num_paths > 0 {
+                device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.bbox_pipeline);
+                device.cmd_dispatch(cmd_buf, push_constants.num_paths.div_ceil(BIN_WG_SIZE), 1, 1);
+                
+                cmd_memory_barrier(device, cmd_buf, vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE);
+            }
+            
+            // Bin paths into tiles
+            let total_tiles = num_tiles[0] * num_tiles[1];
+            
+            device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.coarse_pipeline);
+            device.cmd_dispatch(cmd_buf, total_tiles.div_ceil(BIN_WG_SIZE), 1, 1);
+            
+            cmd_memory_barrier(device, cmd_buf, vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE);
+            
+            // The layer images are fully overwritten every frame, so their previous contents are discarded
+            let barrier = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(frame_bufs.layer_images.image())
+                .subresource_range(frame_bufs.layer_images.subresource_range())
+                .build();
+            
+            device.cmd_pipeline_barrier(
+                cmd_buf,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+            
+            // Rasterize and blur each blurred layer, nested layers come first so they're ready by
+            // the time the layers containing them are rasterized
+            for (layer_idx, blur_layer) in recording.blur_layers.iter().enumerate() {
+                let layer_push_constants = PushConstants {
+                    first_path: blur_layer.paths.start,
+                    end_path: blur_layer.paths.end,
+                    target_layer: layer_idx as u32,
+                    blur_std_dev: blur_layer.std_dev,
+                    ..push_constants
+                };
+                
+                // The layer is needed in the redrawn tiles, grown by the blurs of the layers
+                // containing it, and its contents are needed in the tiles its blur reaches
+                let outer_blur_radius = recording.blur_layers
+                    .iter()
+                    .filter(|outer| outer.paths.start < blur_layer.paths.start && blur_layer.paths.end < outer.paths.end)
+                    .map(|outer| blur_radius_tiles(outer.std_dev))
+                    .sum();
+                
+                let blurred_tiles = damage::expand_rects(&redraw, outer_blur_radius, num_tiles);
+                let raster_tiles = damage::expand_rects(&blurred_tiles, blur_radius_tiles(blur_layer.std_dev), num_tiles);
+                
+                device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.raster_pipeline);
+                cmd_dispatch_tiles(device, cmd_buf, self.pipeline_layout, &layer_push_constants, &raster_tiles);
+                
+                cmd_memory_barrier(device, cmd_buf, vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE);
+                
+                device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.blur_pipeline);
+                
+                for (blur_vertical, tiles) in [(0, &raster_tiles), (1, &blurred_tiles)] {
+                    let blur_push_constants = PushConstants { blur_vertical, ..layer_push_constants };
+                    
+                    cmd_dispatch_tiles(device, cmd_buf, self.pipeline_layout, &blur_push_constants, tiles);
+                    
+                    cmd_memory_barrier(device, cmd_buf, vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE);
+                }
+            }
             
-            device.cmd_dispatch(cmd_buf, workgroups_x, workgroups_y, 1);
+            // Rasterize tiles
+            device.cmd_bind_pipeline(cmd_buf, vk::PipelineBindPoint::COMPUTE, self.raster_pipeline);
+            cmd_dispatch_tiles(device, cmd_buf, self.pipeline_layout, &push_constants, &redraw);
+            
+            // Make the render target's new contents visible to whatever samples it next
+            if let Some(image) = target_image {
+                let barrier = vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                    .old_layout(vk::ImageLayout::GENERAL)
+                    .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(image.image())
+                    .subresource_range(image.subresource_range())
+                    .build();
+                
+                device.cmd_pipeline_barrier(
+                    cmd_buf,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[barrier]
+                );
+            }
         }
+        
+        Ok(())
     }
     
     pub fn destroy(self, device: &Device, vma_alloc: &VmaAllocator) {
-        for buf in self.cmd_list_bufs {
-            buf.destroy(vma_alloc);
+        self.window.destroy(device, vma_alloc);
+        
+        for target in self.targets.into_iter().flatten() {
+            target.destroy(device, vma_alloc);
         }
+        
+        for texture in self.textures.into_iter().flatten() {
+            if let Texture::Image(texture) = texture {
+                texture.destroy(device, vma_alloc);
+            }
+        }
+        
+        self.placeholder_texture.destroy(device, vma_alloc);
 
         unsafe {
+            for sampler in self.samplers {
+                device.destroy_sampler(sampler, None);
+            }
+            
             device.destroy_descriptor_pool(self.desc_pool, None);
+            device.destroy_descriptor_set_layout(self.frame_set_layout, None);
+            device.destroy_descriptor_set_layout(self.image_set_layout, None);
             device.destroy_pipeline_layout(self.pipeline_layout, None);
-            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline(self.bbox_pipeline, None);
+            device.destroy_pipeline(self.coarse_pipeline, None);
+            device.destroy_pipeline(self.raster_pipeline, None);
+            device.destroy_pipeline(self.blur_pipeline, None);
+        }
+    }
+}
+
+impl RenderTarget {
+    fn destroy(self, device: &Device, vma_alloc: &VmaAllocator) {
+        self.frames.destroy(device, vma_alloc);
+        
+        unsafe {
+            device.destroy_descriptor_pool(self.desc_pool, None);
+            device.destroy_image_view(self.srgb_view, None);
+        }
+        
+        self.image.destroy(device, vma_alloc);
+    }
+}
+
+impl TargetFrames {
+    fn new(device: &Device, vma_alloc: &VmaAllocator, frame_desc_sets: Vec<vk::DescriptorSet>, num_images: usize) -> Result<Self> {
+        let frame_bufs = frame_desc_sets
+            .into_iter()
+            .map(|desc_set| FrameBuffers::new(device, vma_alloc, desc_set))
+            .collect::<Result<Vec<FrameBuffers>>>()
+            .context("Failed to create canvas frame buffers")?;
+        
+        Ok(Self {
+            frame_bufs,
+            damage_tracker: DamageTracker::new(num_images),
+            drawn_scene: None
+        })
+    }
+    
+    fn destroy(self, device: &Device, vma_alloc: &VmaAllocator) {
+        for frame_bufs in self.frame_bufs {
+            frame_bufs.destroy(device, vma_alloc);
+        }
+    }
+}
+
+impl FrameBuffers {
+    fn new(device: &Device, vma_alloc: &VmaAllocator, desc_set: vk::DescriptorSet) -> Result<Self> {
+        let frame_bufs = Self {
+            cmd_list: create_transfer_buf(vma_alloc, INITIAL_BUF_SIZE)?,
+            paths: create_transfer_buf(vma_alloc, INITIAL_BUF_SIZE)?,
+            bboxes: DeviceBuffer::new(vma_alloc, INITIAL_BUF_SIZE)?,
+            tile_masks: DeviceBuffer::new(vma_alloc, INITIAL_BUF_SIZE)?,
+            layer_images: create_layer_images(device, vma_alloc, vk::Extent2D { width: 1, height: 1 }, 1)?,
+            desc_set,
+            uploaded_scene: None
+        };
+        
+        frame_bufs.write_desc_set(device);
+        
+        Ok(frame_bufs)
+    }
+    
+    /// Replaces any buffers too small for the recording with bigger ones, and the layer images
+    /// if they don't cover every tile or have too few layers
+    ///
+    /// Replacing the command or path buffer discards the scene uploaded to them
+    ///
+    /// The frame's previous submission must have finished, which is the case once the frame
+    /// queue hands out the frame again
+    fn reserve(
+        &mut self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        recording: &CanvasRecording,
+        push_constants: &PushConstants
+    ) -> Result<()> {
+        let num_paths = recording.paths.len() as u64;
+        let num_tiles = push_constants.num_tiles[0] as u64 * push_constants.num_tiles[1] as u64;
+        
+        let cmd_list_size = recording.cmds.len() as u64 * CMD_SIZE;
+        let paths_size = num_paths * PATH_INFO_SIZE;
+        let bboxes_size = num_paths * BBOX_SIZE;
+        let tile_masks_size = num_tiles * push_constants.mask_words as u64 * 4;
+        
+        let mut grown = false;
+        
+        if cmd_list_size > self.cmd_list.size() {
+            let new_buf = create_transfer_buf(vma_alloc, grown_size(cmd_list_size, "command list")?)?;
+            mem::replace(&mut self.cmd_list, new_buf).destroy(vma_alloc);
+            self.uploaded_scene = None;
+            grown = true;
+        }
+        
+        if paths_size > self.paths.size() {
+            let new_buf = create_transfer_buf(vma_alloc, grown_size(paths_size, "path list")?)?;
+            mem::replace(&mut self.paths, new_buf).destroy(vma_alloc);
+            self.uploaded_scene = None;
+            grown = true;
+        }
+        
+        if bboxes_size > self.bboxes.size {
+            let new_buf = DeviceBuffer::new(vma_alloc, grown_size(bboxes_size, "bounding box")?)?;
+            mem::replace(&mut self.bboxes, new_buf).destroy(vma_alloc);
+            grown = true;
+        }
+        
+        if tile_masks_size > self.tile_masks.size {
+            let new_buf = DeviceBuffer::new(vma_alloc, grown_size(tile_masks_size, "tile mask")?)?;
+            mem::replace(&mut self.tile_masks, new_buf).destroy(vma_alloc);
+            grown = true;
+        }
+        
+        let tiles_extent = vk::Extent2D {
+            width: push_constants.num_tiles[0] * TILE_SIZE,
+            height: push_constants.num_tiles[1] * TILE_SIZE
+        };
+        
+        let layer_images_extent = self.layer_images.extent();
+        let num_layers = recording.blur_layers.len() as u32 + 1;
+        
+        if !recording.blur_layers.is_empty() && (
+            layer_images_extent.width < tiles_extent.width
+            || layer_images_extent.height < tiles_extent.height
+            || self.layer_images.layers() < num_layers
+        ) {
+            let new_images = create_layer_images(
+                device,
+                vma_alloc,
+                tiles_extent,
+                num_layers.next_power_of_two().max(self.layer_images.layers())
+            )?;
+            
+            mem::replace(&mut self.layer_images, new_images).destroy(device, vma_alloc);
+            grown = true;
+        }
+        
+        if grown {
+            self.write_desc_set(device);
+        }
+        
+        Ok(())
+    }
+    
+    fn write_desc_set(&self, device: &Device) {
+        let buf_infos = [
+            (self.cmd_list.buf(), self.cmd_list.size()),
+            (self.paths.buf(), self.paths.size()),
+            (self.bboxes.buf.buf(), self.bboxes.size),
+            (self.tile_masks.buf.buf(), self.tile_masks.size)
+        ].map(|(buffer, range)| [vk::DescriptorBufferInfo { buffer, offset: 0, range }]);
+        
+        let image_info = [
+            vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: self.layer_images.view(),
+                image_layout: vk::ImageLayout::GENERAL
+            }
+        ];
+        
+        let mut writes = buf_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, buf_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.desc_set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(buf_info)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        
+        writes.push(
+            vk::WriteDescriptorSet::builder()
+                .dst_set(self.desc_set)
+                .dst_binding(4)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&image_info)
+                .build()
+        );
+            
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+    
+    fn destroy(self, device: &Device, vma_alloc: &VmaAllocator) {
+        self.cmd_list.destroy(vma_alloc);
+        self.paths.destroy(vma_alloc);
+        self.bboxes.destroy(vma_alloc);
+        self.tile_masks.destroy(vma_alloc);
+        self.layer_images.destroy(device, vma_alloc);
+    }
+}
+
+impl DeviceBuffer {
+    fn new(vma_alloc: &VmaAllocator, size: u64) -> Result<Self> {
+        let create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(vk::BufferUsageFlags::STORAGE_BUFFER)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            
+        let alloc_info = AllocInfo::new().prefer_device();
+        
+        let buf = vma_alloc
+            .create_buffer(&create_info, &alloc_info)
+            .context("Failed to create canvas device buffer")?;
+            
+        Ok(Self { buf, size })
+    }
+    
+    fn destroy(self, vma_alloc: &VmaAllocator) {
+        vma_alloc.destroy_buffer(self.buf);
+    }
+}
+
+fn create_transfer_buf(vma_alloc: &VmaAllocator, size: u64) -> Result<TransferBuffer> {
+    let create_info = vk::BufferCreateInfo::builder()
+        .size(size)
+        .usage(vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        
+    TransferBuffer::new(vma_alloc, &create_info)
+}
+
+/// Creates a render target's descriptor pool, and allocates a frame buffer descriptor set for
+/// each frame in flight and an image descriptor set from it
+fn create_target_desc_sets(
+    device: &Device,
+    frame_set_layout: vk::DescriptorSetLayout,
+    image_set_layout: vk::DescriptorSetLayout,
+    frames_in_flight: usize
+) -> Result<(vk::DescriptorPool, Vec<vk::DescriptorSet>, vk::DescriptorSet)> {
+    let frames_in_flight = frames_in_flight as u32;
+    
+    let desc_pool = unsafe {
+        let pool_sizes = [
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(4 * frames_in_flight)
+                .build(),
+                
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(frames_in_flight + 1)
+                .build()
+        ];
+        
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(frames_in_flight + 1)
+            .pool_sizes(&pool_sizes);
+            
+        device
+            .create_descriptor_pool(&create_info, None)
+            .context("Failed to create render target descriptor pool")?
+    };
+    
+    let mut set_layouts = vec![frame_set_layout; frames_in_flight as usize];
+    set_layouts.push(image_set_layout);
+    
+    let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(desc_pool)
+        .set_layouts(&set_layouts);
+        
+    match unsafe { device.allocate_descriptor_sets(&alloc_info) } {
+        Ok(mut desc_sets) => {
+            let image_desc_set = desc_sets.pop().unwrap();
+            
+            Ok((desc_pool, desc_sets, image_desc_set))
+        },
+        
+        Err(err) => {
+            unsafe { device.destroy_descriptor_pool(desc_pool, None) };
+            Err(err).context("Failed to allocate render target descriptor sets")
         }
     }
+}
+
+/// Points storage image descriptor sets at the images the raster pass draws into
+fn write_image_desc_sets(device: &Device, desc_sets: &[vk::DescriptorSet], image_views: &[vk::ImageView]) {
+    let image_infos = image_views
+        .iter()
+        .map(|&image_view| {
+            let info = vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view,
+                image_layout: vk::ImageLayout::GENERAL
+            };
+            
+            [info]
+        })
+        .collect::<Vec<_>>();
+        
+    let writes = desc_sets
+        .iter()
+        .zip(&image_infos)
+        .map(|(desc_set, image_info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(*desc_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(image_info)
+                .build()
+        })
+        .collect::<Vec<_>>();
+        
+    unsafe { device.update_descriptor_sets(&writes, &[]) };
+}
+
+fn create_layer_images(device: &Device, vma_alloc: &VmaAllocator, extent: vk::Extent2D, layers: u32) -> Result<Image2D> {
+    Image2D::new_array(device, vma_alloc, extent, layers, LAYER_IMAGE_FORMAT, vk::ImageUsageFlags::STORAGE)
+        .context("Failed to create canvas layer images")
+}
+
+/// Number of tiles a blur reaches past the pixels it's applied to
+fn blur_radius_tiles(std_dev: f32) -> u32 {
+    let radius = ((3.0 * std_dev).ceil() as u32).min(MAX_BLUR_RADIUS);
+    
+    radius.div_ceil(TILE_SIZE)
+}
+
+/// Converts rectangles of tiles to rectangles of the swapchain image, clipped to its extent
+fn present_rects(tiles: &[Aabr<u32>], extent: &vk::Extent2D) -> Vec<vk::RectLayerKHR> {
+    tiles
+        .iter()
+        .map(|rect| {
+            let min = rect.min * TILE_SIZE;
+            let max = (rect.max * TILE_SIZE).map2(Vec2::new(extent.width, extent.height), u32::min);
+            
+            vk::RectLayerKHR {
+                offset: vk::Offset2D { x: min.x as i32, y: min.y as i32 },
+                extent: vk::Extent2D { width: max.x - min.x, height: max.y - min.y },
+                layer: 0
+            }
+        })
+        .collect()
+}
+
+/// Size to grow a buffer to so it fits atleast `min_size` bytes, leaving room to grow further
+fn grown_size(min_size: u64, name: &str) -> Result<u64> {
+    if min_size > MAX_BUF_SIZE {
+        bail!("Canvas recording needs a {min_size} byte {name} buffer, more than the limit of {MAX_BUF_SIZE} bytes");
+    }
+    
+    Ok(min_size.next_power_of_two().min(MAX_BUF_SIZE))
+}
+
+/// Updates the push constants shared by all passes
+unsafe fn cmd_push_constants(
+    device: &Device,
+    cmd_buf: vk::CommandBuffer,
+    pipeline_layout: vk::PipelineLayout,
+    push_constants: &PushConstants
+) {
+    let push_constants_bytes = slice::from_raw_parts(
+        push_constants as *const PushConstants as *const u8,
+        mem::size_of::<PushConstants>()
+    );
+    
+    device.cmd_push_constants(
+        cmd_buf,
+        pipeline_layout,
+        vk::ShaderStageFlags::COMPUTE,
+        0,
+        push_constants_bytes
+    );
+}
+
+/// Dispatches a raster or blur pass over rectangles of tiles, one workgroup per tile
+unsafe fn cmd_dispatch_tiles(
+    device: &Device,
+    cmd_buf: vk::CommandBuffer,
+    pipeline_layout: vk::PipelineLayout,
+    push_constants: &PushConstants,
+    tiles: &[Aabr<u32>]
+) {
+    for rect in tiles {
+        let rect_push_constants = PushConstants {
+            tile_offset: rect.min.into_array(),
+            ..*push_constants
+        };
+        
+        cmd_push_constants(device, cmd_buf, pipeline_layout, &rect_push_constants);
+        
+        let size = rect.max - rect.min;
+        device.cmd_dispatch(cmd_buf, size.x, size.y, 1);
+    }
+}
+
+/// Makes writes from the given stage visible to the compute shader passes that follow
+unsafe fn cmd_memory_barrier(
+    device: &Device,
+    cmd_buf: vk::CommandBuffer,
+    src_stage: vk::PipelineStageFlags,
+    src_access: vk::AccessFlags
+) {
+    let barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(src_access)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ)
+        .build();
+        
+    device.cmd_pipeline_barrier(
+        cmd_buf,
+        src_stage,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::DependencyFlags::empty(),
+        &[barrier],
+        &[],
+        &[]
+    );
+}
+
+fn create_pipeline(device: &Device, pipeline_layout: vk::PipelineLayout, shader_spv: &[u8]) -> Result<vk::Pipeline> {
+    unsafe {
+        // Convert [u8] to [u32]
+        let shader_spv = {
+            let len = shader_spv.len() / 4;
+            slice::from_raw_parts(shader_spv.as_ptr() as *const u32, len)
+        };
+        
+        let create_info = vk::ShaderModuleCreateInfo::builder().code(shader_spv);
+        
+        let shader_module = device
+            .create_shader_module(&create_info, None)
+            .context("Failed to create shader module")?;
+            
+        let entry_point = CString::new("main").unwrap();
+        
+        let stage_create_info = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader_module)
+            .name(&entry_point)
+            .build();
+            
+        let create_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage_create_info)
+            .layout(pipeline_layout)
+            .build();
+            
+        let result = device
+            .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
+            .map_err(|(_, result)| result)
+            .context("Failed to create compute pipeline");
+            
+        device.destroy_shader_module(shader_module, None);
+        
+        Ok(result?[0])
+    }
 }
\ No newline at end of file
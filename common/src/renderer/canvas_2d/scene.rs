@@ -0,0 +1,138 @@
+use std::mem;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use vek::Rect;
+use anyhow::Result;
+
+use super::recorder::{CanvasRecording, Canvas2DRecorder, InitState};
+
+/// Source of unique scene recording IDs, so frame buffers can tell which recording they hold
+static NEXT_SCENE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Re-recordings a scene remembers the damage of, a renderer that last drew the scene before
+/// these redraws all of it
+const MAX_SCENE_CHANGES: usize = 8;
+
+/// A recorded group of contours, clips and layers that can be drawn any number of times with
+/// [`draw_picture()`](Canvas2DRecorder::draw_picture)
+///
+/// Drawing a picture copies its commands into the recording, with the transform it's drawn with
+/// applied on top of the transforms it was recorded with, which is much cheaper than recording
+/// it again. Pictures can draw other pictures, so they can be composed and instanced freely
+pub struct CanvasPicture {
+    pub(super) recording: CanvasRecording
+}
+
+/// A whole canvas frame, recorded once and drawn by [`Canvas2DRenderer::cmd_render_scene()`](super::Canvas2DRenderer::cmd_render_scene)
+/// until it's recorded again
+///
+/// The renderer only uploads a scene's commands when they've changed since the frame's buffers
+/// were last used, so a scene that's rarely recorded costs nothing to upload on most frames.
+/// Frames where the scene hasn't changed don't redraw anything, and if it's recorded again with
+/// [`record_damaged()`](Self::record_damaged), only the damaged parts of the window are redrawn
+pub struct CanvasScene {
+    pub(super) recording: CanvasRecording,
+
+    /// Changes every time the scene is recorded
+    pub(super) id: u64,
+
+    /// ID the scene had before each of its latest re-recordings, and the window space
+    /// rectangles that changed, oldest first
+    changes: VecDeque<(u64, Vec<Rect<f32, f32>>)>
+}
+
+impl CanvasPicture {
+    /// Records a picture, with coordinates relative to the transform it's drawn with
+    ///
+    /// Fails if clips are nested more than 8 deep
+    pub fn record(record_fn: impl FnOnce(Canvas2DRecorder<InitState>) -> Canvas2DRecorder<InitState>) -> Result<Self> {
+        let recording = record_fn(Canvas2DRecorder::new_picture(CanvasRecording::default())).end_picture()?;
+
+        Ok(Self { recording })
+    }
+
+    /// Checks if nothing was recorded into the picture
+    pub fn is_empty(&self) -> bool {
+        self.recording.cmds.is_empty()
+    }
+}
+
+impl CanvasScene {
+    /// Creates an empty scene, which draws nothing
+    pub fn new() -> Self {
+        Self::from_recording(Canvas2DRecorder::empty_recording())
+    }
+
+    /// Replaces the scene's contents, reusing its allocations
+    ///
+    /// Anything could have changed, so the whole window is redrawn the next time it's drawn.
+    /// Fails if clips are nested more than 8 deep, leaving the scene empty
+    pub fn record(&mut self, record_fn: impl FnOnce(Canvas2DRecorder<InitState>) -> Canvas2DRecorder<InitState>) -> Result<()> {
+        self.changes.clear();
+        self.rerecord(record_fn)
+    }
+
+    /// Replaces the scene's contents, where only the window space rectangles in `damage` look
+    /// any different, so only they're redrawn the next time it's drawn
+    ///
+    /// Fails like [`record()`](Self::record)
+    pub fn record_damaged(
+        &mut self,
+        damage: &[Rect<f32, f32>],
+        record_fn: impl FnOnce(Canvas2DRecorder<InitState>) -> Canvas2DRecorder<InitState>
+    ) -> Result<()> {
+        if self.changes.len() == MAX_SCENE_CHANGES {
+            self.changes.pop_front();
+        }
+
+        self.changes.push_back((self.id, damage.to_vec()));
+        self.rerecord(record_fn)
+    }
+
+    /// Creates a scene from a finished recording, such as a captured frame's
+    pub(super) fn from_recording(recording: CanvasRecording) -> Self {
+        Self {
+            recording,
+            id: NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed),
+            changes: VecDeque::new()
+        }
+    }
+
+    /// Window space rectangles that changed since the scene had the given ID, or `None` if it's
+    /// unknown what changed
+    pub(super) fn damage_since(&self, id: u64) -> Option<Vec<Rect<f32, f32>>> {
+        if id == self.id {
+            return Some(vec![]);
+        }
+
+        let first_change = self.changes.iter().position(|(prev_id, _)| *prev_id == id)?;
+
+        Some(self.changes.range(first_change..).flat_map(|(_, rects)| rects.iter().copied()).collect())
+    }
+
+    fn rerecord(&mut self, record_fn: impl FnOnce(Canvas2DRecorder<InitState>) -> Canvas2DRecorder<InitState>) -> Result<()> {
+        let result = record_fn(Canvas2DRecorder::new(mem::take(&mut self.recording))).end();
+        self.id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+
+        match result {
+            Ok(recording) => {
+                self.recording = recording;
+                Ok(())
+            },
+
+            // What changed is unknown once the scene is emptied
+            Err(err) => {
+                self.recording = Canvas2DRecorder::empty_recording();
+                self.changes.clear();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Default for CanvasScene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
\ No newline at end of file
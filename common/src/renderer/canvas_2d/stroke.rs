@@ -0,0 +1,69 @@
+/// How the segments of a stroke are joined where they meet at an angle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineJoin {
+    /// The outer edges are extended till they meet, falling back to a bevel past the miter limit
+    Miter = 0,
+
+    /// The corner is rounded off with a circle
+    Round = 1,
+
+    /// The corner is cut off with a straight line
+    Bevel = 2
+}
+
+/// How the open ends of a stroke's subpaths, and the ends of each dash, are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineCap {
+    /// The stroke ends exactly at the end point
+    Butt = 0,
+
+    /// The stroke ends with a half circle around the end point
+    Round = 1,
+
+    /// The stroke extends past the end point by half its width
+    Square = 2
+}
+
+/// Describes the outline drawn by a stroke contour
+///
+/// Curves, arcs and the rounded parts of shapes are stroked smoothly, joins only apply to the
+/// corners between separate lines and curves, and to the corners of rectangles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrokeStyle<'a> {
+    /// Total width of the stroke, centered on the path
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+
+    /// Limit on the ratio of a miter join's length to the stroke width, sharper corners are beveled
+    pub miter_limit: f32,
+
+    /// Alternating lengths of dashes and gaps, starting with a dash, or empty for a solid stroke
+    ///
+    /// Odd length patterns are repeated to get an even number of lengths. Patterns with negative
+    /// or non finite lengths, or a total length of 0, are ignored
+    pub dashes: &'a [f32],
+
+    /// Distance into the dash pattern at which each subpath starts
+    pub dash_offset: f32
+}
+
+impl StrokeStyle<'_> {
+    /// Solid stroke with miter joins and butt caps, like the HTML canvas defaults
+    pub fn new(width: f32) -> Self {
+        Self {
+            width,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 10.0,
+            dashes: &[],
+            dash_offset: 0.0
+        }
+    }
+}
+
+impl From<f32> for StrokeStyle<'_> {
+    fn from(width: f32) -> Self {
+        StrokeStyle::new(width)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,830 @@
+use std::fs;
+use std::path::Path;
+
+use vek::{Vec2, Rgba};
+use anyhow::{bail, Result, Context};
+
+use super::recorder::{Canvas2DRecorder, ContourState, DrawState, FillRule};
+use super::stroke::{LineJoin, LineCap};
+use super::svg_path::{SvgPath, PathSegment, push_arc};
+use super::transform::Transform;
+
+/// An SVG document loaded into shapes, which can be drawn any number of times with
+/// [`draw_svg()`](Canvas2DRecorder::draw_svg)
+///
+/// Only a subset of SVG is supported, aimed at icons:
+/// - `<path>`, `<rect>`, `<circle>` and `<ellipse>` elements, inside nested `<g>` groups
+/// - `transform` attributes, and the root `viewBox`, always scaled uniformly and centered
+/// - Solid `fill` and `stroke` colors, `fill-opacity`, `stroke-opacity`, `fill-rule`, `stroke-width`,
+///   `stroke-linejoin`, `stroke-linecap` and `stroke-miterlimit`, as attributes or in `style`
+/// - `opacity` on groups and shapes, drawn with layers
+///
+/// Other elements, including `<defs>` and everything in them, are skipped along with their children.
+/// Like in browsers, invalid or unsupported attribute values are ignored, and paints referencing
+/// something like a gradient use their fallback color, or aren't drawn without one. Only malformed
+/// XML and path data fail to load
+#[derive(Clone, Debug)]
+pub struct SvgImage {
+    size: Vec2<f32>,
+    pub(super) items: Vec<SvgItem>
+}
+
+/// A step in drawing an [`SvgImage`], with the document tree flattened into a list
+#[derive(Clone, Debug)]
+pub(super) enum SvgItem {
+    /// Starts a group or shape with an opacity below 1
+    PushLayer(f32),
+    PopLayer,
+
+    Fill {
+        shape: SvgShape,
+        transform: Transform,
+        color: Rgba<u8>,
+        fill_rule: FillRule
+    },
+
+    Stroke {
+        shape: SvgShape,
+        transform: Transform,
+        color: Rgba<u8>,
+        width: f32,
+        join: LineJoin,
+        cap: LineCap,
+        miter_limit: f32
+    }
+}
+
+/// Geometry of a shape element, in its own user space
+#[derive(Clone, Debug)]
+pub(super) enum SvgShape {
+    Path(SvgPath),
+    Circle(Vec2<f32>, f32),
+    Ellipse(Vec2<f32>, Vec2<f32>),
+    RoundedRect(Vec2<f32>, Vec2<f32>, f32)
+}
+
+impl SvgShape {
+    /// Adds the shape to a contour
+    pub(super) fn record<Parent: DrawState>(&self, recorder: Canvas2DRecorder<ContourState<Parent>>) -> Canvas2DRecorder<ContourState<Parent>> {
+        match *self {
+            SvgShape::Path(ref path) => recorder.svg_path(path),
+            SvgShape::Circle(center, radius) => recorder.circle(center, radius),
+            SvgShape::Ellipse(center, radii) => recorder.ellipse(center, radii),
+            SvgShape::RoundedRect(top_left, size, radius) => recorder.rounded_rect(top_left, size, radius)
+        }
+    }
+}
+
+/// Inherited presentation attributes
+#[derive(Clone)]
+struct Style {
+    color: Rgba<u8>,
+    fill: Option<Paint>,
+    stroke: Option<Paint>,
+    fill_opacity: f32,
+    stroke_opacity: f32,
+    fill_rule: FillRule,
+    stroke_width: f32,
+    join: LineJoin,
+    cap: LineCap,
+    miter_limit: f32
+}
+
+/// A fill or stroke color, which may be the inherited `color`
+#[derive(Clone, Copy)]
+enum Paint {
+    Color(Rgba<u8>),
+    CurrentColor
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            color: Rgba::new(0, 0, 0, 255),
+            fill: Some(Paint::Color(Rgba::new(0, 0, 0, 255))),
+            stroke: None,
+            fill_opacity: 1.0,
+            stroke_opacity: 1.0,
+            fill_rule: FillRule::NonZero,
+            stroke_width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0
+        }
+    }
+}
+
+/// An element being loaded, with the state its children inherit
+struct Element {
+    style: Style,
+    transform: Transform,
+
+    /// Whether a layer was pushed for the element's opacity
+    layer: bool,
+
+    /// Unsupported elements are skipped along with everything in them
+    skipped: bool
+}
+
+impl SvgImage {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).with_context(|| format!("Failed to read SVG file {}", path.display()))?;
+
+        Self::parse(&source).with_context(|| format!("Failed to load SVG file {}", path.display()))
+    }
+
+    /// Loads an SVG document from its source
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut reader = XmlReader { source, pos: 0 };
+        let mut stack: Vec<Element> = vec![];
+        let mut image: Option<SvgImage> = None;
+
+        while let Some(event) = reader.next_event()? {
+            let (name, attrs, self_closing) = match event {
+                XmlEvent::Start { name, attrs, self_closing } => (name, attrs, self_closing),
+                XmlEvent::End(name) => {
+                    let element = stack.pop().with_context(|| format!("Unexpected closing tag </{name}>"))?;
+
+                    // Layers are only pushed once the root has been loaded
+                    if let (true, Some(image)) = (element.layer, image.as_mut()) {
+                        image.items.push(SvgItem::PopLayer);
+                    }
+
+                    continue;
+                }
+            };
+
+            let attr = |attr_name: &str| attrs.iter().find(|(name, _)| *name == attr_name).map(|(_, value)| value.as_str());
+            let parent = stack.last();
+
+            // The root element sets up the size and maps the view box onto it
+            let Some(image) = image.as_mut() else {
+                if name != "svg" {
+                    bail!("Root element is <{name}>, not <svg>");
+                }
+
+                let view_box = attr("viewBox").and_then(|value| parse_view_box(value).ok());
+                let length = |attr_name| attr(attr_name).and_then(|value| parse_length(value).ok());
+
+                let size = Vec2::new(
+                    length("width").or(view_box.map(|(_, size)| size.x)).unwrap_or(100.0),
+                    length("height").or(view_box.map(|(_, size)| size.y)).unwrap_or(100.0)
+                );
+
+                let transform = match view_box {
+                    Some((min, view_size)) if view_size.x > 0.0 && view_size.y > 0.0 => {
+                        let scale = (size.x / view_size.x).min(size.y / view_size.y);
+                        let offset = (size - view_size * scale) * 0.5 - min * scale;
+
+                        Transform::new(scale, 0.0, 0.0, scale, offset.x, offset.y)
+                    },
+                    _ => Transform::IDENTITY
+                };
+
+                let mut style = Style::default();
+                apply_style(&mut style, &attr);
+
+                image = Some(SvgImage { size, items: vec![] });
+                stack.push(Element { style, transform, layer: false, skipped: false });
+
+                if self_closing {
+                    stack.pop();
+                }
+
+                continue;
+            };
+
+            let Some(parent) = parent.filter(|parent| !parent.skipped) else {
+                // Inside a skipped element, or past the end of the root
+                if parent.is_none() {
+                    bail!("Element <{name}> outside of the root <svg>");
+                }
+
+                if !self_closing {
+                    stack.push(Element { style: Style::default(), transform: Transform::IDENTITY, layer: false, skipped: true });
+                }
+
+                continue;
+            };
+
+            let mut element = Element {
+                style: parent.style.clone(),
+                transform: parent.transform,
+                layer: false,
+                skipped: !matches!(name, "g" | "path" | "rect" | "circle" | "ellipse")
+            };
+
+            if !element.skipped {
+                if let Some(transform) = attr("transform").and_then(|value| parse_transform(value).ok()) {
+                    element.transform = transform.then(&element.transform);
+                }
+
+                apply_style(&mut element.style, &attr);
+
+                let opacity = attr("opacity")
+                    .and_then(|value| parse_number(value).ok())
+                    .unwrap_or(1.0)
+                    .clamp(0.0, 1.0);
+
+                if opacity < 1.0 {
+                    image.items.push(SvgItem::PushLayer(opacity));
+                    element.layer = true;
+                }
+
+                if name != "g" {
+                    let shape = parse_shape(name, &attr).with_context(|| format!("Invalid <{name}>"))?;
+
+                    if let Some(shape) = shape {
+                        image.push_shape(shape, &element);
+                    }
+                }
+            }
+
+            if self_closing {
+                if element.layer {
+                    image.items.push(SvgItem::PopLayer);
+                }
+            }
+            else {
+                stack.push(element);
+            }
+        }
+
+        if !stack.is_empty() {
+            bail!("SVG document ends before all its elements are closed");
+        }
+
+        image.context("SVG document has no root element")
+    }
+
+    /// Size of the image in canvas units, its width and height attributes
+    pub fn size(&self) -> Vec2<f32> {
+        self.size
+    }
+
+    /// Adds the fill and stroke of a shape, in that order
+    fn push_shape(&mut self, shape: SvgShape, element: &Element) {
+        let style = &element.style;
+        let resolve = |paint: Paint, opacity: f32| {
+            let color = match paint {
+                Paint::Color(color) => color,
+                Paint::CurrentColor => style.color
+            };
+
+            Rgba { a: (color.a as f32 * opacity.clamp(0.0, 1.0)).round() as u8, ..color }
+        };
+
+        if let Some(fill) = style.fill {
+            self.items.push(SvgItem::Fill {
+                shape: shape.clone(),
+                transform: element.transform,
+                color: resolve(fill, style.fill_opacity),
+                fill_rule: style.fill_rule
+            });
+        }
+
+        if let Some(stroke) = style.stroke.filter(|_| style.stroke_width > 0.0) {
+            self.items.push(SvgItem::Stroke {
+                shape,
+                transform: element.transform,
+                color: resolve(stroke, style.stroke_opacity),
+                width: style.stroke_width,
+                join: style.join,
+                cap: style.cap,
+                miter_limit: style.miter_limit
+            });
+        }
+    }
+}
+
+/// Reads a shape element's geometry, `None` if it's empty and so not drawn
+///
+/// Missing and invalid lengths are 0, only invalid path data is an error
+fn parse_shape<'a>(name: &str, attr: &impl Fn(&str) -> Option<&'a str>) -> Result<Option<SvgShape>> {
+    let optional_length = |attr_name: &str| attr(attr_name).and_then(|value| parse_length(value).ok());
+    let length = |attr_name: &str| optional_length(attr_name).unwrap_or(0.0);
+
+    let shape = match name {
+        "path" => {
+            let path = SvgPath::parse(attr("d").unwrap_or_default())?;
+
+            (!path.segments.is_empty()).then_some(SvgShape::Path(path))
+        },
+        "circle" => {
+            let radius = length("r");
+
+            (radius > 0.0).then_some(SvgShape::Circle(Vec2::new(length("cx"), length("cy")), radius))
+        },
+        "ellipse" => {
+            let radii = Vec2::new(length("rx"), length("ry"));
+
+            (radii.x > 0.0 && radii.y > 0.0).then_some(SvgShape::Ellipse(Vec2::new(length("cx"), length("cy")), radii))
+        },
+        "rect" => {
+            let top_left = Vec2::new(length("x"), length("y"));
+            let size = Vec2::new(length("width"), length("height"));
+
+            if size.x <= 0.0 || size.y <= 0.0 {
+                return Ok(None);
+            }
+
+            // A missing corner radius is the same as the other one
+            let rx = optional_length("rx");
+            let ry = optional_length("ry");
+
+            let radii = Vec2::new(rx.or(ry).unwrap_or(0.0), ry.or(rx).unwrap_or(0.0))
+                .map2(size * 0.5, |radius, max| radius.clamp(0.0, max));
+
+            if radii.x == radii.y {
+                Some(SvgShape::RoundedRect(top_left, size, radii.x))
+            }
+            else {
+                Some(SvgShape::Path(elliptical_rect(top_left, size, radii)))
+            }
+        },
+        _ => None
+    };
+
+    Ok(shape)
+}
+
+/// Outline of a rectangle with elliptical corners, going clockwise from the top left
+fn elliptical_rect(top_left: Vec2<f32>, size: Vec2<f32>, radii: Vec2<f32>) -> SvgPath {
+    let bottom_right = top_left + size;
+    let mut segments = vec![PathSegment::MoveTo(Vec2::new(top_left.x + radii.x, top_left.y))];
+
+    let corners = [
+        (Vec2::new(bottom_right.x - radii.x, top_left.y), Vec2::new(bottom_right.x, top_left.y + radii.y)),
+        (Vec2::new(bottom_right.x, bottom_right.y - radii.y), Vec2::new(bottom_right.x - radii.x, bottom_right.y)),
+        (Vec2::new(top_left.x + radii.x, bottom_right.y), Vec2::new(top_left.x, bottom_right.y - radii.y)),
+        (Vec2::new(top_left.x, top_left.y + radii.y), Vec2::new(top_left.x + radii.x, top_left.y))
+    ];
+
+    for (edge_end, corner_end) in corners {
+        segments.push(PathSegment::LineTo(edge_end));
+        push_arc(&mut segments, edge_end, radii, 0.0, false, true, corner_end);
+    }
+
+    segments.push(PathSegment::Close);
+
+    SvgPath { segments }
+}
+
+/// Applies an element's presentation attributes to the style it inherited, then the
+/// declarations in its `style` attribute, which take precedence
+///
+/// Properties with invalid or unsupported values keep the value they inherited
+fn apply_style<'a>(style: &mut Style, attr: &impl Fn(&str) -> Option<&'a str>) {
+    const PROPERTIES: [&str; 10] = [
+        "color", "fill", "stroke", "fill-opacity", "stroke-opacity", "fill-rule",
+        "stroke-width", "stroke-linejoin", "stroke-linecap", "stroke-miterlimit"
+    ];
+
+    for property in PROPERTIES {
+        if let Some(value) = attr(property) {
+            apply_property(style, property, value).ok();
+        }
+    }
+
+    for declaration in attr("style").unwrap_or_default().split(';') {
+        if let Some((property, value)) = declaration.split_once(':') {
+            let property = property.trim();
+
+            if PROPERTIES.contains(&property) {
+                apply_property(style, property, value).ok();
+            }
+        }
+    }
+}
+
+fn apply_property(style: &mut Style, property: &str, value: &str) -> Result<()> {
+    let value = value.trim();
+
+    // Inheriting is the default anyway
+    if value == "inherit" {
+        return Ok(());
+    }
+
+    match property {
+        "color" => style.color = parse_color(value)?,
+        "fill" => style.fill = parse_paint(value)?,
+        "stroke" => style.stroke = parse_paint(value)?,
+        "fill-opacity" => style.fill_opacity = parse_number(value)?,
+        "stroke-opacity" => style.stroke_opacity = parse_number(value)?,
+        "stroke-width" => style.stroke_width = parse_length(value)?,
+        "stroke-miterlimit" => style.miter_limit = parse_number(value)?,
+        "fill-rule" => {
+            style.fill_rule = match value {
+                "nonzero" => FillRule::NonZero,
+                "evenodd" => FillRule::EvenOdd,
+                _ => bail!("Unknown fill rule {value}")
+            };
+        },
+        "stroke-linejoin" => {
+            style.join = match value {
+                "miter" | "miter-clip" | "arcs" => LineJoin::Miter,
+                "round" => LineJoin::Round,
+                "bevel" => LineJoin::Bevel,
+                _ => bail!("Unknown line join {value}")
+            };
+        },
+        "stroke-linecap" => {
+            style.cap = match value {
+                "butt" => LineCap::Butt,
+                "round" => LineCap::Round,
+                "square" => LineCap::Square,
+                _ => bail!("Unknown line cap {value}")
+            };
+        },
+        _ => ()
+    }
+
+    Ok(())
+}
+
+/// Parses a fill or stroke, `None` if it's not drawn
+///
+/// Paint servers like gradients aren't supported, references to them are replaced by their
+/// fallback, which is `none` if there isn't one
+fn parse_paint(value: &str) -> Result<Option<Paint>> {
+    if let Some(reference) = value.strip_prefix("url(") {
+        let (_, fallback) = reference.split_once(')').with_context(|| format!("Expected ) in paint {value}"))?;
+        let fallback = fallback.trim();
+
+        return if fallback.is_empty() { Ok(None) } else { parse_paint(fallback) };
+    }
+
+    match value {
+        "none" => Ok(None),
+        "currentColor" => Ok(Some(Paint::CurrentColor)),
+        _ => Ok(Some(Paint::Color(parse_color(value)?)))
+    }
+}
+
+fn parse_number(value: &str) -> Result<f32> {
+    value.trim().parse().with_context(|| format!("Invalid number {value}"))
+}
+
+/// Parses a length in user units, which may have a `px` suffix, other units aren't supported
+fn parse_length(value: &str) -> Result<f32> {
+    let value = value.trim();
+
+    parse_number(value.strip_suffix("px").unwrap_or(value))
+}
+
+/// Parses a view box into its minimum corner and size
+fn parse_view_box(value: &str) -> Result<(Vec2<f32>, Vec2<f32>)> {
+    let numbers = split_numbers(value)?;
+
+    match numbers[..] {
+        [x, y, width, height] => Ok((Vec2::new(x, y), Vec2::new(width, height))),
+        _ => bail!("View box {value} doesn't have 4 numbers")
+    }
+}
+
+/// Parses a list of numbers separated by whitespace and commas
+fn split_numbers(value: &str) -> Result<Vec<f32>> {
+    value
+        .split(|c: char| c.is_ascii_whitespace() || c == ',')
+        .filter(|number| !number.is_empty())
+        .map(parse_number)
+        .collect()
+}
+
+/// Parses a transform list, e.g. `"translate(10, 20) rotate(45)"`, applied right to left
+fn parse_transform(value: &str) -> Result<Transform> {
+    let mut transform = Transform::IDENTITY;
+    let mut rest = value.trim();
+
+    while !rest.is_empty() {
+        let (name, after_name) = rest.split_once('(').with_context(|| format!("Expected ( in transform {value}"))?;
+        let (args, after_args) = after_name.split_once(')').with_context(|| format!("Expected ) in transform {value}"))?;
+
+        let name = name.trim();
+        let args = split_numbers(args)?;
+
+        let function = match (name, &args[..]) {
+            ("matrix", &[a, b, c, d, e, f]) => Transform::new(a, b, c, d, e, f),
+            ("translate", &[x]) => Transform::translation(Vec2::new(x, 0.0)),
+            ("translate", &[x, y]) => Transform::translation(Vec2::new(x, y)),
+            ("scale", &[scale]) => Transform::scaling(Vec2::broadcast(scale)),
+            ("scale", &[x, y]) => Transform::scaling(Vec2::new(x, y)),
+            ("rotate", &[angle]) => Transform::rotation(angle.to_radians()),
+            ("rotate", &[angle, x, y]) => {
+                Transform::translation(Vec2::new(-x, -y))
+                    .then(&Transform::rotation(angle.to_radians()))
+                    .then(&Transform::translation(Vec2::new(x, y)))
+            },
+            ("skewX", &[angle]) => Transform::new(1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0),
+            ("skewY", &[angle]) => Transform::new(1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0),
+            _ => bail!("Unsupported transform function {name} with {} arguments", args.len())
+        };
+
+        // Each function applies before the ones to its left
+        transform = function.then(&transform);
+        rest = after_args.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+    }
+
+    Ok(transform)
+}
+
+/// Parses a hex, `rgb()`, `rgba()` or basic named color
+fn parse_color(value: &str) -> Result<Rgba<u8>> {
+    let value = value.trim();
+
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<Vec<u8>>>()
+            .with_context(|| format!("Invalid hex color {value}"))?;
+
+        return match digits[..] {
+            [r, g, b] => Ok(Rgba::new(r * 17, g * 17, b * 17, 255)),
+            [r, g, b, a] => Ok(Rgba::new(r * 17, g * 17, b * 17, a * 17)),
+            [r1, r2, g1, g2, b1, b2] => Ok(Rgba::new(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2, 255)),
+            [r1, r2, g1, g2, b1, b2, a1, a2] => Ok(Rgba::new(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2, a1 * 16 + a2)),
+            _ => bail!("Invalid hex color {value}")
+        };
+    }
+
+    if let Some(args) = value.strip_prefix("rgba(").or(value.strip_prefix("rgb(")) {
+        let args = args.strip_suffix(')').with_context(|| format!("Expected ) in color {value}"))?;
+
+        // Components are 0 to 255 or percentages, and alpha is 0 to 1
+        let component = |arg: &str| -> Result<u8> {
+            let component = match arg.trim().strip_suffix('%') {
+                Some(percent) => parse_number(percent)? * 2.55,
+                None => parse_number(arg)?
+            };
+
+            Ok(component.round().clamp(0.0, 255.0) as u8)
+        };
+
+        let args = args.split(',').collect::<Vec<_>>();
+
+        return match args[..] {
+            [r, g, b] => Ok(Rgba::new(component(r)?, component(g)?, component(b)?, 255)),
+            [r, g, b, a] => {
+                let alpha = (parse_number(a)?.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+                Ok(Rgba::new(component(r)?, component(g)?, component(b)?, alpha))
+            },
+            _ => bail!("Invalid color {value}")
+        };
+    }
+
+    let rgb = match value.to_ascii_lowercase().as_str() {
+        "transparent" => return Ok(Rgba::zero()),
+        "black" => 0x000000,
+        "white" => 0xFFFFFF,
+        "red" => 0xFF0000,
+        "lime" => 0x00FF00,
+        "green" => 0x008000,
+        "blue" => 0x0000FF,
+        "yellow" => 0xFFFF00,
+        "cyan" | "aqua" => 0x00FFFF,
+        "magenta" | "fuchsia" => 0xFF00FF,
+        "gray" | "grey" => 0x808080,
+        "silver" => 0xC0C0C0,
+        "maroon" => 0x800000,
+        "olive" => 0x808000,
+        "navy" => 0x000080,
+        "purple" => 0x800080,
+        "teal" => 0x008080,
+        "orange" => 0xFFA500,
+        _ => bail!("Unsupported color {value}")
+    };
+
+    let [_, r, g, b] = u32::to_be_bytes(rgb);
+
+    Ok(Rgba::new(r, g, b, 255))
+}
+
+/// Start or end of an element in an XML document
+enum XmlEvent<'a> {
+    Start {
+        name: &'a str,
+        attrs: Vec<(&'a str, String)>,
+        self_closing: bool
+    },
+    End(&'a str)
+}
+
+/// Reads the elements of an XML document, skipping text, comments, CDATA, processing
+/// instructions and the doctype, which SVG images don't need
+struct XmlReader<'a> {
+    source: &'a str,
+    pos: usize
+}
+
+impl<'a> XmlReader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    /// Moves past the next occurrence of `end`
+    fn skip_past(&mut self, end: &str) -> Result<()> {
+        let offset = self.rest().find(end).with_context(|| format!("Expected {end} in XML"))?;
+        self.pos += offset + end.len();
+
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos += self.rest().len() - self.rest().trim_start().len();
+    }
+
+    /// Reads an element or attribute name
+    fn name(&mut self) -> Result<&'a str> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_ascii_whitespace() || matches!(c, '=' | '>' | '/'))
+            .unwrap_or(rest.len());
+
+        if len == 0 {
+            bail!("Expected a name in XML at {}", self.pos);
+        }
+
+        self.pos += len;
+
+        Ok(&rest[..len])
+    }
+
+    fn next_event(&mut self) -> Result<Option<XmlEvent<'a>>> {
+        loop {
+            // Text between tags is skipped
+            let Some(offset) = self.rest().find('<') else {
+                return Ok(None);
+            };
+
+            self.pos += offset;
+            let rest = self.rest();
+
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            }
+            else if rest.starts_with("<![CDATA[") {
+                self.skip_past("]]>")?;
+            }
+            else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            }
+            else if rest.starts_with("<!") {
+                // Doctypes may have an internal subset in brackets
+                let tag_end = rest.find('>').unwrap_or(rest.len());
+
+                if rest[..tag_end].contains('[') {
+                    self.skip_past("]")?;
+                }
+
+                self.skip_past(">")?;
+            }
+            else if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.name()?;
+
+                self.skip_whitespace();
+                self.skip_past(">")?;
+
+                return Ok(Some(XmlEvent::End(name)));
+            }
+            else {
+                self.pos += 1;
+                return self.start_tag().map(Some);
+            }
+        }
+    }
+
+    /// Reads the rest of a start tag, after its `<`
+    fn start_tag(&mut self) -> Result<XmlEvent<'a>> {
+        let name = self.name()?;
+        let mut attrs = vec![];
+
+        loop {
+            self.skip_whitespace();
+
+            let rest = self.rest();
+
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(XmlEvent::Start { name, attrs, self_closing: true });
+            }
+
+            if rest.starts_with('>') {
+                self.pos += 1;
+                return Ok(XmlEvent::Start { name, attrs, self_closing: false });
+            }
+
+            let attr_name = self.name()?;
+
+            self.skip_whitespace();
+
+            if !self.rest().starts_with('=') {
+                bail!("Expected = after attribute {attr_name} of <{name}>");
+            }
+
+            self.pos += 1;
+            self.skip_whitespace();
+
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => bail!("Expected a quoted value for attribute {attr_name} of <{name}>")
+            };
+
+            self.pos += 1;
+
+            let len = self.rest().find(quote).with_context(|| format!("Unterminated value for attribute {attr_name} of <{name}>"))?;
+            let value = unescape(&self.rest()[..len]);
+
+            self.pos += len + 1;
+            attrs.push((attr_name, value));
+        }
+    }
+}
+
+/// Replaces the predefined and numeric character references in an attribute value
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let reference = rest.find(';').map(|end| &rest[1..end]);
+        let replacement = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = match reference.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => reference.strip_prefix('#').and_then(|decimal| decimal.parse().ok())
+                };
+
+                code.and_then(char::from_u32)
+            }
+        });
+
+        match (reference, replacement) {
+            (Some(reference), Some(replacement)) => {
+                unescaped.push(replacement);
+                rest = &rest[reference.len() + 2..];
+            },
+
+            // Not a reference, so it's kept as is
+            _ => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_attributes_are_ignored() {
+        let image = SvgImage::parse(concat!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 24 24" fill="red">"##,
+            r##"<defs><linearGradient id="grad"/></defs>"##,
+            r##"<rect width="10" height="10" fill="url(#grad)"/>"##,
+            r##"<rect width="10" height="10" fill="url(#grad) blue" stroke="#fff" stroke-width="1em"/>"##,
+            r##"<circle cx="5" cy="5" r="4" fill="bogus" opacity="half" transform="spin(3)"/>"##,
+            r##"</svg>"##
+        )).unwrap();
+
+        let drawn: Vec<_> = image.items
+            .iter()
+            .map(|item| match *item {
+                SvgItem::Fill { color, transform, .. } => ("fill", color, 0.0, transform),
+                SvgItem::Stroke { color, width, transform, .. } => ("stroke", color, width, transform),
+                SvgItem::PushLayer(_) | SvgItem::PopLayer => panic!("Invalid opacity pushed a layer")
+            })
+            .collect();
+
+        assert_eq!(drawn, [
+            ("fill", Rgba::new(0, 0, 255, 255), 0.0, Transform::IDENTITY),
+            ("stroke", Rgba::new(255, 255, 255, 255), 1.0, Transform::IDENTITY),
+            ("fill", Rgba::new(255, 0, 0, 255), 0.0, Transform::IDENTITY)
+        ]);
+    }
+
+    #[test]
+    fn malformed_documents_fail() {
+        let svg = |content: &str| format!(r##"<svg xmlns="http://www.w3.org/2000/svg">{content}</svg>"##);
+
+        assert!(SvgImage::parse(&svg(r##"<path d="M 0 0 L 10"/>"##)).is_err());
+        assert!(SvgImage::parse(&svg(r##"<g><path d="M 0 0 L 10 10"/>"##)).is_err());
+        assert!(SvgImage::parse(&svg(r##"<path d="M 0 0 L 10 10" fill="#12"/>"##)).is_ok());
+    }
+}
\ No newline at end of file
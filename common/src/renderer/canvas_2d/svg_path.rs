@@ -0,0 +1,358 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use vek::Vec2;
+use anyhow::{bail, Result, Context};
+
+/// A segment of a parsed path, in absolute coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathSegment {
+    /// Starts a new subpath
+    MoveTo(Vec2<f32>),
+    LineTo(Vec2<f32>),
+
+    /// Control point and end point
+    QuadTo(Vec2<f32>, Vec2<f32>),
+
+    /// Both control points and end point
+    CubicTo(Vec2<f32>, Vec2<f32>, Vec2<f32>),
+
+    /// Closes the subpath with a line back to its start
+    Close
+}
+
+/// A path parsed from SVG path data, the `d` attribute of a `<path>` element
+///
+/// Every command is supported, relative commands are made absolute, `H` and `V` become lines,
+/// the reflected control points of `S` and `T` are filled in, and elliptical arcs are
+/// converted to cubic beziers. Each subpath starts with a [`PathSegment::MoveTo`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SvgPath {
+    pub(super) segments: Vec<PathSegment>
+}
+
+impl SvgPath {
+    /// Parses SVG path data, e.g. `"M 10 10 h 20 a 5 5 0 0 1 5 5 z"`
+    ///
+    /// Unlike browsers, which draw the path up to the first error, invalid path data is rejected
+    pub fn parse(data: &str) -> Result<Self> {
+        let mut parser = PathParser { data: data.as_bytes(), pos: 0 };
+        let mut segments = vec![];
+
+        let mut cursor = Vec2::zero();
+        let mut subpath_start = Vec2::zero();
+        let mut command = None;
+
+        // Second control point of the previous cubic, or control point of the previous quadratic,
+        // reflected by S and T
+        let mut prev_cubic_control = None;
+        let mut prev_quad_control = None;
+
+        // Commands following a close without a move to start a new subpath at the closed one's start
+        let mut closed = false;
+
+        parser.skip_whitespace();
+
+        while !parser.at_end() {
+            let byte = parser.data[parser.pos];
+
+            if byte.is_ascii_alphabetic() {
+                command = Some(byte);
+                parser.pos += 1;
+                parser.skip_whitespace();
+            }
+            else if !parser.at_number() {
+                bail!("Unexpected character '{}' in path data at {}", byte as char, parser.pos);
+            }
+
+            let Some(command_byte) = command else {
+                bail!("Expected a path command at {}", parser.pos);
+            };
+
+            let relative = command_byte.is_ascii_lowercase();
+            let origin = if relative { cursor } else { Vec2::zero() };
+            let upper = command_byte.to_ascii_uppercase();
+
+            if segments.is_empty() && upper != b'M' {
+                bail!("Path data must start with a move to, not '{}'", command_byte as char);
+            }
+
+            if closed && upper != b'M' {
+                segments.push(PathSegment::MoveTo(cursor));
+            }
+
+            closed = false;
+
+            let mut cubic_control = None;
+            let mut quad_control = None;
+
+            match upper {
+                b'M' => {
+                    cursor = origin + parser.point()?;
+                    subpath_start = cursor;
+                    segments.push(PathSegment::MoveTo(cursor));
+
+                    // Further coordinate pairs are implicit line tos
+                    command = Some(if relative { b'l' } else { b'L' });
+                },
+                b'L' => {
+                    cursor = origin + parser.point()?;
+                    segments.push(PathSegment::LineTo(cursor));
+                },
+                b'H' => {
+                    cursor.x = origin.x + parser.number()?;
+                    segments.push(PathSegment::LineTo(cursor));
+                },
+                b'V' => {
+                    cursor.y = origin.y + parser.number()?;
+                    segments.push(PathSegment::LineTo(cursor));
+                },
+                b'C' | b'S' => {
+                    let control1 = if upper == b'C' {
+                        origin + parser.point()?
+                    }
+                    else {
+                        prev_cubic_control.map_or(cursor, |control| cursor * 2.0 - control)
+                    };
+
+                    let control2 = origin + parser.point()?;
+                    cursor = origin + parser.point()?;
+
+                    segments.push(PathSegment::CubicTo(control1, control2, cursor));
+                    cubic_control = Some(control2);
+                },
+                b'Q' | b'T' => {
+                    let control = if upper == b'Q' {
+                        origin + parser.point()?
+                    }
+                    else {
+                        prev_quad_control.map_or(cursor, |control| cursor * 2.0 - control)
+                    };
+
+                    cursor = origin + parser.point()?;
+
+                    segments.push(PathSegment::QuadTo(control, cursor));
+                    quad_control = Some(control);
+                },
+                b'A' => {
+                    let radii = parser.point()?;
+                    let rotation = parser.number()?;
+                    let large_arc = parser.flag()?;
+                    let sweep = parser.flag()?;
+                    let end = origin + parser.point()?;
+
+                    push_arc(&mut segments, cursor, radii, rotation.to_radians(), large_arc, sweep, end);
+                    cursor = end;
+                },
+                b'Z' => {
+                    segments.push(PathSegment::Close);
+                    cursor = subpath_start;
+                    closed = true;
+
+                    // Close doesn't take any arguments, so it can't be repeated implicitly
+                    command = None;
+                },
+                _ => bail!("Unknown path command '{}'", command_byte as char)
+            }
+
+            prev_cubic_control = cubic_control;
+            prev_quad_control = quad_control;
+        }
+
+        Ok(Self { segments })
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+}
+
+/// Appends an SVG elliptical arc from `start` to `end` as cubic beziers, each spanning atmost a quarter turn
+///
+/// `radii` are scaled up if they're too small to reach `end`, and an arc with a zero radius is a
+/// straight line, as described in the SVG implementation notes
+pub(super) fn push_arc(
+    segments: &mut Vec<PathSegment>,
+    start: Vec2<f32>,
+    radii: Vec2<f32>,
+    rotation: f32,
+    large_arc: bool,
+    sweep: bool,
+    end: Vec2<f32>
+) {
+    if start == end {
+        return;
+    }
+
+    let mut radii = radii.map(f32::abs);
+
+    if radii.x == 0.0 || radii.y == 0.0 {
+        segments.push(PathSegment::LineTo(end));
+        return;
+    }
+
+    // Find the center, working in a space rotated to align the ellipse's axes
+    let (sin, cos) = rotation.sin_cos();
+    let rotate = |v: Vec2<f32>| Vec2::new(cos * v.x - sin * v.y, sin * v.x + cos * v.y);
+
+    let half_chord = (start - end) * 0.5;
+    let p = Vec2::new(cos * half_chord.x + sin * half_chord.y, -sin * half_chord.x + cos * half_chord.y);
+
+    let lambda = (p.x * p.x) / (radii.x * radii.x) + (p.y * p.y) / (radii.y * radii.y);
+
+    if lambda > 1.0 {
+        radii *= lambda.sqrt();
+    }
+
+    let (rx2, ry2) = (radii.x * radii.x, radii.y * radii.y);
+    let numerator = rx2 * ry2 - rx2 * p.y * p.y - ry2 * p.x * p.x;
+    let denominator = rx2 * p.y * p.y + ry2 * p.x * p.x;
+
+    let sign = if large_arc == sweep { -1.0 } else { 1.0 };
+    let coef = sign * (numerator / denominator).max(0.0).sqrt();
+
+    let center_rotated = Vec2::new(coef * radii.x * p.y / radii.y, -coef * radii.y * p.x / radii.x);
+    let center = rotate(center_rotated) + (start + end) * 0.5;
+
+    // Angles on the unit circle the ellipse is mapped from
+    let angle_between = |u: Vec2<f32>, v: Vec2<f32>| (u.x * v.y - u.y * v.x).atan2(u.dot(v));
+
+    let start_unit = (p - center_rotated) / radii;
+    let end_unit = (-p - center_rotated) / radii;
+
+    let start_angle = angle_between(Vec2::unit_x(), start_unit);
+    let mut sweep_angle = angle_between(start_unit, end_unit);
+
+    if !sweep && sweep_angle > 0.0 {
+        sweep_angle -= TAU;
+    }
+    else if sweep && sweep_angle < 0.0 {
+        sweep_angle += TAU;
+    }
+
+    // Each piece is approximated by a cubic with control points along the tangents at its ends
+    let num_pieces = (sweep_angle.abs() / FRAC_PI_2 - 1e-4).ceil().max(1.0);
+    let piece_angle = sweep_angle / num_pieces;
+    let handle = 4.0 / 3.0 * (piece_angle / 4.0).tan();
+
+    let to_ellipse = |v: Vec2<f32>| center + rotate(v * radii);
+    let mut from_angle = start_angle;
+
+    for piece in 0..num_pieces as u32 {
+        let to_angle = from_angle + piece_angle;
+
+        let (from_sin, from_cos) = from_angle.sin_cos();
+        let (to_sin, to_cos) = to_angle.sin_cos();
+
+        let control1 = Vec2::new(from_cos - handle * from_sin, from_sin + handle * from_cos);
+        let control2 = Vec2::new(to_cos + handle * to_sin, to_sin - handle * to_cos);
+
+        // The last piece ends exactly at the end point, whatever the rounding error
+        let piece_end = if piece == num_pieces as u32 - 1 { end } else { to_ellipse(Vec2::new(to_cos, to_sin)) };
+
+        segments.push(PathSegment::CubicTo(to_ellipse(control1), to_ellipse(control2), piece_end));
+        from_angle = to_angle;
+    }
+}
+
+/// Reads the numbers and flags of SVG path data
+struct PathParser<'a> {
+    data: &'a [u8],
+    pos: usize
+}
+
+impl PathParser<'_> {
+    fn at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    /// Checks if a number starts at the current position
+    fn at_number(&self) -> bool {
+        matches!(self.data.get(self.pos), Some(b'0'..=b'9' | b'+' | b'-' | b'.'))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while !self.at_end() && self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips whitespace and atmost one comma between arguments
+    fn skip_separator(&mut self) {
+        self.skip_whitespace();
+
+        if self.data.get(self.pos) == Some(&b',') {
+            self.pos += 1;
+            self.skip_whitespace();
+        }
+    }
+
+    fn number(&mut self) -> Result<f32> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let digits_start = parser.pos;
+
+            while !parser.at_end() && parser.data[parser.pos].is_ascii_digit() {
+                parser.pos += 1;
+            }
+
+            parser.pos > digits_start
+        };
+
+        if matches!(self.data.get(self.pos), Some(b'+' | b'-')) {
+            self.pos += 1;
+        }
+
+        let mut has_digits = digits(self);
+
+        if self.data.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            has_digits |= digits(self);
+        }
+
+        if !has_digits {
+            bail!("Expected a number in path data at {start}");
+        }
+
+        // Only treat an e as an exponent if it's followed by one, path commands never start with e
+        if matches!(self.data.get(self.pos), Some(b'e' | b'E')) {
+            let mantissa_end = self.pos;
+            self.pos += 1;
+
+            if matches!(self.data.get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+
+            if !digits(self) {
+                self.pos = mantissa_end;
+            }
+        }
+
+        // The scanned bytes are all ASCII
+        let number = std::str::from_utf8(&self.data[start..self.pos])
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .with_context(|| format!("Invalid number in path data at {start}"))?;
+
+        self.skip_separator();
+
+        Ok(number)
+    }
+
+    fn point(&mut self) -> Result<Vec2<f32>> {
+        Ok(Vec2::new(self.number()?, self.number()?))
+    }
+
+    /// Arc flags are a single 0 or 1, and don't need to be separated from what follows
+    fn flag(&mut self) -> Result<bool> {
+        let flag = match self.data.get(self.pos) {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => bail!("Expected an arc flag in path data at {}", self.pos)
+        };
+
+        self.pos += 1;
+        self.skip_separator();
+
+        Ok(flag)
+    }
+}
\ No newline at end of file
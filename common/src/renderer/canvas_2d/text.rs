@@ -0,0 +1,915 @@
+use std::ptr;
+use std::ops::Range;
+
+use vek::{Rect, Vec2};
+use ttf_parser::GlyphId;
+use unicode_bidi::{BidiClass, BidiInfo, ParagraphInfo, bidi_class};
+use unicode_linebreak::{BreakClass, BreakOpportunity, break_property, linebreaks};
+
+use super::font::Font;
+use super::paint::Paint;
+
+const ZERO_WIDTH_JOINER: char = '\u{200d}';
+
+/// How the lines of a [`TextLayout`] are positioned within its width
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+
+    /// Wrapped lines are stretched to the full width by widening the spaces between words, the
+    /// last line of each paragraph is aligned to the paragraph's start instead
+    Justify
+}
+
+/// A run of text drawn with a single font, size and paint
+#[derive(Clone, Copy)]
+pub struct TextSpan<'a> {
+    pub text: &'a str,
+    pub font: &'a Font,
+
+    /// Font size in pixels
+    pub size: f32,
+    pub paint: Paint<'a>
+}
+
+impl<'a> TextSpan<'a> {
+    pub fn new(text: &'a str, font: &'a Font, size: f32, paint: impl Into<Paint<'a>>) -> Self {
+        Self { text, font, size, paint: paint.into() }
+    }
+}
+
+/// Where the caret is drawn for a position in a [`TextLayout`], in layout coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Caret {
+    /// Top of the caret, which spans the full height of its line
+    pub position: Vec2<f32>,
+    pub height: f32,
+
+    /// Whether the text at the caret runs right to left, e.g. to draw the caret with a flag
+    /// pointing in the text's direction
+    pub rtl: bool
+}
+
+/// A shaped glyph, positioned relative to the left end of its cluster on the baseline, in
+/// pixels with y pointing down
+struct LayoutGlyph {
+    id: GlyphId,
+    offset: Vec2<f32>
+}
+
+/// Characters shaped into a group of glyphs as a whole, like a ligature or a character with
+/// combining marks, which is never split across lines
+struct Cluster {
+    /// Byte range of the cluster's characters in the layout's text
+    text: Range<usize>,
+    span: usize,
+    rtl: bool,
+    whitespace: bool,
+
+    /// The cluster's glyphs in visual order, control characters have none
+    glyphs: Range<usize>,
+    advance: f32,
+
+    /// Left end and width on its line, the width includes any space added by justification
+    x: f32,
+    width: f32
+}
+
+struct Line {
+    /// The line's clusters in logical order, and their byte range in the layout's text
+    clusters: Range<usize>,
+    text: Range<usize>,
+
+    /// The line's clusters in visual order, from left to right
+    visual: Vec<usize>,
+
+    /// Whether the line ends its paragraph or ends with a line break, rather than being wrapped
+    hard_break: bool,
+    rtl: bool,
+
+    /// Width without trailing whitespace, which hangs past the line's end
+    width: f32,
+
+    /// Where the caret goes if the line is empty
+    start_x: f32,
+
+    top: f32,
+    height: f32,
+    baseline: f32
+}
+
+/// Glyphs drawn with the same span, positioned on the baseline relative to the top left corner
+/// of the layout
+pub(super) struct GlyphRun<'l, 'a> {
+    pub(super) span: &'l TextSpan<'a>,
+    pub(super) glyphs: Vec<(GlyphId, Vec2<f32>)>
+}
+
+/// Paragraphs of rich text, shaped, wrapped and aligned, drawn with
+/// [`Canvas2DRecorder::draw_text_layout()`](super::Canvas2DRecorder::draw_text_layout)
+///
+/// Consecutive spans with the same font and size are shaped as a whole, so ligatures and
+/// combining marks work across them, and the text is reordered by the Unicode bidirectional
+/// algorithm. Each paragraph's direction comes from its first strong character. Lines are
+/// wrapped at Unicode line break opportunities, words wider than a line are broken between
+/// clusters
+///
+/// Positions in the text are byte indices into the concatenated text of the spans, and layout
+/// coordinates have their origin at the top left corner of the first line with y pointing down
+pub struct TextLayout<'a> {
+    text: String,
+    spans: Vec<TextSpan<'a>>,
+    glyphs: Vec<LayoutGlyph>,
+    clusters: Vec<Cluster>,
+    lines: Vec<Line>,
+    size: Vec2<f32>
+}
+
+impl<'a> TextLayout<'a> {
+    /// Lays out spans of text, wrapping lines wider than `max_width` if there is one
+    ///
+    /// The layout is as wide as `max_width`, or as its widest line, and lines are aligned within
+    /// that width
+    pub fn new(spans: &[TextSpan<'a>], max_width: Option<f32>, align: TextAlign) -> Self {
+        let text: String = spans.iter().map(|span| span.text).collect();
+        let bidi = BidiInfo::new(&text, None);
+
+        let (mut clusters, glyphs) = shape(&text, spans, &bidi);
+        let mut lines = break_lines(&text, &clusters, &bidi, max_width);
+        let size = place_lines(&mut lines, &mut clusters, spans, &bidi, max_width, align);
+
+        Self {
+            text,
+            spans: spans.to_vec(),
+            glyphs,
+            clusters,
+            lines,
+            size
+        }
+    }
+
+    /// The text of all the spans
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Width and height of the layout, in pixels
+    pub fn size(&self) -> Vec2<f32> {
+        self.size
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Byte range of the line containing a text position, without its line break, e.g. for
+    /// moving the caret to the start or end of the line
+    pub fn line_range(&self, index: usize) -> Range<usize> {
+        let line = &self.lines[self.line_at(index)];
+        let text = &self.text[line.text.clone()];
+        let content = text.trim_end_matches(|c: char| is_line_break(c));
+
+        line.text.start..line.text.start + content.len()
+    }
+
+    /// The caret for a text position, positions within clusters that the caret can't be placed
+    /// in are moved back to the closest one before them
+    ///
+    /// Positions on the boundary of a wrapped line are on the start of the next line
+    pub fn caret(&self, index: usize) -> Caret {
+        let index = index.min(self.text.len());
+        let line = &self.lines[self.line_at(index)];
+        let mut clusters = self.clusters[line.clusters.clone()].iter();
+
+        let (x, rtl) = if let Some(cluster) = clusters.clone().find(|cluster| cluster.text.contains(&index)) {
+            (self.caret_x(cluster, index), cluster.rtl)
+        }
+        else if let Some(last) = clusters.next_back() {
+            // After the end of the line's last cluster, in logical order
+            (if last.rtl { last.x } else { last.x + last.width }, last.rtl)
+        }
+        else {
+            (line.start_x, line.rtl)
+        };
+
+        Caret {
+            position: Vec2::new(x, line.top),
+            height: line.height,
+            rtl
+        }
+    }
+
+    /// The text position with its caret closest to a point in layout coordinates, e.g. to place
+    /// the caret where the text was clicked
+    pub fn index_at(&self, point: Vec2<f32>) -> usize {
+        let line_idx = self.lines
+            .partition_point(|line| line.top + line.height <= point.y)
+            .min(self.lines.len() - 1);
+
+        let line = &self.lines[line_idx];
+
+        // The end of the text can only be reached from the last line, wrapped lines end where the
+        // next one starts
+        let mut closest = (line.text.start, f32::INFINITY);
+
+        if line_idx == self.lines.len() - 1 {
+            closest = (self.text.len(), (self.caret(self.text.len()).position.x - point.x).abs());
+        }
+
+        for cluster in &self.clusters[line.clusters.clone()] {
+            for stop in caret_stops(&self.text, cluster.text.clone()) {
+                let dist = (self.caret_x(cluster, stop) - point.x).abs();
+
+                if dist < closest.1 {
+                    closest = (stop, dist);
+                }
+            }
+        }
+
+        closest.0
+    }
+
+    /// The next position the caret can be placed at after a text position, in logical order
+    pub fn next_caret_index(&self, index: usize) -> usize {
+        let cluster_idx = self.clusters.partition_point(|cluster| cluster.text.start <= index).saturating_sub(1);
+
+        self.clusters[cluster_idx..]
+            .iter()
+            .flat_map(|cluster| caret_stops(&self.text, cluster.text.clone()))
+            .find(|&stop| stop > index)
+            .unwrap_or(self.text.len())
+    }
+
+    /// The previous position the caret can be placed at before a text position, in logical order
+    pub fn prev_caret_index(&self, index: usize) -> usize {
+        let cluster_idx = self.clusters.partition_point(|cluster| cluster.text.start < index);
+
+        self.clusters[..cluster_idx]
+            .iter()
+            .rev()
+            .flat_map(|cluster| caret_stops(&self.text, cluster.text.clone()).rev())
+            .find(|&stop| stop < index)
+            .unwrap_or(0)
+    }
+
+    /// Rectangles covering a range of the text, one for each visually contiguous part of it on
+    /// each line, in layout coordinates
+    ///
+    /// Mixed direction lines can need several rectangles, as the logical range is split up
+    /// where the text is reordered
+    pub fn selection_rects(&self, range: Range<usize>) -> Vec<Rect<f32, f32>> {
+        let mut rects = vec![];
+
+        for line in &self.lines {
+            if line.text.end <= range.start || line.text.start >= range.end {
+                continue;
+            }
+
+            let mut current: Option<Range<f32>> = None;
+
+            for &cluster_idx in &line.visual {
+                let cluster = &self.clusters[cluster_idx];
+                let stops: Vec<usize> = caret_stops(&self.text, cluster.text.clone()).collect();
+
+                // Ligatures can be partially selected, their width is split evenly between the
+                // characters the caret can be placed at
+                let first = stops.iter().position(|stop| range.contains(stop));
+                let selected = stops.iter().filter(|stop| range.contains(stop)).count();
+
+                let Some(first) = first else {
+                    rects.extend(current.take().map(|x| selection_rect(line, x)));
+                    continue;
+                };
+
+                let start = first as f32 / stops.len() as f32;
+                let end = (first + selected) as f32 / stops.len() as f32;
+
+                let x = if cluster.rtl {
+                    cluster.x + cluster.width * (1.0 - end)..cluster.x + cluster.width * (1.0 - start)
+                }
+                else {
+                    cluster.x + cluster.width * start..cluster.x + cluster.width * end
+                };
+
+                match &mut current {
+                    Some(current) if (current.end - x.start).abs() < 1e-3 => current.end = x.end,
+
+                    _ => {
+                        rects.extend(current.take().map(|x| selection_rect(line, x)));
+                        current = Some(x);
+                    }
+                }
+            }
+
+            rects.extend(current.map(|x| selection_rect(line, x)));
+        }
+
+        rects.retain(|rect| rect.w > 0.0);
+        rects
+    }
+
+    /// The glyphs to draw, in runs of consecutive clusters on a line that share a span
+    pub(super) fn glyph_runs(&self) -> Vec<GlyphRun<'_, 'a>> {
+        let mut runs: Vec<GlyphRun> = vec![];
+
+        for line in &self.lines {
+            let mut prev_span = None;
+
+            for &cluster_idx in &line.visual {
+                let cluster = &self.clusters[cluster_idx];
+
+                if cluster.glyphs.is_empty() {
+                    continue;
+                }
+
+                if prev_span != Some(cluster.span) {
+                    runs.push(GlyphRun { span: &self.spans[cluster.span], glyphs: vec![] });
+                    prev_span = Some(cluster.span);
+                }
+
+                let origin = Vec2::new(cluster.x, line.baseline);
+                let run = runs.last_mut().unwrap();
+
+                run.glyphs.extend(self.glyphs[cluster.glyphs.clone()].iter().map(|glyph| (glyph.id, origin + glyph.offset)));
+            }
+        }
+
+        runs
+    }
+
+    /// Index of the line containing a text position
+    fn line_at(&self, index: usize) -> usize {
+        self.lines.partition_point(|line| line.text.start <= index).saturating_sub(1)
+    }
+
+    /// Horizontal position of the caret at a position within a cluster
+    fn caret_x(&self, cluster: &Cluster, index: usize) -> f32 {
+        let stops = caret_stops(&self.text, cluster.text.clone());
+        let num_stops = stops.clone().count();
+        let stop_idx = stops.filter(|&stop| stop <= index).count().saturating_sub(1);
+        let fraction = stop_idx as f32 / num_stops as f32;
+
+        if cluster.rtl {
+            cluster.x + cluster.width * (1.0 - fraction)
+        }
+        else {
+            cluster.x + cluster.width * fraction
+        }
+    }
+}
+
+/// Stacks the lines, reorders their clusters and positions them according to the alignment,
+/// returning the size of the layout
+fn place_lines(
+    lines: &mut [Line],
+    clusters: &mut [Cluster],
+    spans: &[TextSpan],
+    bidi: &BidiInfo,
+    max_width: Option<f32>,
+    align: TextAlign
+) -> Vec2<f32> {
+    let mut top = 0.0;
+
+    for line in lines.iter_mut() {
+        let line_clusters = &clusters[line.clusters.clone()];
+        let trailing = line_clusters.iter().rev().take_while(|cluster| cluster.whitespace).count();
+
+        line.width = line_clusters[..line_clusters.len() - trailing].iter().map(|cluster| cluster.advance).sum();
+
+        // Lines are as tall as their tallest font, with the extra line gap split evenly
+        // above and below the text
+        let mut ascent: f32 = 0.0;
+        let mut descent: f32 = 0.0;
+        let mut line_gap: f32 = 0.0;
+
+        let mut line_spans: Vec<usize> = line_clusters.iter().map(|cluster| cluster.span).collect();
+
+        if line_spans.is_empty() {
+            line_spans.extend(span_at(spans, line.text.start));
+        }
+
+        for span in line_spans.into_iter().map(|span_idx| &spans[span_idx]) {
+            let span_ascent = span.font.ascent(span.size);
+            let span_descent = span.font.descent(span.size);
+
+            ascent = ascent.max(span_ascent);
+            descent = descent.min(span_descent);
+            line_gap = line_gap.max(span.font.line_height(span.size) - (span_ascent - span_descent));
+        }
+
+        line.top = top;
+        line.height = ascent - descent + line_gap;
+        line.baseline = top + 0.5 * line_gap + ascent;
+        top += line.height;
+
+        // Each cluster is in a single level run, so the visual order of the runs gives the
+        // visual order of the clusters
+        if let Some(para) = paragraph_at(bidi, line.text.start) {
+            line.rtl = para.level.is_rtl();
+
+            if !line_clusters.is_empty() {
+                let (levels, runs) = bidi.visual_runs(para, line.text.clone());
+
+                for run in runs {
+                    let run_clusters = line.clusters.clone().filter(|&idx| run.contains(&clusters[idx].text.start));
+
+                    if levels[run.start].is_rtl() {
+                        line.visual.extend(run_clusters.rev());
+                    }
+                    else {
+                        line.visual.extend(run_clusters);
+                    }
+                }
+            }
+        }
+    }
+
+    let width = max_width.unwrap_or_else(|| lines.iter().map(|line| line.width).fold(0.0, f32::max));
+
+    for line in lines.iter_mut() {
+        let free = width - line.width;
+
+        let trailing_start = line.clusters.clone()
+            .rev()
+            .find(|&idx| !clusters[idx].whitespace)
+            .map_or(line.clusters.start, |idx| idx + 1);
+
+        let trailing_width: f32 = clusters[trailing_start..line.clusters.end].iter().map(|cluster| cluster.advance).sum();
+        let spaces = clusters[line.clusters.start..trailing_start].iter().filter(|cluster| cluster.whitespace).count();
+        let justify = align == TextAlign::Justify && !line.hard_break && spaces > 0;
+
+        let offset = match align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => 0.5 * free,
+            TextAlign::Right => free,
+            TextAlign::Justify if justify || !line.rtl => 0.0,
+            TextAlign::Justify => free
+        };
+
+        let space_extra = if justify { free / spaces as f32 } else { 0.0 };
+
+        // Trailing whitespace ends up on the left of right to left lines, where it hangs past
+        // the left edge instead
+        let mut x = if line.rtl { offset - trailing_width } else { offset };
+
+        for &idx in &line.visual {
+            let cluster = &mut clusters[idx];
+            let stretched = cluster.whitespace && idx < trailing_start;
+
+            cluster.x = x;
+            cluster.width = cluster.advance + if stretched { space_extra } else { 0.0 };
+            x += cluster.width;
+        }
+
+        line.start_x = if line.rtl { offset + line.width } else { offset };
+    }
+
+    Vec2::new(width, top)
+}
+
+/// Shapes the text into clusters in logical order, in runs that share a paragraph, bidi level,
+/// font and size
+///
+/// Consecutive spans with the same font and size are shaped together, so ligatures and combining
+/// marks can join characters from different spans. Clusters like that take the span of their
+/// first character
+fn shape(text: &str, spans: &[TextSpan], bidi: &BidiInfo) -> (Vec<Cluster>, Vec<LayoutGlyph>) {
+    let mut clusters = vec![];
+    let mut glyphs = vec![];
+
+    let span_starts: Vec<usize> = spans
+        .iter()
+        .scan(0, |span_end, span| {
+            let span_start = *span_end;
+            *span_end += span.text.len();
+
+            Some(span_start)
+        })
+        .collect();
+
+    let mut group_start = 0;
+
+    for group in spans.chunk_by(|a, b| ptr::eq(a.font, b.font) && a.size == b.size) {
+        let group_range = group_start..group_start + group.iter().map(|span| span.text.len()).sum::<usize>();
+        group_start = group_range.end;
+
+        let mut shape_run = |run: Range<usize>, rtl: bool| {
+            shape_run(text, run, &span_starts, group[0].font, group[0].size, rtl, &mut clusters, &mut glyphs);
+        };
+
+        for para in &bidi.paragraphs {
+            let start = para.range.start.max(group_range.start);
+            let end = para.range.end.min(group_range.end);
+
+            if start >= end {
+                continue;
+            }
+
+            let mut run_start = start;
+
+            for (i, _) in text[start..end].char_indices().map(|(i, c)| (start + i, c)).skip(1) {
+                if bidi.levels[i] != bidi.levels[run_start] {
+                    shape_run(run_start..i, bidi.levels[run_start].is_rtl());
+                    run_start = i;
+                }
+            }
+
+            if run_start < end {
+                shape_run(run_start..end, bidi.levels[run_start].is_rtl());
+            }
+        }
+    }
+
+    (clusters, glyphs)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn shape_run(
+    text: &str,
+    run: Range<usize>,
+    span_starts: &[usize],
+    font: &Font,
+    size: f32,
+    rtl: bool,
+    clusters: &mut Vec<Cluster>,
+    glyphs: &mut Vec<LayoutGlyph>
+) {
+    let scale = font.scale(size);
+    let shaped = font.shape(&text[run.clone()], Some(rtl));
+
+    // The glyphs of each cluster are consecutive, and the clusters come in visual order
+    let mut groups: Vec<_> = shaped.chunk_by(|a, b| a.cluster == b.cluster).collect();
+
+    if rtl {
+        groups.reverse();
+    }
+
+    for (i, group) in groups.iter().enumerate() {
+        let start = run.start + group[0].cluster;
+        let end = groups.get(i + 1).map_or(run.end, |next| run.start + next[0].cluster);
+        let control = text[start..].starts_with(char::is_control);
+        let first_glyph = glyphs.len();
+        let mut pen = 0.0;
+
+        if !control {
+            for shaped in group.iter() {
+                glyphs.push(LayoutGlyph {
+                    id: shaped.id,
+                    offset: Vec2::new(pen + shaped.offset.x * scale, -shaped.offset.y * scale)
+                });
+
+                pen += shaped.advance * scale;
+            }
+        }
+
+        clusters.push(Cluster {
+            text: start..end,
+
+            // Empty spans start where the span after them does, so the last span starting at or
+            // before the cluster is the one containing it
+            span: span_starts.partition_point(|&span_start| span_start <= start) - 1,
+            rtl,
+            whitespace: text[start..].starts_with(char::is_whitespace),
+            glyphs: first_glyph..glyphs.len(),
+            advance: pen,
+            x: 0.0,
+            width: 0.0
+        });
+    }
+}
+
+/// Wraps the clusters into lines, at mandatory breaks and at the last break opportunity before
+/// a line gets wider than `max_width`
+///
+/// Lines are only positioned later, once they're all known
+fn break_lines(text: &str, clusters: &[Cluster], bidi: &BidiInfo, max_width: Option<f32>) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut opportunities = linebreaks(text).peekable();
+    let mut paragraph_starts = bidi.paragraphs.iter().map(|para| para.range.start).peekable();
+
+    let mut line_start = 0;
+    let mut last_opportunity = None;
+    let mut width = 0.0;
+
+    for (i, cluster) in clusters.iter().enumerate() {
+        while opportunities.next_if(|&(pos, _)| pos < cluster.text.start).is_some() {}
+        while paragraph_starts.next_if(|&pos| pos < cluster.text.start).is_some() {}
+
+        // Lines can't span paragraphs, which are reordered separately
+        let opportunity = opportunities.next_if(|&(pos, _)| pos == cluster.text.start).map(|(_, opportunity)| opportunity);
+        let paragraph_start = paragraph_starts.next_if(|&pos| pos == cluster.text.start).is_some();
+
+        let mandatory = paragraph_start || matches!(opportunity, Some(BreakOpportunity::Mandatory));
+
+        if mandatory && i > line_start {
+            lines.push(new_line(text, clusters, line_start..i, true));
+            line_start = i;
+            last_opportunity = None;
+            width = 0.0;
+        }
+        else if opportunity.is_some() && i > line_start {
+            last_opportunity = Some(i);
+        }
+
+        // Whitespace hangs past the end of the line, so it never makes a line too wide. A word
+        // too wide for a line of its own is broken before the cluster that doesn't fit
+        let too_wide = |width: f32| max_width.is_some_and(|max_width| width + cluster.advance > max_width);
+
+        while !cluster.whitespace && i > line_start && too_wide(width) {
+            let line_end = last_opportunity.take().unwrap_or(i);
+
+            lines.push(new_line(text, clusters, line_start..line_end, false));
+            line_start = line_end;
+            width = clusters[line_start..i].iter().map(|cluster| cluster.advance).sum();
+        }
+
+        width += cluster.advance;
+    }
+
+    if line_start < clusters.len() {
+        lines.push(new_line(text, clusters, line_start..clusters.len(), true));
+    }
+
+    // Text ending in a line break, or no text at all, ends with an empty line
+    let ends_with_break = text.chars().next_back().is_none_or(is_line_break);
+
+    if ends_with_break {
+        lines.push(new_line(text, clusters, clusters.len()..clusters.len(), true));
+    }
+
+    lines
+}
+
+fn new_line(text: &str, clusters: &[Cluster], range: Range<usize>, hard_break: bool) -> Line {
+    let text_range = match (clusters.get(range.start), range.end.checked_sub(1).and_then(|last| clusters.get(last))) {
+        (Some(first), Some(last)) if !range.is_empty() => first.text.start..last.text.end,
+        _ => text.len()..text.len()
+    };
+
+    Line {
+        clusters: range,
+        text: text_range,
+        visual: vec![],
+        hard_break,
+        rtl: false,
+        width: 0.0,
+        start_x: 0.0,
+        top: 0.0,
+        height: 0.0,
+        baseline: 0.0
+    }
+}
+
+/// Checks if a character forces a line break after it
+fn is_line_break(c: char) -> bool {
+    matches!(
+        break_property(c as u32),
+        BreakClass::Mandatory | BreakClass::CarriageReturn | BreakClass::LineFeed | BreakClass::NextLine
+    )
+}
+
+/// The paragraph containing a text position, or the last one for the end of the text
+fn paragraph_at<'b>(bidi: &'b BidiInfo, index: usize) -> Option<&'b ParagraphInfo> {
+    bidi.paragraphs
+        .iter()
+        .find(|para| para.range.contains(&index))
+        .or(bidi.paragraphs.last())
+}
+
+/// Index of the span containing a text position, or of the last span for the end of the text
+fn span_at(spans: &[TextSpan], index: usize) -> Option<usize> {
+    let mut span_end = 0;
+
+    spans
+        .iter()
+        .position(|span| {
+            span_end += span.text.len();
+            index < span_end
+        })
+        .or(spans.len().checked_sub(1))
+}
+
+/// Positions within a cluster the caret can be placed at, in logical order
+///
+/// These are the start of each character that isn't a combining mark or joined to the previous
+/// one by a zero width joiner, so the caret can move through ligatures but not split up a
+/// character and its marks
+fn caret_stops(text: &str, range: Range<usize>) -> impl DoubleEndedIterator<Item = usize> + Clone + '_ {
+    let cluster_text = &text[range.clone()];
+
+    cluster_text
+        .char_indices()
+        .filter(move |&(i, c)| {
+            let prev = cluster_text[..i].chars().next_back();
+            let joined = bidi_class(c) == BidiClass::NSM || c == ZERO_WIDTH_JOINER || prev == Some(ZERO_WIDTH_JOINER);
+
+            i == 0 || !joined
+        })
+        .map(move |(i, _)| range.start + i)
+}
+
+fn selection_rect(line: &Line, x: Range<f32>) -> Rect<f32, f32> {
+    Rect::new(x.start, line.top, x.end - x.start, line.height)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use vek::Rgba;
+
+    use super::*;
+
+    const SIZE: f32 = 10.0;
+
+    fn test_font() -> Font {
+        Font::from_bytes(include_bytes!("test_fonts/DejaVuSans.ttf").to_vec(), 0).unwrap()
+    }
+
+    fn layout<'a>(font: &'a Font, text: &'a str, max_width: Option<f32>) -> TextLayout<'a> {
+        TextLayout::new(&[TextSpan::new(text, font, SIZE, Rgba::new(0, 0, 0, 255))], max_width, TextAlign::Left)
+    }
+
+    /// Advance of some text on a single line, including any trailing whitespace
+    fn width(font: &Font, text: &str) -> f32 {
+        font.shape(text, None).iter().map(|glyph| glyph.advance).sum::<f32>() * font.scale(SIZE)
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{a} != {b}");
+    }
+
+    fn assert_rects_close(rects: &[Rect<f32, f32>], expected: &[Rect<f32, f32>]) {
+        assert_eq!(rects.len(), expected.len(), "{rects:?} != {expected:?}");
+
+        for (rect, expected) in rects.iter().zip(expected) {
+            assert_close(rect.x, expected.x);
+            assert_close(rect.y, expected.y);
+            assert_close(rect.w, expected.w);
+            assert_close(rect.h, expected.h);
+        }
+    }
+
+    #[test]
+    fn wraps_at_max_width() {
+        let font = test_font();
+        let line_height = font.line_height(SIZE);
+        let max_width = width(&font, "aaa bbb") + 1.0;
+        let layout = layout(&font, "aaa bbb ccc", Some(max_width));
+
+        // The space before the break hangs past the end of the first line
+        assert_eq!(layout.line_count(), 2);
+        assert_eq!(layout.line_range(0), 0..8);
+        assert_eq!(layout.line_range(8), 8..11);
+        assert_eq!(layout.size(), Vec2::new(max_width, 2.0 * line_height));
+
+        assert_close(layout.caret(7).position.x, width(&font, "aaa bbb"));
+        assert_eq!(layout.caret(7).position.y, 0.0);
+        assert_eq!(layout.caret(8).position, Vec2::new(0.0, line_height));
+        assert_close(layout.caret(11).position.x, width(&font, "ccc"));
+    }
+
+    #[test]
+    fn breaks_words_too_wide_for_a_line() {
+        let font = test_font();
+        let line_height = font.line_height(SIZE);
+        let layout = layout(&font, "aaaaaaaa bb", Some(width(&font, "aaa") + 1.0));
+
+        assert_eq!(layout.line_count(), 4);
+
+        for (i, range) in [0..3, 3..6, 6..9, 9..11].into_iter().enumerate() {
+            assert_eq!(layout.line_range(range.start), range);
+            assert_eq!(layout.caret(range.start).position, Vec2::new(0.0, i as f32 * line_height));
+        }
+    }
+
+    #[test]
+    fn ends_with_an_empty_line_after_a_line_break() {
+        let font = test_font();
+        let line_height = font.line_height(SIZE);
+        let layout = layout(&font, "abc\n", None);
+
+        assert_eq!(layout.line_count(), 2);
+        assert_eq!(layout.line_range(0), 0..3);
+        assert_eq!(layout.line_range(4), 4..4);
+        assert_eq!(layout.size(), Vec2::new(width(&font, "abc"), 2.0 * line_height));
+
+        assert_close(layout.caret(3).position.x, width(&font, "abc"));
+        assert_eq!(layout.caret(3).position.y, 0.0);
+        assert_eq!(layout.caret(4), Caret { position: Vec2::new(0.0, line_height), height: line_height, rtl: false });
+
+        assert_eq!(layout.index_at(Vec2::new(20.0, 1.5 * line_height)), 4);
+        assert_eq!(layout.next_caret_index(3), 4);
+        assert_eq!(layout.prev_caret_index(4), 3);
+    }
+
+    #[test]
+    fn lays_out_empty_text() {
+        let font = test_font();
+        let line_height = font.line_height(SIZE);
+        let layout = layout(&font, "", Some(100.0));
+
+        assert_eq!(layout.line_count(), 1);
+        assert_eq!(layout.line_range(0), 0..0);
+        assert_eq!(layout.size(), Vec2::new(100.0, line_height));
+        assert_eq!(layout.caret(0), Caret { position: Vec2::zero(), height: line_height, rtl: false });
+
+        assert_eq!(layout.index_at(Vec2::new(50.0, 50.0)), 0);
+        assert_eq!(layout.next_caret_index(0), 0);
+        assert_eq!(layout.prev_caret_index(0), 0);
+        assert!(layout.selection_rects(0..0).is_empty());
+        assert!(layout.glyph_runs().is_empty());
+    }
+
+    #[test]
+    fn places_carets_in_mixed_direction_text() {
+        let font = test_font();
+        let line_height = font.line_height(SIZE);
+
+        // "abc ", then the Hebrew word reversed, then " def", with each Hebrew letter 2 bytes
+        let text = "abc \u{5e9}\u{5dc}\u{5d5}\u{5dd} def";
+        let layout = layout(&font, text, None);
+
+        let ltr_width = width(&font, "abc ");
+        let rtl_end = ltr_width + width(&font, "\u{5e9}\u{5dc}\u{5d5}\u{5dd}");
+
+        assert!(!layout.caret(3).rtl);
+        assert_close(layout.caret(3).position.x, width(&font, "abc"));
+
+        // Going forward through the Hebrew word moves the caret from its right end to the left
+        assert!(layout.caret(4).rtl);
+        assert_close(layout.caret(4).position.x, rtl_end);
+        assert_close(layout.caret(6).position.x, rtl_end - width(&font, "\u{5e9}"));
+        assert_close(layout.caret(10).position.x, ltr_width + width(&font, "\u{5dd}"));
+
+        for i in [4, 6, 8] {
+            assert!(layout.caret(i + 2).position.x < layout.caret(i).position.x);
+        }
+
+        // The space after the Hebrew word takes the paragraph's direction
+        assert!(!layout.caret(12).rtl);
+        assert_close(layout.caret(12).position.x, rtl_end);
+        assert_close(layout.caret(text.len()).position.x, layout.size().x);
+
+        let stops: Vec<usize> = iter::successors(Some(0), |&index| {
+            (index < text.len()).then(|| layout.next_caret_index(index))
+        })
+        .collect();
+
+        assert_eq!(stops, [0, 1, 2, 3, 4, 6, 8, 10, 12, 13, 14, 15, 16]);
+        assert_eq!(layout.prev_caret_index(12), 10);
+        assert_eq!(layout.prev_caret_index(5), 4);
+
+        // Both ends of the Hebrew word are next to the space after it, so clicking a caret
+        // position can give either of the indices placed there
+        for index in stops {
+            let caret = layout.caret(index);
+            let hit = layout.index_at(caret.position + Vec2::new(0.0, 0.5 * caret.height));
+
+            assert_close(layout.caret(hit).position.x, caret.position.x);
+        }
+
+        // Selecting from "c" into the first Hebrew letter covers "c " on the left, and the letter
+        // on the right end of the reversed word
+        let c_start = width(&font, "ab");
+        let shin_width = width(&font, "\u{5e9}");
+
+        assert_rects_close(&layout.selection_rects(2..6), &[
+            Rect::new(c_start, 0.0, ltr_width - c_start, line_height),
+            Rect::new(rtl_end - shin_width, 0.0, shin_width, line_height)
+        ]);
+
+        // The whole Hebrew word is a single contiguous rectangle
+        assert_rects_close(&layout.selection_rects(4..12), &[
+            Rect::new(ltr_width, 0.0, rtl_end - ltr_width, line_height)
+        ]);
+    }
+
+    #[test]
+    fn starts_right_to_left_paragraphs_on_the_right() {
+        let font = test_font();
+        let text = "\u{5e9}\u{5dc}\u{5d5}\u{5dd} abc";
+        let layout = layout(&font, text, None);
+
+        // The paragraph's direction comes from the Hebrew word, so "abc" ends up on the left
+        assert!(layout.caret(0).rtl);
+        assert_close(layout.caret(0).position.x, layout.size().x);
+        assert!(!layout.caret(9).rtl);
+        assert_eq!(layout.caret(9).position.x, 0.0);
+        assert_close(layout.caret(text.len()).position.x, width(&font, "abc"));
+    }
+
+    #[test]
+    fn keeps_combining_marks_with_their_character() {
+        let font = test_font();
+        let layout = layout(&font, "a\u{301}b", None);
+
+        assert_eq!(layout.next_caret_index(0), 3);
+        assert_eq!(layout.prev_caret_index(3), 0);
+        assert_eq!(layout.caret(1), layout.caret(0));
+        assert_close(layout.caret(3).position.x, width(&font, "a\u{301}"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,83 @@
+use vek::Vec2;
+
+/// A 2D affine transform, stored as the top two rows of a 3x3 matrix
+///
+/// A point `(x, y)` is transformed to `(a * x + c * y + e, b * x + d * y + f)`,
+/// the same layout as the CSS and HTML canvas `matrix(a, b, c, d, e, f)`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+
+    pub const fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Self {
+        Self { a, b, c, d, e, f }
+    }
+
+    pub fn translation(offset: Vec2<f32>) -> Self {
+        Self::new(1.0, 0.0, 0.0, 1.0, offset.x, offset.y)
+    }
+
+    pub fn scaling(scale: Vec2<f32>) -> Self {
+        Self::new(scale.x, 0.0, 0.0, scale.y, 0.0, 0.0)
+    }
+
+    /// Clockwise rotation on screen (since y points down), in radians
+    pub fn rotation(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::new(cos, sin, -sin, cos, 0.0, 0.0)
+    }
+
+    /// The transform that applies `self` and then `other`
+    pub fn then(&self, other: &Self) -> Self {
+        Self::new(
+            other.a * self.a + other.c * self.b,
+            other.b * self.a + other.d * self.b,
+            other.a * self.c + other.c * self.d,
+            other.b * self.c + other.d * self.d,
+            other.a * self.e + other.c * self.f + other.e,
+            other.b * self.e + other.d * self.f + other.f
+        )
+    }
+
+    /// The inverse transform, `None` if the transform isn't invertible
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.a * self.d - self.b * self.c;
+
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+
+        Some(Self::new(
+            self.d * inv_det,
+            -self.b * inv_det,
+            -self.c * inv_det,
+            self.a * inv_det,
+            (self.c * self.f - self.d * self.e) * inv_det,
+            (self.b * self.e - self.a * self.f) * inv_det
+        ))
+    }
+
+    /// Transforms a point
+    pub fn apply(&self, point: Vec2<f32>) -> Vec2<f32> {
+        Vec2::new(
+            self.a * point.x + self.c * point.y + self.e,
+            self.b * point.x + self.d * point.y + self.f
+        )
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
\ No newline at end of file
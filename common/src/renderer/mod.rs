@@ -4,4 +4,11 @@ mod vk_util;
 mod renderer;
 mod canvas_2d;
 
-pub use renderer::{Renderer, RendererConfig};
\ No newline at end of file
+pub use renderer::{Renderer, RendererConfig};
+pub use canvas_2d::{
+    Canvas2DRecorder, InitState, ContourState, ClipState, LayerState, DrawState, FillRule, BlendMode,
+    Paint, ColorStop, ExtendMode, FilterMode, TextureId, StrokeStyle, LineJoin, LineCap,
+    Transform, Font, TextLayout, TextSpan, TextAlign, Caret, SvgPath, PathSegment, SvgImage, CpuRasterizer,
+    CanvasPicture, CanvasScene, Path, HitMap, TargetId, CanvasCapture, CapturedTexture, Antialiasing
+};
+pub use canvas_2d::color as canvas_color;
\ No newline at end of file
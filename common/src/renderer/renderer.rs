@@ -13,7 +13,7 @@ use super::vk_util::{
     vma::VmaAllocator
 };
 
-use super::canvas_2d::Canvas2DRenderer;
+use super::canvas_2d::{Canvas2DRenderer, TextureId, CanvasScene, TargetId, CanvasCapture, Antialiasing};
 
 const DEFAULT_FRAMES_IN_FLIGHT: u32 = 2;
 
@@ -37,7 +37,11 @@ pub struct Renderer {
     cmd_pool: vk::CommandPool,
     cmd_bufs: Vec<vk::CommandBuffer>,
     vma_alloc: VmaAllocator,
-    canvas_2d: Canvas2DRenderer
+    canvas_2d: Canvas2DRenderer,
+    canvas_scene: Option<CanvasScene>,
+    
+    /// Scenes drawn into render targets before the window, in the order they were set
+    canvas_target_scenes: Vec<(TargetId, CanvasScene)>
 }
 
 impl Renderer {
@@ -72,7 +76,15 @@ impl Renderer {
         println!("Number of swapchain images: {}", frame_queue.swap_image_views().len());
         println!("Frames in flight: {frames_in_flight}");
         
-        let canvas_2d = Canvas2DRenderer::new(&device, &frame_queue, &vma_alloc, frames_in_flight)?;
+        let canvas_2d = Canvas2DRenderer::new(
+            &device,
+            &frame_queue,
+            &vma_alloc,
+            cmd_pool,
+            gfx_queue,
+            frames_in_flight,
+            phys_dev_info.max_image_dimension_2d()
+        )?;
 
         Ok(Self {
             _entry: entry,
@@ -86,7 +98,9 @@ impl Renderer {
             cmd_pool,
             cmd_bufs,
             vma_alloc,
-            canvas_2d
+            canvas_2d,
+            canvas_scene: None,
+            canvas_target_scenes: vec![]
         })
     }
 
@@ -106,11 +120,12 @@ impl Renderer {
                 .begin_command_buffer(cmd_buf, &begin_info)
                 .context("Failed to begin command buffer recording")?;
     
-            // Transition swapchain image layout from UNDEFINED to GENERAL
+            // Transition swapchain image layout to GENERAL, keeping what was last drawn to it
+            // so only damaged tiles have to be redrawn
             let barrier = vk::ImageMemoryBarrier::builder()
                 .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
                 .dst_access_mask(vk::AccessFlags::MEMORY_READ)
-                .old_layout(vk::ImageLayout::UNDEFINED)
+                .old_layout(frame_info.swap_image_layout())
                 .new_layout(vk::ImageLayout::GENERAL)
                 .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
                 .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
@@ -134,30 +149,18 @@ impl Renderer {
                 &[barrier]
             );
             
-            // Record canvas2d commands
-            let record_fn = |canvas_2d: super::canvas_2d::Canvas2DRecorder<super::canvas_2d::InitState>| {
-                canvas_2d
-                    .start_fill(vek::Vec2::new(100, 100), vek::Rgba::new(255, 100, 0, 255))
-                    .line_to(vek::Vec2::new(300, 100))
-                    .line_to(vek::Vec2::new(300, 300))
-                    .line_to(vek::Vec2::new(100, 300))
-                    .line_to(vek::Vec2::new(100, 100))
-                    .end()
-                    .start_fill(vek::Vec2::new(200, 200), vek::Rgba::new(255, 255, 255, 100))
-                    .line_to(vek::Vec2::new(500, 200))
-                    .line_to(vek::Vec2::new(500, 500))
-                    .line_to(vek::Vec2::new(200, 500))
-                    .line_to(vek::Vec2::new(200, 200))
-                    .end()
-                    .start_stroke(vek::Vec2::new(400, 250), vek::Rgba::new(100, 255, 255, 255), 3)
-                    .line_to(vek::Vec2::new(530, 250))
-                    .line_to(vek::Vec2::new(590, 350))
-                    .line_to(vek::Vec2::new(460, 350))
-                    .line_to(vek::Vec2::new(400, 250))
-                    .end()
-            };
+            // Draw the render target scenes, so the window can draw their textures
+            for (target_id, scene) in &self.canvas_target_scenes {
+                self.canvas_2d
+                    .cmd_render_scene_target(&self.device, &self.vma_alloc, cmd_buf, &frame_info, *target_id, scene)
+                    .context("Failed to record canvas render target commands")?;
+            }
             
-            self.canvas_2d.cmd_render(&self.device, cmd_buf, &frame_info, record_fn);
+            // Draw the scene if one was set, otherwise just clear the window
+            match &self.canvas_scene {
+                Some(scene) => self.canvas_2d.cmd_render_scene(&self.device, &self.vma_alloc, cmd_buf, &frame_info, scene),
+                None => self.canvas_2d.cmd_render(&self.device, &self.vma_alloc, cmd_buf, &frame_info, None, |canvas_2d| canvas_2d)
+            }.context("Failed to record canvas commands")?;
             
             // Transition swapchain image layout from GENERAL TO PRESENT_SRC_KHR
             let barrier = vk::ImageMemoryBarrier::builder()
@@ -215,11 +218,26 @@ impl Renderer {
             let swapchains = [frame_info.swapchain()];
             let image_indices = [frame_info.swap_image_idx() as u32];
             
-            let present_info = vk::PresentInfoKHR::builder()
+            let mut present_info = vk::PresentInfoKHR::builder()
                 .wait_semaphores(&wait_semaphores)
                 .swapchains(&swapchains)
                 .image_indices(&image_indices);
             
+            // Tell the presentation engine which parts of the image changed, a region without
+            // rectangles would mean all of it did, so an unchanged image gets an empty rectangle
+            let empty_rect = [vk::RectLayerKHR::default()];
+            let present_damage = self.canvas_2d
+                .present_damage()
+                .filter(|_| self.device_exts.incremental_present())
+                .map(|rects| if rects.is_empty() { &empty_rect[..] } else { rects });
+            
+            let regions = present_damage.map(|rects| [vk::PresentRegionKHR::builder().rectangles(rects).build()]);
+            let mut present_regions = regions.as_ref().map(|regions| vk::PresentRegionsKHR::builder().regions(regions));
+            
+            if let Some(present_regions) = &mut present_regions {
+                present_info = present_info.push_next(present_regions);
+            }
+            
             self.device_exts
                 .swapchain_ext()
                 .queue_present(self.gfx_queue, &present_info)
@@ -229,6 +247,133 @@ impl Renderer {
         }
     }
 
+    /// Sets the retained scene drawn by [`render_frame()`](Self::render_frame), without one the
+    /// window is only cleared
+    ///
+    /// The scene's commands are only uploaded again after it's re-recorded, which can be done
+    /// through [`canvas_scene_mut()`](Self::canvas_scene_mut). Re-recording it with
+    /// [`CanvasScene::record_damaged()`] only redraws the damaged parts of the window
+    pub fn set_canvas_scene(&mut self, scene: Option<CanvasScene>) {
+        self.canvas_scene = scene;
+    }
+    
+    /// Gets the retained scene drawn each frame, if one was set
+    pub fn canvas_scene_mut(&mut self) -> Option<&mut CanvasScene> {
+        self.canvas_scene.as_mut()
+    }
+
+    /// Sets how the edges of canvas fills are antialiased, everything is redrawn if it changes
+    pub fn set_canvas_antialiasing(&mut self, antialiasing: Antialiasing) {
+        self.canvas_2d.set_antialiasing(antialiasing);
+    }
+
+    pub fn canvas_antialiasing(&self) -> Antialiasing {
+        self.canvas_2d.antialiasing()
+    }
+
+    /// Creates an offscreen canvas render target of the given size, cleared to an sRGB encoded
+    /// color, that can be drawn as a texture with [`canvas_target_texture()`](Self::canvas_target_texture)
+    ///
+    /// Blocks till the device is idle
+    pub fn create_canvas_target(&mut self, width: u32, height: u32, clear_color: vek::Rgba<u8>) -> Result<TargetId> {
+        self.canvas_2d.create_target(
+            &self.device,
+            &self.vma_alloc,
+            self.cmd_pool,
+            self.gfx_queue,
+            width,
+            height,
+            clear_color
+        )
+    }
+    
+    /// Destroys a canvas render target, its texture and the scene drawn into it
+    ///
+    /// Blocks till the device is idle
+    pub fn destroy_canvas_target(&mut self, target_id: TargetId) -> Result<()> {
+        self.canvas_target_scenes.retain(|(id, _)| *id != target_id);
+        self.canvas_2d.destroy_target(&self.device, &self.vma_alloc, target_id)
+    }
+    
+    /// Texture holding what was last drawn into a canvas render target, for use as a
+    /// [`Paint::Image`], or `None` if the target doesn't exist
+    pub fn canvas_target_texture(&self, target_id: TargetId) -> Option<TextureId> {
+        self.canvas_2d.target_texture(target_id)
+    }
+    
+    /// Sets the retained scene drawn into a render target each frame, before the window
+    ///
+    /// Targets are drawn in the order their scenes were set, so a target's scene can draw the
+    /// textures of targets whose scenes were set before it. A target without a scene keeps what
+    /// was last drawn into it. Re-recording the scene with [`CanvasScene::record_damaged()`]
+    /// only redraws the damaged parts of the target, the parts of the window and other targets
+    /// its texture is drawn over have to be damaged too
+    pub fn set_canvas_target_scene(&mut self, target_id: TargetId, scene: Option<CanvasScene>) {
+        self.canvas_target_scenes.retain(|(id, _)| *id != target_id);
+        
+        if let Some(scene) = scene {
+            self.canvas_target_scenes.push((target_id, scene));
+        }
+    }
+    
+    /// Gets the retained scene drawn into a render target each frame, if one was set
+    pub fn canvas_target_scene_mut(&mut self, target_id: TargetId) -> Option<&mut CanvasScene> {
+        self.canvas_target_scenes
+            .iter_mut()
+            .find(|(id, _)| *id == target_id)
+            .map(|(_, scene)| scene)
+    }
+
+    /// Renders a frame and captures what the canvas drew into the window, along with the textures
+    /// it sampled, e.g. to save it for a bug report with [`CanvasCapture::save()`]
+    ///
+    /// Blocks till the device is idle
+    pub fn capture_canvas_frame(&mut self) -> Result<CanvasCapture> {
+        self.canvas_2d.capture_next_frame();
+        self.render_frame()?;
+        
+        self.canvas_2d
+            .take_capture(&self.device, &self.vma_alloc, self.cmd_pool, self.gfx_queue)
+            .context("Failed to capture canvas frame")
+    }
+    
+    /// Draws a captured canvas frame in the window from now on, by setting it as the canvas scene
+    ///
+    /// The capture's textures are registered again, under new IDs that are returned so they can
+    /// be unregistered once the capture is no longer drawn. Blocks till the device is idle
+    pub fn replay_canvas_capture(&mut self, capture: &CanvasCapture) -> Result<Vec<TextureId>> {
+        let (scene, texture_ids) = self.canvas_2d
+            .load_capture(&self.device, &self.vma_alloc, self.cmd_pool, self.gfx_queue, capture)
+            .context("Failed to load canvas capture")?;
+            
+        self.set_canvas_scene(Some(scene));
+        
+        Ok(texture_ids)
+    }
+
+    /// Uploads an sRGB encoded RGBA8 image with straight alpha and tightly packed rows, for use
+    /// as a canvas [`Paint::Image`]
+    ///
+    /// Blocks till the device is idle
+    pub fn register_canvas_texture(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<TextureId> {
+        self.canvas_2d.register_texture(
+            &self.device,
+            &self.vma_alloc,
+            self.cmd_pool,
+            self.gfx_queue,
+            width,
+            height,
+            pixels
+        )
+    }
+    
+    /// Destroys a canvas texture
+    ///
+    /// Blocks till the device is idle
+    pub fn unregister_canvas_texture(&mut self, texture_id: TextureId) -> Result<()> {
+        self.canvas_2d.unregister_texture(&self.device, &self.vma_alloc, texture_id)
+    }
+
     pub fn destroy(self) {
         unsafe {
             self.device.device_wait_idle().unwrap();
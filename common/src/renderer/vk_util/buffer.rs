@@ -91,13 +91,13 @@ impl TransferBuffer {
         self.ptr
     }
 
-    /// Record this in a command buffer to ensure the data is transferred
-    pub fn cmd_transfer(&self, device: &Device, cmd_buf: vk::CommandBuffer) {
+    /// Record this in a command buffer to ensure the first `size` bytes of data are transferred
+    pub fn cmd_transfer(&self, device: &Device, cmd_buf: vk::CommandBuffer, size: u64) {
         if let Some(staging_buf) = &self.staging_buf {
             let region = vk::BufferCopy::builder()
                 .src_offset(0)
                 .dst_offset(0)
-                .size(self.size)
+                .size(size.min(self.size))
                 .build();
 
             unsafe { device.cmd_copy_buffer(cmd_buf, staging_buf.buf(), self.dest_buf.buf(), &[region]) };
@@ -27,4 +27,55 @@ pub fn create_command_buffers(
         .context("Failed to allocate command buffer")?;
 
     Ok((cmd_pool, cmd_bufs))
+}
+
+/// Records commands into a temporary command buffer, submits it and waits for it to finish
+///
+/// Meant for one off work like resource uploads, not for per frame work
+pub fn submit_one_time(
+    device: &Device,
+    cmd_pool: vk::CommandPool,
+    queue: vk::Queue,
+    record_fn: impl FnOnce(vk::CommandBuffer)
+) -> Result<()> {
+    let alloc_info = vk::CommandBufferAllocateInfo::builder()
+        .command_pool(cmd_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(1);
+
+    let cmd_buf = unsafe { device.allocate_command_buffers(&alloc_info) }
+        .context("Failed to allocate one time command buffer")?[0];
+
+    let result = unsafe {
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        device
+            .begin_command_buffer(cmd_buf, &begin_info)
+            .context("Failed to begin one time command buffer")
+            .and_then(|_| {
+                record_fn(cmd_buf);
+
+                device
+                    .end_command_buffer(cmd_buf)
+                    .context("Failed to end one time command buffer")
+            })
+            .and_then(|_| {
+                let cmd_bufs = [cmd_buf];
+                let submit_info = vk::SubmitInfo::builder().command_buffers(&cmd_bufs).build();
+
+                device
+                    .queue_submit(queue, &[submit_info], vk::Fence::null())
+                    .context("Failed to submit one time command buffer")
+            })
+            .and_then(|_| {
+                device
+                    .queue_wait_idle(queue)
+                    .context("Failed to wait for one time command buffer")
+            })
+    };
+
+    unsafe { device.free_command_buffers(cmd_pool, &[cmd_buf]) };
+
+    result
 }
\ No newline at end of file
@@ -5,7 +5,8 @@ use super::phys_dev::{DEVICE_EXTS, PhysicalDeviceInfo};
 
 /// Device extensions functions
 pub struct DeviceExts {
-    swapchain_ext: khr::Swapchain
+    swapchain_ext: khr::Swapchain,
+    incremental_present: bool
 }
 
 impl DeviceExts {
@@ -13,6 +14,12 @@ impl DeviceExts {
     pub fn swapchain_ext(&self) -> &khr::Swapchain {
         &self.swapchain_ext
     }
+
+    /// Whether `VK_KHR_incremental_present` is enabled, so present regions can be passed to
+    /// [`queue_present()`](khr::Swapchain::queue_present)
+    pub fn incremental_present(&self) -> bool {
+        self.incremental_present
+    }
 }
 
 /// Create a logical device, load its extension functions and get the graphics queue
@@ -27,6 +34,9 @@ impl DeviceExts {
 /// Enabled VK_KHR_16bit_storage features
 /// - storage buffer 16 bit access
 /// - uniform and storage buffer 16 bit access
+///
+/// Enabled optional extensions, if supported:
+/// - VK_KHR_incremental_present
 pub fn create_device(
     instance: &Instance,
     phys_dev: vk::PhysicalDevice,
@@ -47,10 +57,17 @@ pub fn create_device(
         .storage_buffer16_bit_access(true)
         .uniform_and_storage_buffer16_bit_access(true);
 
+    let incremental_present = phys_dev_info.supports_incremental_present();
+    let mut ext_names = DEVICE_EXTS.to_vec();
+
+    if incremental_present {
+        ext_names.push(vk::KhrIncrementalPresentFn::name().as_ptr());
+    }
+
     let create_info = vk::DeviceCreateInfo::builder()
         .push_next(&mut dev_16_bit_storage_features)
         .queue_create_infos(&queue_create_infos)
-        .enabled_extension_names(&DEVICE_EXTS)
+        .enabled_extension_names(&ext_names)
         .enabled_features(&dev_features);
 
     let device = unsafe { instance.create_device(phys_dev, &create_info, None) }
@@ -59,7 +76,8 @@ pub fn create_device(
     let gfx_queue = unsafe { device.get_device_queue(phys_dev_info.gfx_queue_family(), 0) };
 
     let device_exts = DeviceExts {
-        swapchain_ext: khr::Swapchain::new(instance, &device)
+        swapchain_ext: khr::Swapchain::new(instance, &device),
+        incremental_present
     };
 
     Ok((Box::new(device), device_exts, gfx_queue))
@@ -1,4 +1,5 @@
 use std::cmp;
+use std::mem;
 
 use ash::{vk, Device};
 use anyhow::{bail, Result, Context};
@@ -83,7 +84,8 @@ pub struct FrameInfo<'a> {
     swapchain: vk::SwapchainKHR,
     sync_set: &'a SyncSet,
     swap_image: vk::Image,
-    swap_image_extent: &'a vk::Extent2D
+    swap_image_extent: &'a vk::Extent2D,
+    swap_image_layout: vk::ImageLayout
 }
 
 impl<'a> FrameInfo<'a> {
@@ -121,6 +123,14 @@ impl<'a> FrameInfo<'a> {
     pub fn swap_image_extent(&self) -> &vk::Extent2D {
         self.swap_image_extent
     }
+    
+    /// The layout the swapchain image is in, `UNDEFINED` the first time it's acquired and
+    /// `PRESENT_SRC_KHR` after that
+    ///
+    /// Transitioning the image from this layout keeps what was last drawn to it
+    pub fn swap_image_layout(&self) -> vk::ImageLayout {
+        self.swap_image_layout
+    }
 }
 
 /// An abstraction over [`vk::SwapchainKHR`] that integrates frame synchronization as well
@@ -129,6 +139,9 @@ pub struct FrameQueue {
     swap_image_extent: vk::Extent2D,
     swap_images: Vec<vk::Image>,
     swap_image_views: Vec<vk::ImageView>,
+    
+    /// Whether each swapchain image has been acquired before, and so presented
+    swap_images_acquired: Vec<bool>,
     sync_sets: Vec<SyncSet>,
     frame_idx: usize
 }
@@ -200,7 +213,12 @@ impl FrameQueue {
             .pre_transform(capab.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(vk::PresentModeKHR::FIFO)
-            .clipped(true);
+            // The canvas renderer only redraws the tiles that changed since an image was last drawn
+            // to, and relies on the rest of the image still holding what it drew. A clipped
+            // swapchain may leave pixels hidden by other windows undefined, which would then show up
+            // once they're uncovered. Drawing is done by compute passes that write every pixel of
+            // a tile anyway, so clipping wouldn't save any work
+            .clipped(false);
             
         let swapchain = unsafe {
             device_exts
@@ -249,11 +267,14 @@ impl FrameQueue {
             .map(|_| SyncSet::new(device))
             .collect::<Result<Vec<SyncSet>>>()?;
                     
+        let swap_images_acquired = vec![false; swap_images.len()];
+                    
         Ok(Self {
             swapchain,
             swap_image_extent,
             swap_images,
             swap_image_views,
+            swap_images_acquired,
             sync_sets,
             frame_idx: 0
         })
@@ -289,13 +310,23 @@ impl FrameQueue {
             device.reset_fences(&[sync_set.frame_done])
                 .context("Failed to reset frame_done fence")?;
                 
+            let acquired_before = mem::replace(&mut self.swap_images_acquired[swap_image_idx as usize], true);
+            
+            let swap_image_layout = if acquired_before {
+                vk::ImageLayout::PRESENT_SRC_KHR
+            }
+            else {
+                vk::ImageLayout::UNDEFINED
+            };
+                
             let info = FrameInfo {
                 frame_idx: self.frame_idx,
                 swap_image_idx: swap_image_idx as usize,
                 swapchain: self.swapchain,
                 sync_set,
                 swap_image: self.swap_images[swap_image_idx as usize],
-                swap_image_extent: &self.swap_image_extent
+                swap_image_extent: &self.swap_image_extent,
+                swap_image_layout
             };
             
             let frames_in_flight = self.sync_sets.len();
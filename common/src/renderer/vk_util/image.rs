@@ -0,0 +1,506 @@
+use std::ptr;
+use std::slice;
+
+use ash::{vk, Device};
+use anyhow::{bail, Result, Context};
+
+use super::{
+    vma::{VmaAllocator, AllocInfo, VmaImage},
+    cmd_buf::submit_one_time
+};
+
+/// A device local 2D color image, or array of them, along with a view of it
+pub struct Image2D {
+    image: VmaImage,
+    view: vk::ImageView,
+    extent: vk::Extent2D,
+    layers: u32,
+    format: vk::Format
+}
+
+unsafe impl Send for Image2D {}
+
+impl Image2D {
+    pub fn new(
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags
+    ) -> Result<Self> {
+        Self::create(device, vma_alloc, extent, 1, vk::ImageViewType::TYPE_2D, format, usage, vk::ImageCreateFlags::empty())
+    }
+
+    /// Creates an image that can also be viewed with other formats of the same size, through
+    /// [`create_view()`](Self::create_view)
+    pub fn new_mutable(
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags
+    ) -> Result<Self> {
+        Self::create(device, vma_alloc, extent, 1, vk::ImageViewType::TYPE_2D, format, usage, vk::ImageCreateFlags::MUTABLE_FORMAT)
+    }
+
+    /// Creates an image with `layers` array layers, viewed as a 2D array image
+    pub fn new_array(
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        extent: vk::Extent2D,
+        layers: u32,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags
+    ) -> Result<Self> {
+        Self::create(device, vma_alloc, extent, layers, vk::ImageViewType::TYPE_2D_ARRAY, format, usage, vk::ImageCreateFlags::empty())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        extent: vk::Extent2D,
+        layers: u32,
+        view_type: vk::ImageViewType,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        flags: vk::ImageCreateFlags
+    ) -> Result<Self> {
+        let create_info = vk::ImageCreateInfo::builder()
+            .flags(flags)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1
+            })
+            .mip_levels(1)
+            .array_layers(layers)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let alloc_info = AllocInfo::new().prefer_device();
+
+        let image = vma_alloc
+            .create_image(&create_info, &alloc_info)
+            .context("Failed to create image")?;
+
+        let view = match create_image_view(device, image.image(), view_type, format, layers) {
+            Ok(view) => view,
+            Err(err) => {
+                vma_alloc.destroy_image(image);
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            image,
+            view,
+            extent,
+            layers,
+            format
+        })
+    }
+
+    /// The underlying [`vk::Image`]
+    pub fn image(&self) -> vk::Image {
+        self.image.image()
+    }
+
+    /// A view of the whole image
+    pub fn view(&self) -> vk::ImageView {
+        self.view
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Number of array layers, 1 unless created with [`new_array()`](Self::new_array)
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    /// Creates another view of the whole image with a different format, which must be the same
+    /// size as the image's format
+    ///
+    /// The image must have been created with [`new_mutable()`](Self::new_mutable), and the view
+    /// must be destroyed before the image
+    pub fn create_view(&self, device: &Device, format: vk::Format) -> Result<vk::ImageView> {
+        create_image_view(device, self.image(), vk::ImageViewType::TYPE_2D, format, 1)
+    }
+
+    /// Subresource range covering every layer of the image
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        subresource_range(self.layers)
+    }
+
+    /// Uploads tightly packed pixel data to the image and leaves it in the given layout
+    ///
+    /// This blocks till the upload is finished. The image must have been created with
+    /// [`vk::ImageUsageFlags::TRANSFER_DST`], only the first layer is uploaded to
+    pub fn upload(
+        &self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_pool: vk::CommandPool,
+        queue: vk::Queue,
+        data: &[u8],
+        final_layout: vk::ImageLayout
+    ) -> Result<()> {
+        let expected_len = self.extent.width as usize * self.extent.height as usize * format_size(self.format)?;
+
+        if data.len() != expected_len {
+            bail!("Image data is {} bytes, expected {expected_len} bytes", data.len());
+        }
+
+        // Create staging buffer
+        let create_info = vk::BufferCreateInfo::builder()
+            .size(data.len() as u64)
+            .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let alloc_info = AllocInfo::new()
+            .prefer_host()
+            .mapped()
+            .sequential_access();
+
+        let staging_buf = vma_alloc
+            .create_buffer(&create_info, &alloc_info)
+            .context("Failed to create image staging buffer")?;
+
+        match staging_buf.ptr() {
+            Some(staging_ptr) => unsafe {
+                ptr::copy_nonoverlapping(data.as_ptr(), staging_ptr.as_ptr() as *mut u8, data.len());
+            },
+            None => {
+                vma_alloc.destroy_buffer(staging_buf);
+                bail!("Image staging buffer somehow not mapped");
+            }
+        }
+
+        // Transition to TRANSFER_DST_OPTIMAL, copy, then transition to the final layout
+        let result = submit_one_time(device, cmd_pool, queue, |cmd_buf| unsafe {
+            let barrier = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image())
+                .subresource_range(COLOR_SUBRESOURCE_RANGE)
+                .build();
+
+            device.cmd_pipeline_barrier(
+                cmd_buf,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+
+            let region = vk::BufferImageCopy::builder()
+                .buffer_offset(0)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1
+                })
+                .image_offset(vk::Offset3D::default())
+                .image_extent(vk::Extent3D {
+                    width: self.extent.width,
+                    height: self.extent.height,
+                    depth: 1
+                })
+                .build();
+
+            device.cmd_copy_buffer_to_image(
+                cmd_buf,
+                staging_buf.buf(),
+                self.image(),
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region]
+            );
+
+            let barrier = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(final_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image())
+                .subresource_range(COLOR_SUBRESOURCE_RANGE)
+                .build();
+
+            device.cmd_pipeline_barrier(
+                cmd_buf,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+        });
+
+        vma_alloc.destroy_buffer(staging_buf);
+
+        result
+    }
+
+    /// Downloads the first layer of the image as tightly packed pixel data
+    ///
+    /// This blocks till the download is finished. The image must have been created with
+    /// [`vk::ImageUsageFlags::TRANSFER_SRC`], and be in `layout`, which it's left in
+    pub fn download(
+        &self,
+        device: &Device,
+        vma_alloc: &VmaAllocator,
+        cmd_pool: vk::CommandPool,
+        queue: vk::Queue,
+        layout: vk::ImageLayout
+    ) -> Result<Vec<u8>> {
+        let len = self.extent.width as usize * self.extent.height as usize * format_size(self.format)?;
+
+        // Create readback buffer
+        let create_info = vk::BufferCreateInfo::builder()
+            .size(len as u64)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let alloc_info = AllocInfo::new()
+            .prefer_host()
+            .mapped()
+            .random_access()
+            .coherent();
+
+        let readback_buf = vma_alloc
+            .create_buffer(&create_info, &alloc_info)
+            .context("Failed to create image readback buffer")?;
+
+        // Transition to TRANSFER_SRC_OPTIMAL, copy, then transition back
+        let result = submit_one_time(device, cmd_pool, queue, |cmd_buf| unsafe {
+            let barrier = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .old_layout(layout)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image())
+                .subresource_range(COLOR_SUBRESOURCE_RANGE)
+                .build();
+
+            device.cmd_pipeline_barrier(
+                cmd_buf,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+
+            let region = vk::BufferImageCopy::builder()
+                .buffer_offset(0)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1
+                })
+                .image_offset(vk::Offset3D::default())
+                .image_extent(vk::Extent3D {
+                    width: self.extent.width,
+                    height: self.extent.height,
+                    depth: 1
+                })
+                .build();
+
+            device.cmd_copy_image_to_buffer(
+                cmd_buf,
+                self.image(),
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback_buf.buf(),
+                &[region]
+            );
+
+            let barriers = [
+                vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .dst_access_mask(vk::AccessFlags::MEMORY_READ)
+                    .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .new_layout(layout)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(self.image())
+                    .subresource_range(COLOR_SUBRESOURCE_RANGE)
+                    .build()
+            ];
+
+            let buf_barriers = [
+                vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::HOST_READ)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(readback_buf.buf())
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .build()
+            ];
+
+            device.cmd_pipeline_barrier(
+                cmd_buf,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buf_barriers,
+                &barriers
+            );
+        });
+
+        let result = result.and_then(|_| match readback_buf.ptr() {
+            Some(readback_ptr) => unsafe {
+                Ok(slice::from_raw_parts(readback_ptr.as_ptr() as *const u8, len).to_vec())
+            },
+            None => bail!("Image readback buffer somehow not mapped")
+        });
+
+        vma_alloc.destroy_buffer(readback_buf);
+
+        result
+    }
+
+    /// Clears every layer of the image to a color and leaves it in the given layout
+    ///
+    /// This blocks till the clear is finished. The image must have been created with
+    /// [`vk::ImageUsageFlags::TRANSFER_DST`]
+    pub fn clear(
+        &self,
+        device: &Device,
+        cmd_pool: vk::CommandPool,
+        queue: vk::Queue,
+        color: [f32; 4],
+        final_layout: vk::ImageLayout
+    ) -> Result<()> {
+        submit_one_time(device, cmd_pool, queue, |cmd_buf| unsafe {
+            let barrier = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image())
+                .subresource_range(self.subresource_range())
+                .build();
+
+            device.cmd_pipeline_barrier(
+                cmd_buf,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+
+            device.cmd_clear_color_image(
+                cmd_buf,
+                self.image(),
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &vk::ClearColorValue { float32: color },
+                &[self.subresource_range()]
+            );
+
+            let barrier = vk::ImageMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(final_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image())
+                .subresource_range(self.subresource_range())
+                .build();
+
+            device.cmd_pipeline_barrier(
+                cmd_buf,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+        })
+    }
+
+    pub fn destroy(self, device: &Device, vma_alloc: &VmaAllocator) {
+        unsafe { device.destroy_image_view(self.view, None) };
+        vma_alloc.destroy_image(self.image);
+    }
+}
+
+/// Subresource range covering the single mip level and layer of an [`Image2D`]
+pub const COLOR_SUBRESOURCE_RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+    aspect_mask: vk::ImageAspectFlags::COLOR,
+    base_mip_level: 0,
+    level_count: 1,
+    base_array_layer: 0,
+    layer_count: 1
+};
+
+fn create_image_view(
+    device: &Device,
+    image: vk::Image,
+    view_type: vk::ImageViewType,
+    format: vk::Format,
+    layers: u32
+) -> Result<vk::ImageView> {
+    let create_info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(view_type)
+        .format(format)
+        .components(vk::ComponentMapping {
+            r: vk::ComponentSwizzle::IDENTITY,
+            g: vk::ComponentSwizzle::IDENTITY,
+            b: vk::ComponentSwizzle::IDENTITY,
+            a: vk::ComponentSwizzle::IDENTITY,
+        })
+        .subresource_range(subresource_range(layers));
+
+    unsafe { device.create_image_view(&create_info, None) }.context("Failed to create image view")
+}
+
+/// Subresource range covering the single mip level and the first `layers` layers of an image
+fn subresource_range(layers: u32) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        layer_count: layers,
+        ..COLOR_SUBRESOURCE_RANGE
+    }
+}
+
+/// Size of a pixel in bytes for the formats we upload
+fn format_size(format: vk::Format) -> Result<usize> {
+    match format {
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Ok(4),
+        vk::Format::R8_UNORM => Ok(1),
+        _ => bail!("Unsupported upload format {format:?}")
+    }
+}
\ No newline at end of file
@@ -7,4 +7,5 @@ pub mod device;
 pub mod frame_queue;
 pub mod cmd_buf;
 pub mod vma;
-pub mod buffer;
\ No newline at end of file
+pub mod buffer;
+pub mod image;
\ No newline at end of file
@@ -15,7 +15,9 @@ pub const DEVICE_EXTS: [*const ffi::c_char; 2] = [
 /// Information associated with a physical device
 pub struct PhysicalDeviceInfo {
     gfx_queue_family: u32,
-    name: String
+    name: String,
+    incremental_present: bool,
+    max_image_dimension_2d: u32
 }
 
 impl PhysicalDeviceInfo {
@@ -28,6 +30,16 @@ impl PhysicalDeviceInfo {
     pub fn device_name(&self) -> &str {
         &self.name
     }
+
+    /// Whether the optional `VK_KHR_incremental_present` extension is supported
+    pub fn supports_incremental_present(&self) -> bool {
+        self.incremental_present
+    }
+
+    /// Largest width or height of a 2D image the device supports
+    pub fn max_image_dimension_2d(&self) -> u32 {
+        self.max_image_dimension_2d
+    }
 }
 
 /// Pick a supported physical device and retrieve its info
@@ -141,9 +153,19 @@ pub fn pick_physical_device(
                     .into_owned()
             };
             
+            let incremental_present = unsafe {
+                instance
+                    .enumerate_device_extension_properties(chosen_dev.phys_dev)
+                    .context("Failed to get available device extensions")?
+                    .iter()
+                    .any(|ext| CStr::from_ptr(ext.extension_name.as_ptr()) == vk::KhrIncrementalPresentFn::name())
+            };
+            
             let phys_dev_info = PhysicalDeviceInfo {
                 gfx_queue_family: chosen_dev.gfx_queue_family,
-                name
+                name,
+                incremental_present,
+                max_image_dimension_2d: chosen_dev.props.limits.max_image_dimension2_d
             };
 
             Ok((chosen_dev.phys_dev, phys_dev_info))
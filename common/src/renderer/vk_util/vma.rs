@@ -86,6 +86,12 @@ impl AllocInfo {
         self
     }
 
+    /// Requires `HOST_COHERENT` memory, so the host sees device writes without invalidating
+    pub fn coherent(mut self) -> Self {
+        self.0.requiredFlags |= vk::MemoryPropertyFlags::HOST_COHERENT;
+        self
+    }
+
     /// Sets `VMA_ALLOCATION_CREATE_HOST_ACCESS_ALLOW_TRANSFER_INSTEAD_BIT` flag
     pub fn allow_transfer_instead(mut self) -> Self {
         self.0.flags |= ffi::VmaAllocationCreateFlagBits::VMA_ALLOCATION_CREATE_HOST_ACCESS_ALLOW_TRANSFER_INSTEAD_BIT as u32;
@@ -112,6 +118,19 @@ impl VmaBuffer {
     }
 }
 
+/// A vulkan image with memory allocated and bound to it
+pub struct VmaImage {
+    image: vk::Image,
+    alloc: ffi::VmaAllocation
+}
+
+impl VmaImage {
+    /// The underlying [`vk::Image`]
+    pub fn image(&self) -> vk::Image {
+        self.image
+    }
+}
+
 /// Vulkan Memory Allocator
 pub struct VmaAllocator(ffi::VmaAllocator);
 
@@ -213,6 +232,35 @@ impl VmaAllocator {
         unsafe { ffi::vmaDestroyBuffer(self.0, buf.buf, buf.alloc) };
     }
 
+    /// Creates an image with memory bound and allocated to it
+    pub fn create_image(&self, create_info: &vk::ImageCreateInfo, alloc_info: &AllocInfo) -> Result<VmaImage> {
+        unsafe {
+            let mut image = MaybeUninit::uninit();
+            let mut allocation = MaybeUninit::uninit();
+
+            ffi::vmaCreateImage(
+                self.0,
+                create_info,
+                &alloc_info.0,
+                image.as_mut_ptr(),
+                allocation.as_mut_ptr(),
+                ptr::null_mut()
+            )
+            .result()
+            .context("vmaCreateImage failed")?;
+
+            Ok(VmaImage {
+                image: image.assume_init(),
+                alloc: allocation.assume_init()
+            })
+        }
+    }
+
+    /// Destroys a [`VmaImage`] and frees its memory
+    pub fn destroy_image(&self, image: VmaImage) {
+        unsafe { ffi::vmaDestroyImage(self.0, image.image, image.alloc) };
+    }
+
     pub fn destroy(self) {
         unsafe { ffi::vmaDestroyAllocator(self.0) };
     }
@@ -5,6 +5,7 @@ mod x11;
 
 use ash::vk;
 use anyhow::Result;
+use vek::Vec2;
 
 /// Represents a position in pixels
 pub struct Position {
@@ -12,6 +13,13 @@ pub struct Position {
     pub y: u32
 }
 
+impl Position {
+    /// Center of the pixel at the position, e.g. for hit testing canvas shapes
+    pub fn pixel_center(&self) -> Vec2<f32> {
+        Vec2::new(self.x as f32 + 0.5, self.y as f32 + 0.5)
+    }
+}
+
 /// Represents a size in pixels
 pub struct Size {
     pub width: u32,
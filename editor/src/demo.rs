@@ -0,0 +1,174 @@
+//! A scene showing off what the canvas can draw
+
+use std::f32::consts::{FRAC_PI_6, FRAC_PI_8};
+
+use common::{
+    renderer::{
+        CanvasScene, CanvasPicture, SvgImage, FillRule, BlendMode, Paint, ColorStop, ExtendMode, StrokeStyle, LineJoin, LineCap
+    },
+    vek::{Vec2, Rgba},
+    anyhow::{Result, Context}
+};
+
+/// Records the demo scene, drawn in the window till the editor has anything else to show
+pub fn demo_scene() -> Result<CanvasScene> {
+    let icon = SvgImage::parse(concat!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="48" height="48" viewBox="0 0 24 24">"##,
+        r##"<path fill="#ffc83d" d="M12 2l2.9 6.3 6.9.7-5.2 4.6 1.5 6.8L12 16.9 5.9 20.4l1.5-6.8L2.2 9l6.9-.7z"/>"##,
+        r##"<circle cx="12" cy="12" r="10.5" fill="none" stroke="#fff" stroke-width="1.5" opacity="0.6"/>"##,
+        r##"</svg>"##
+    )).context("Failed to parse demo icon")?;
+    
+    // The icon is drawn twice, so it's recorded once and drawn as a picture
+    let icon = CanvasPicture::record(|canvas_2d| canvas_2d.draw_svg(&icon))?;
+    
+    let stops = [
+        ColorStop::new(0.0, Rgba::new(255, 0, 100, 255)),
+        ColorStop::new(0.5, Rgba::new(255, 255, 0, 255)),
+        ColorStop::new(1.0, Rgba::new(0, 150, 255, 255))
+    ];
+    
+    let gradient = Paint::LinearGradient {
+        start: Vec2::new(550.0, 0.0),
+        end: Vec2::new(850.0, 0.0),
+        stops: &stops,
+        extend: ExtendMode::Pad
+    };
+    
+    let outline_style = StrokeStyle {
+        join: LineJoin::Round,
+        cap: LineCap::Round,
+        ..StrokeStyle::new(6.0)
+    };
+    
+    let marquee_style = StrokeStyle {
+        dashes: &[6.0, 4.0],
+        ..StrokeStyle::new(1.5)
+    };
+    
+    let mut scene = CanvasScene::new();
+    
+    scene.record(|canvas_2d| {
+        canvas_2d
+            .start_fill(Vec2::new(100.0, 100.0), Rgba::new(255, 100, 0, 255), FillRule::NonZero)
+            .line_to(Vec2::new(300.0, 100.0))
+            .line_to(Vec2::new(300.0, 300.0))
+            .line_to(Vec2::new(100.0, 300.0))
+            .line_to(Vec2::new(100.0, 100.0))
+            .end()
+            .draw_box_shadow(
+                Vec2::new(120.0, 120.0),
+                Vec2::new(60.0, 40.0),
+                6.0,
+                12.0,
+                Vec2::new(0.0, 4.0),
+                Rgba::new(0, 0, 0, 180)
+            )
+            .start_fill(Vec2::new(0.0, 0.0), Rgba::new(245, 245, 245, 255), FillRule::NonZero)
+            .rounded_rect(Vec2::new(120.0, 120.0), Vec2::new(60.0, 40.0), 6.0)
+            .end()
+            .start_fill(Vec2::new(200.0, 200.0), Rgba::new(255, 255, 255, 100), FillRule::NonZero)
+            .line_to(Vec2::new(500.0, 200.0))
+            .line_to(Vec2::new(500.0, 500.0))
+            .line_to(Vec2::new(200.0, 500.0))
+            .line_to(Vec2::new(200.0, 200.0))
+            .end()
+            .start_stroke(Vec2::new(400.0, 250.0), Rgba::new(100, 255, 255, 255), outline_style)
+            .line_to(Vec2::new(530.0, 250.0))
+            .line_to(Vec2::new(590.0, 350.0))
+            .line_to(Vec2::new(460.0, 350.0))
+            .line_to(Vec2::new(400.0, 250.0))
+            .end()
+            .start_fill(Vec2::new(600.0, 450.0), Rgba::new(255, 200, 0, 255), FillRule::NonZero)
+            .quad_to(Vec2::new(700.0, 350.0), Vec2::new(800.0, 450.0))
+            .cubic_to(Vec2::new(750.0, 550.0), Vec2::new(650.0, 500.0), Vec2::new(600.0, 450.0))
+            .end()
+            .start_fill(Vec2::new(50.0, 400.0), Rgba::new(80, 80, 200, 255), FillRule::NonZero)
+            .rounded_rect(Vec2::new(50.0, 400.0), Vec2::new(120.0, 40.0), 10.0)
+            .circle(Vec2::new(250.0, 520.0), 30.0)
+            .ellipse(Vec2::new(350.0, 540.0), Vec2::new(50.0, 20.0))
+            .end()
+            .start_stroke(Vec2::new(650.0, 100.0), Rgba::new(255, 255, 255, 255), 4.0)
+            .arc_to(Vec2::new(800.0, 100.0), Vec2::new(800.0, 250.0), 40.0)
+            .line_to(Vec2::new(800.0, 250.0))
+            .end()
+            .start_fill(Vec2::new(650.0, 280.0), Rgba::new(0, 200, 120, 255), FillRule::EvenOdd)
+            .line_to(Vec2::new(750.0, 280.0))
+            .line_to(Vec2::new(750.0, 380.0))
+            .line_to(Vec2::new(650.0, 380.0))
+            .move_to(Vec2::new(675.0, 305.0))
+            .line_to(Vec2::new(725.0, 305.0))
+            .line_to(Vec2::new(725.0, 355.0))
+            .line_to(Vec2::new(675.0, 355.0))
+            .circle(Vec2::new(840.0, 330.0), 30.0)
+            .circle(Vec2::new(840.0, 330.0), 18.0)
+            .end()
+            .start_fill(Vec2::new(0.0, 0.0), gradient, FillRule::NonZero)
+            .rounded_rect(Vec2::new(550.0, 20.0), Vec2::new(300.0, 40.0), 8.0)
+            .end()
+            .push_clip_path(Vec2::new(0.0, 0.0), FillRule::NonZero)
+            .circle(Vec2::new(500.0, 620.0), 60.0)
+            .end()
+            .push_clip_rect(Vec2::new(440.0, 560.0), Vec2::new(120.0, 60.0))
+            .start_fill(Vec2::new(400.0, 540.0), Rgba::new(200, 60, 160, 255), FillRule::NonZero)
+            .line_to(Vec2::new(600.0, 540.0))
+            .line_to(Vec2::new(600.0, 700.0))
+            .line_to(Vec2::new(400.0, 700.0))
+            .end()
+            .pop_clip()
+            .pop_clip()
+            .save()
+            .translate(Vec2::new(750.0, 600.0))
+            .rotate(FRAC_PI_6)
+            .scale(Vec2::new(1.5, 1.5))
+            .start_stroke(Vec2::new(0.0, 0.0), Rgba::new(255, 230, 120, 255), 4.0)
+            .rounded_rect(Vec2::new(0.0, 0.0), Vec2::new(80.0, 50.0), 6.0)
+            .end()
+            .restore()
+            .start_stroke(Vec2::new(0.0, 0.0), Rgba::new(255, 255, 255, 255), marquee_style)
+            .rounded_rect(Vec2::new(90.0, 90.0), Vec2::new(220.0, 220.0), 0.0)
+            .end()
+            .start_stroke(Vec2::new(880.0, 420.0), Rgba::new(255, 120, 120, 255), 8.0)
+            .line_to(Vec2::new(910.0, 480.0))
+            .line_to(Vec2::new(940.0, 420.0))
+            .line_to(Vec2::new(970.0, 480.0))
+            .end()
+            .start_fill(Vec2::new(0.0, 0.0), Rgba::new(120, 200, 255, 160), FillRule::NonZero)
+            .circle(Vec2::new(-20.0, 250.5), 60.25)
+            .end()
+            .set_blend_mode(BlendMode::Multiply)
+            .start_fill(Vec2::new(0.0, 0.0), Rgba::new(255, 220, 80, 255), FillRule::NonZero)
+            .circle(Vec2::new(280.0, 120.0), 50.0)
+            .end()
+            .set_blend_mode(BlendMode::DstOut)
+            .start_fill(Vec2::new(0.0, 0.0), Rgba::new(0, 0, 0, 255), FillRule::NonZero)
+            .circle(Vec2::new(120.0, 280.0), 25.0)
+            .end()
+            .set_blend_mode(BlendMode::SrcOver)
+            .push_layer(0.5, BlendMode::SrcOver)
+            .start_fill(Vec2::new(0.0, 0.0), Rgba::new(60, 200, 90, 255), FillRule::NonZero)
+            .rounded_rect(Vec2::new(880.0, 520.0), Vec2::new(100.0, 60.0), 12.0)
+            .end()
+            .start_fill(Vec2::new(0.0, 0.0), Rgba::new(240, 240, 240, 255), FillRule::NonZero)
+            .circle(Vec2::new(930.0, 550.0), 20.0)
+            .end()
+            .pop_layer()
+            .push_blur_layer(1.0, BlendMode::SrcOver, 16.0)
+            .start_fill(Vec2::new(0.0, 0.0), Rgba::new(80, 220, 255, 255), FillRule::NonZero)
+            .circle(Vec2::new(960.0, 200.0), 25.0)
+            .end()
+            .pop_layer()
+            .start_fill(Vec2::new(0.0, 0.0), Rgba::new(255, 255, 255, 255), FillRule::NonZero)
+            .circle(Vec2::new(960.0, 200.0), 20.0)
+            .end()
+            .save()
+            .translate(Vec2::new(1000.0, 40.0))
+            .draw_picture(&icon)
+            .translate(Vec2::new(60.0, 0.0))
+            .rotate(FRAC_PI_8)
+            .draw_picture(&icon)
+            .restore()
+    })?;
+    
+    Ok(scene)
+}
\ No newline at end of file
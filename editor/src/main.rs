@@ -1,4 +1,5 @@
 mod cli_args;
+mod demo;
 
 use std::thread;
 use std::sync::Arc;
@@ -24,6 +25,8 @@ fn main() -> Result<()> {
     };
 
     let mut renderer = Renderer::new(&renderer_config, window.as_ref())?;
+    renderer.set_canvas_scene(Some(demo::demo_scene()?));
+    
     let result = Arc::new(RwLock::new(None));
     
     // Start render loop